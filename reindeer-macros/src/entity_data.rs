@@ -1,11 +1,13 @@
 use std::str::FromStr;
 
-use syn::{Attribute, Meta,Ident, Fields};
+use syn::{Attribute, Meta,Ident, Fields, Token, punctuated::Punctuated};
 use crate::Errors;
 use proc_macro2::{Span, TokenStream};
-use crate::relations::Relations;
+use crate::relations::{FreeRelations, Relations};
 
-const ID_PARSE_ERROR : &'static str = "Could not parse id parameter. id must be a string containing either a field name.";
+const ID_PARSE_ERROR : &str = "Could not parse id parameter. id must be a string containing either a field name.";
+const COMPRESS_PARSE_ERROR : &str = "Unknown compression algorithm. compress must be either \"zstd\" or \"lz4\".";
+const CODEC_PARSE_ERROR : &str = "Unknown codec. codec must be one of \"bincode\", \"json\", \"postcard\", \"cbor\" or \"messagepack\".";
 
 
 
@@ -14,17 +16,22 @@ pub struct EntityData {
     pub crate_name : String,
     pub name : Option<String>,
     pub version : Option<u32>,
+    pub codec : Option<Ident>,
+    pub compress : Option<Ident>,
+    pub compress_threshold : Option<usize>,
     pub id : Option<Ident>,
     pub id_type : Option<syn::Type>,
     pub children : Relations,
     pub siblings : Relations,
+    pub free_relations : FreeRelations,
+    pub migrations : Vec<syn::Type>,
+    pub indexes : Vec<(syn::Ident,syn::Type,bool)>,
     pub fields : Vec<(syn::Visibility,syn::Ident,syn::Type)>,
 }
 
 impl EntityData {
     pub fn parse(span : &Span, attrs : &[Attribute], fields : &Fields, errors : &mut Errors) -> EntityData {
-        let mut entity_data = EntityData::default();
-        entity_data.crate_name = "reindeer".to_string();
+        let mut entity_data = EntityData { crate_name : "reindeer".to_string(), ..Default::default() };
         entity_data.parse_fields( fields, errors);
         for attr in attrs {
             if attr.path.is_ident("entity") {
@@ -38,15 +45,25 @@ impl EntityData {
             else if attr.path.is_ident("children") || attr.path.is_ident("siblings") {
                 entity_data.parse_related_stores(attr,errors);
             }
+            else if attr.path.is_ident("relations") {
+                match attr.parse_args::<FreeRelations>() {
+                    Ok(relations) => entity_data.free_relations = relations,
+                    Err(e) => errors.push(e),
+                }
+            }
+            else if attr.path.is_ident("migrations") {
+                entity_data.parse_migrations(attr,errors);
+            }
         }
         entity_data.check(span,errors);
+        entity_data.check_migrations(span,errors);
         entity_data
     }
 
     fn parse_entity_args(&mut self, meta : &Meta, errors : &mut Errors) {
         match meta {
             Meta::Path(p) => {
-                errors.push(syn::Error::new_spanned(p, "Unrecognized argument. Accepted arguments are 'name', 'version' and 'id'"));
+                errors.push(syn::Error::new_spanned(p, "Unrecognized argument. Accepted arguments are 'name', 'version', 'id', 'codec', 'compress' and 'compress_threshold'"));
             },
             Meta::List(l) => {
                 for token in &l.nested {
@@ -55,7 +72,7 @@ impl EntityData {
                             self.parse_entity_args(m, errors);
                         },
                         syn::NestedMeta::Lit(l) => {
-                            errors.push(syn::Error::new_spanned(l, "Unrecognized argument. Accepted arguments are 'name', 'version' and 'id'"));
+                            errors.push(syn::Error::new_spanned(l, "Unrecognized argument. Accepted arguments are 'name', 'version', 'id', 'codec', 'compress' and 'compress_threshold'"));
                         },
                     }
                 }
@@ -79,7 +96,7 @@ impl EntityData {
                                     self.version = Some(int);
                                 },
                                 Err(_) => {
-                                    errors.push(syn::Error::new_spanned(&int, "Store version must be a positive integer."))
+                                    errors.push(syn::Error::new_spanned(int, "Store version must be a positive integer."))
                                 },
                             }
                         },
@@ -98,6 +115,70 @@ impl EntityData {
                         }
                     }
                 }
+                else if nv.path.is_ident("codec") {
+                    match &nv.lit {
+                        syn::Lit::Str(str) => {
+                            let codec = match str.value().as_str() {
+                                "bincode" => Some("Bincode"),
+                                "json" => Some("Json"),
+                                "postcard" => Some("Postcard"),
+                                "cbor" => Some("Cbor"),
+                                "messagepack" => Some("MessagePack"),
+                                _ => None,
+                            };
+                            match codec {
+                                Some(codec) => {
+                                    self.codec = Some(Ident::new(codec, str.span()));
+                                },
+                                None => {
+                                    errors.push(syn::Error::new_spanned(str, CODEC_PARSE_ERROR))
+                                }
+                            }
+                        },
+                        _ => {
+                            errors.push(syn::Error::new_spanned(&nv.lit, CODEC_PARSE_ERROR))
+                        }
+                    }
+                }
+                else if nv.path.is_ident("compress") {
+                    match &nv.lit {
+                        syn::Lit::Str(str) => {
+                            let compress = match str.value().as_str() {
+                                "zstd" => Some("Zstd"),
+                                "lz4" => Some("Lz4"),
+                                _ => None,
+                            };
+                            match compress {
+                                Some(compress) => {
+                                    self.compress = Some(Ident::new(compress, str.span()));
+                                },
+                                None => {
+                                    errors.push(syn::Error::new_spanned(str, COMPRESS_PARSE_ERROR))
+                                }
+                            }
+                        },
+                        _ => {
+                            errors.push(syn::Error::new_spanned(&nv.lit, COMPRESS_PARSE_ERROR))
+                        }
+                    }
+                }
+                else if nv.path.is_ident("compress_threshold") {
+                    match &nv.lit {
+                        syn::Lit::Int(int) => {
+                            match int.base10_parse::<usize>() {
+                                Ok(int) => {
+                                    self.compress_threshold = Some(int);
+                                },
+                                Err(_) => {
+                                    errors.push(syn::Error::new_spanned(int, "Compression threshold must be a positive integer."))
+                                },
+                            }
+                        },
+                        _ => {
+                            errors.push(syn::Error::new_spanned(&nv.lit, "Compression threshold must be a positive integer."))
+                        }
+                    }
+                }
                 else if nv.path.is_ident("crate") {
                     match &nv.lit {
                         syn::Lit::Str(str) => {
@@ -109,7 +190,7 @@ impl EntityData {
                     }
                 }
                 else {
-                    errors.push(syn::Error::new_spanned(&nv.path, "Unrecognized argument. Accepted arguments are 'name', 'version' and 'id'"))
+                    errors.push(syn::Error::new_spanned(&nv.path, "Unrecognized argument. Accepted arguments are 'name', 'version', 'id', 'codec', 'compress' and 'compress_threshold'"))
                 }
             },
         }
@@ -141,17 +222,37 @@ impl EntityData {
             Fields::Named(fields) => {
                 for field in fields.named.iter() {
                     let field = field.clone();
-                    self.fields.push((field.vis,field.ident.unwrap(),field.ty));
+                    let ident = field.ident.unwrap();
+                    for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("index")) {
+                        match Self::parse_index_attr(attr) {
+                            Ok(unique) => self.indexes.push((ident.clone(),field.ty.clone(),unique)),
+                            Err(e) => errors.push(e),
+                        }
+                    }
+                    self.fields.push((field.vis,ident,field.ty));
                 }
             },
             _ => errors.push(syn::Error::new_spanned(fields, "Reindeer only supports deriving Entity on named structs. Please implement Entity manually.")),
         }
     }
 
+    fn parse_index_attr(attr : &Attribute) -> syn::Result<bool> {
+        match attr.parse_meta()? {
+            Meta::Path(_) => Ok(false),
+            Meta::List(l) => {
+                match l.nested.iter().collect::<Vec<&syn::NestedMeta>>().as_slice() {
+                    [syn::NestedMeta::Meta(Meta::Path(p))] if p.is_ident("unique") => Ok(true),
+                    _ => Err(syn::Error::new_spanned(l, "Unrecognized argument. Use either `#[index]` or `#[index(unique)]`")),
+                }
+            },
+            Meta::NameValue(nv) => Err(syn::Error::new_spanned(nv, "Unrecognized argument. Use either `#[index]` or `#[index(unique)]`")),
+        }
+    }
+
     fn check(&mut self, span : &Span, errors : &mut Errors){
         match &self.id {
             None => {
-                let id_field = self.fields.iter().find(|e| e.1.to_string() == "id");
+                let id_field = self.fields.iter().find(|e| e.1 == "id");
                 if let Some(id_field) = id_field {
                     self.id = Some(id_field.1.clone());
                     self.id_type = Some(id_field.2.clone());
@@ -168,7 +269,7 @@ impl EntityData {
 
     }
     fn check_id(&mut self, ident : &Ident, errors : &mut Errors) {
-        match self.fields.iter().find(|e| e.1.to_string() == ident.to_string()) {
+        match self.fields.iter().find(|e| e.1 == *ident) {
             Some(id) => {
                 self.id_type = Some(id.2.clone());
             }
@@ -178,6 +279,23 @@ impl EntityData {
             
         }
     }
+    fn check_migrations(&self, span : &Span, errors : &mut Errors) {
+        if !self.migrations.is_empty() && self.migrations.len() as u32 != self.version.unwrap_or(0) {
+            errors.push(syn::Error::new(span.to_owned(), "The `migrations` attribute must list one type for each previous version of the entity, starting at version 0."));
+        }
+    }
+
+    fn parse_migrations(&mut self, attr : &Attribute, errors : &mut Errors){
+        match attr.parse_args_with(Punctuated::<syn::Type,Token![,]>::parse_terminated) {
+            Ok(types) => {
+                self.migrations = types.into_iter().collect();
+            },
+            Err(e) => {
+                errors.push(e);
+            }
+        }
+    }
+
     fn parse_related_stores(&mut self, attr : &Attribute, errors : &mut Errors){
         match attr.parse_args::<Relations>() {
             Ok(rel) => {
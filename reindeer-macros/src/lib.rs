@@ -3,6 +3,7 @@
 //! To automatically derive Entity on a `struct`, you simply have to derive `Entity` (as Well as `serde`'s `Serialize` and `Deserialize` traits) like so:
 //! 
 //! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize};
 //! #[derive(Serialize,Deserialize,Entity)]
 //! struct User {
 //!     id : (u32,u32),
@@ -11,12 +12,14 @@
 //!     last_login : i64,
 //!     password_hash : String,
 //! }
+//! # fn main() {}
 //! ```
 //! 
 //! ☝😉 This will generate an `Entity` implementation with store name `User`, version 0, and id being the `id` field.
 //! To specify other values, use the helper attribute `entity` like so :
 //! 
 //! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize};
 //! #[derive(Serialize,Deserialize,Entity)]
 //! #[entity(name = "user", version = 1,id = "email")]
 //! struct User {
@@ -25,16 +28,18 @@
 //!     last_login : i64,
 //!     password_hash : String,
 //! }
+//! # fn main() {}
 //! ```
 //! 
-//! To specify sibling entities and child entities, use the `sibling` and `child` helper attributes
-//! respectively:
+//! To specify sibling entities and child entities, use the `siblings` and `children` helper attributes
+//! respectively, with the type of each related entity:
 //! 
 //! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize};
 //! #[derive(Serialize,Deserialize,Entity)]
 //! #[entity(name = "user", version = 1,id = "email")]
-//! #[sibling(("user_data", Cascade))]
-//! #[children(("doc",Cascade),("shared_doc",BreakLink))]
+//! #[siblings(UserData = Cascade)]
+//! #[children(Doc = Cascade, SharedDoc = BreakLink)]
 //! struct User {
 //!     email : String,
 //!     username : String,
@@ -42,24 +47,157 @@
 //!     password_hash : String,
 //! }
 //! 
-//! //! #[derive(Serialize,Deserialize,Entity)]
+//! #[derive(Serialize,Deserialize,Entity)]
 //! #[entity(name = "user_data", version = 1,id = "email")]
-//! #[sibling(("user", Error))]
+//! #[siblings(User = Error)]
 //! struct UserData {
 //!     email : String,
 //!     username : String,
 //!     last_login : i64,
 //!     password_hash : String,
 //! }
+//! # #[derive(Serialize,Deserialize,Entity)]
+//! # #[entity(name = "doc")]
+//! # struct Doc { id : (String, u32) }
+//! # #[derive(Serialize,Deserialize,Entity)]
+//! # #[entity(name = "shared_doc")]
+//! # struct SharedDoc { id : (String, u32) }
+//! # fn main() {}
 //! ```
 //! 
 //! The second part of each relation is a `reindeer::DeletionBehaviour` enum value : `BreakLink`,`Cascade`, or `Error`.
+//! Sibling entities must share the same key type, and the key of a child entity must be a tuple `(ParentKey, T)` :
+//! both are checked at compile time. Store names can also be given as strings, like `("doc", Cascade)`, without any check.
+//! 
+//! To declare the free relations an entity accepts, use the `relations` helper attribute, giving the related entity type,
+//! the relation name, and the deletion behaviours in both ways. This generates `add_<name>_<store>`, `<name>_<store>s`
+//! and `remove_<name>_<store>` methods taking and returning the related entity type. Creating relations that are not declared,
+//! or declared with other deletion behaviours, fails with `ErrorKind::UndeclaredRelation` :
+//! 
+//! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize};
+//! #[derive(Serialize,Deserialize,Entity)]
+//! #[entity(name = "student")]
+//! #[relations((Club, "member", BreakLink, BreakLink))]
+//! struct Student {
+//!     id : u32,
+//! }
+//! 
+//! # #[derive(Serialize,Deserialize,Entity)]
+//! # #[entity(name = "club")]
+//! # struct Club { id : u32 }
+//! # fn main() -> reindeer::Result<()> {
+//! # let db = sled::Config::new().temporary(true).open()?;
+//! # Student::register(&db)?;
+//! # Club::register(&db)?;
+//! # let student = Student { id : 1 };
+//! # let club = Club { id : 1 };
+//! # club.save(&db)?;
+//! # student.save(&db)?;
+//! student.add_member_club(&club, &db)?;
+//! let clubs : Vec<Club> = student.member_clubs(&db)?;
+//! # Ok(())
+//! # }
+//! ```
+//! 
+//! The related store can also be given as a string, like `("club", "member", BreakLink, BreakLink)` : the generated methods
+//! are then generic over the related entity type, and check that it matches the store at runtime.
+//! 
+//! To look entities up by another field than their key without iterating over the whole store, mark the field
+//! with the `index` helper attribute, or `index(unique)` to also prevent two entities from sharing the same value.
+//! This generates `get_by_<field>` and `find_by_<field>` methods. Entities whose `Option` field is `None`
+//! are not indexed for it, so that several of them can share a unique index :
+//! 
+//! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize};
+//! #[derive(Serialize,Deserialize,Entity)]
+//! #[entity(name = "user")]
+//! struct User {
+//!     id : u32,
+//!     #[index(unique)]
+//!     email : String,
+//!     #[index]
+//!     city : String,
+//! }
+//! 
+//! # fn main() -> reindeer::Result<()> {
+//! # let db = sled::Config::new().temporary(true).open()?;
+//! # User::register(&db)?;
+//! let user = User::get_by_email(&String::from("ada@example.com"), &db)?;
+//! let users = User::find_by_city(&String::from("Paris"), &db)?;
+//! # Ok(())
+//! # }
+//! ```
+//! 
+//! When the structure of an entity changes, bump its `version` and list the previous versions of the struct
+//! (from version 0 onwards) with the `migrations` helper attribute. Each version must implement `From` for the next one.
+//! Registering the entity will then migrate every record saved with an older version :
+//! 
+//! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize};
+//! #[derive(Serialize,Deserialize,Entity)]
+//! #[entity(name = "user", version = 2,id = "email")]
+//! #[migrations(UserV0, UserV1)]
+//! struct User {
+//!     email : String,
+//!     username : String,
+//!     last_login : i64,
+//!     password_hash : String,
+//! }
+//! 
+//! impl From<UserV1> for User {
+//!     fn from(user : UserV1) -> Self {
+//!         User { email : user.email, username : user.username, last_login : user.last_login, password_hash : user.password_hash }
+//!     }
+//! }
+//! # #[derive(Serialize,Deserialize,Entity)]
+//! # #[entity(name = "user", id = "email")]
+//! # struct UserV0 { email : String, username : String, password_hash : String }
+//! # #[derive(Serialize,Deserialize,Entity)]
+//! # #[entity(name = "user", version = 1, id = "email")]
+//! # #[migrations(UserV0)]
+//! # struct UserV1 { email : String, username : String, last_login : i64, password_hash : String }
+//! # impl From<UserV0> for UserV1 {
+//! #     fn from(user : UserV0) -> Self {
+//! #         UserV1 { email : user.email, username : user.username, last_login : 0, password_hash : user.password_hash }
+//! #     }
+//! # }
+//! # fn main() {}
+//! ```
+//! 
+//! Values are saved with `bincode` by default. Another serialization format can be chosen with the `codec` argument,
+//! among `"bincode"`, `"json"`, `"postcard"`, `"cbor"` and `"messagepack"` (the last three need the matching feature of `reindeer`) :
+//! 
+//! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize};
+//! #[derive(Serialize,Deserialize,Entity)]
+//! #[entity(name = "settings", codec = "json")]
+//! struct Settings {
+//!     id : u32,
+//!     #[serde(default, skip_serializing_if = "Option::is_none")]
+//!     theme : Option<String>,
+//! }
+//! # fn main() {}
+//! ```
+//! 
+//! Large values can be compressed with the `compress` argument, either `"zstd"` or `"lz4"` (with the matching feature of `reindeer`).
+//! Only values of at least `compress_threshold` bytes (512 by default) are compressed :
+//! 
+//! ```rust,ignore
+//! #[derive(Serialize,Deserialize,Entity)]
+//! #[entity(name = "page", compress = "zstd", compress_threshold = 1024)]
+//! struct Page {
+//!     id : u32,
+//!     html : String,
+//! }
+//! ```
 //! 
 
 mod entity_data;
 mod relations;
 
 use entity_data::EntityData;
+use relations::{RelatedStore, Relations};
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use syn::{parse_macro_input, DeriveInput, Visibility, spanned::Spanned};
@@ -74,6 +212,7 @@ type Errors = Vec<syn::Error>;
 /// To automatically derive Entity on a `struct`, you simply have to derive `Entity` (as Well as `serde`'s `Serialize` and `Deserialize` traits) like so:
 /// 
 /// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
 /// #[derive(Serialize,Deserialize,Entity)]
 /// struct User {
 ///     id : (u32,u32),
@@ -82,12 +221,14 @@ type Errors = Vec<syn::Error>;
 ///     last_login : i64,
 ///     password_hash : String,
 /// }
+/// # fn main() {}
 /// ```
 /// 
 /// ☝😉 This will generate an `Entity` implementation with store name `User`, version 0, and id being the `id` field.
 /// To specify other values, use the helper attribute `entity` like so :
 /// 
 /// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
 /// #[derive(Serialize,Deserialize,Entity)]
 /// #[entity(name = "user", version = 1,id = "email")]
 /// struct User {
@@ -96,16 +237,18 @@ type Errors = Vec<syn::Error>;
 ///     last_login : i64,
 ///     password_hash : String,
 /// }
+/// # fn main() {}
 /// ```
 /// 
-/// To specify sibling entities and child entities, use the `sibling` and `child` helper attributes
-/// respectively:
+/// To specify sibling entities and child entities, use the `siblings` and `children` helper attributes
+/// respectively, with the type of each related entity:
 /// 
 /// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
 /// #[derive(Serialize,Deserialize,Entity)]
 /// #[entity(name = "user", version = 1,id = "email")]
-/// #[sibling(("user_data", Cascade))]
-/// #[children(("doc",Cascade),("shared_doc",BreakLink))]
+/// #[siblings(UserData = Cascade)]
+/// #[children(Doc = Cascade, SharedDoc = BreakLink)]
 /// struct User {
 ///     email : String,
 ///     username : String,
@@ -115,23 +258,155 @@ type Errors = Vec<syn::Error>;
 /// 
 /// #[derive(Serialize,Deserialize,Entity)]
 /// #[entity(name = "user_data", version = 1,id = "email")]
-/// #[sibling(("user", Error))]
+/// #[siblings(User = Error)]
 /// struct UserData {
 ///     email : String,
 ///     username : String,
 ///     last_login : i64,
 ///     password_hash : String,
 /// }
+/// # #[derive(Serialize,Deserialize,Entity)]
+/// # #[entity(name = "doc")]
+/// # struct Doc { id : (String, u32) }
+/// # #[derive(Serialize,Deserialize,Entity)]
+/// # #[entity(name = "shared_doc")]
+/// # struct SharedDoc { id : (String, u32) }
+/// # fn main() {}
 /// ```
 /// 
 /// The second part of each relation is a `reindeer::DeletionBehaviour` enum value : `BreakLink`,`Cascade`, or `Error`.
+/// Sibling entities must share the same key type, and the key of a child entity must be a tuple `(ParentKey, T)` :
+/// both are checked at compile time. Store names can also be given as strings, like `("doc", Cascade)`, without any check.
+/// 
+/// To declare the free relations an entity accepts, use the `relations` helper attribute, giving the related entity type,
+/// the relation name, and the deletion behaviours in both ways. This generates `add_<name>_<store>`, `<name>_<store>s`
+/// and `remove_<name>_<store>` methods taking and returning the related entity type. Creating relations that are not declared,
+/// or declared with other deletion behaviours, fails with `ErrorKind::UndeclaredRelation` :
+/// 
+/// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// #[derive(Serialize,Deserialize,Entity)]
+/// #[entity(name = "student")]
+/// #[relations((Club, "member", BreakLink, BreakLink))]
+/// struct Student {
+///     id : u32,
+/// }
+/// 
+/// # #[derive(Serialize,Deserialize,Entity)]
+/// # #[entity(name = "club")]
+/// # struct Club { id : u32 }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # Student::register(&db)?;
+/// # Club::register(&db)?;
+/// # let student = Student { id : 1 };
+/// # let club = Club { id : 1 };
+/// # club.save(&db)?;
+/// # student.save(&db)?;
+/// student.add_member_club(&club, &db)?;
+/// let clubs : Vec<Club> = student.member_clubs(&db)?;
+/// # Ok(())
+/// # }
+/// ```
+/// 
+/// The related store can also be given as a string, like `("club", "member", BreakLink, BreakLink)` : the generated methods
+/// are then generic over the related entity type, and check that it matches the store at runtime.
+/// 
+/// To look entities up by another field than their key without iterating over the whole store, mark the field
+/// with the `index` helper attribute, or `index(unique)` to also prevent two entities from sharing the same value.
+/// This generates `get_by_<field>` and `find_by_<field>` methods. Entities whose `Option` field is `None`
+/// are not indexed for it, so that several of them can share a unique index :
+/// 
+/// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// #[derive(Serialize,Deserialize,Entity)]
+/// #[entity(name = "user")]
+/// struct User {
+///     id : u32,
+///     #[index(unique)]
+///     email : String,
+///     #[index]
+///     city : String,
+/// }
+/// 
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # User::register(&db)?;
+/// let user = User::get_by_email(&String::from("ada@example.com"), &db)?;
+/// let users = User::find_by_city(&String::from("Paris"), &db)?;
+/// # Ok(())
+/// # }
+/// ```
+/// 
+/// When the structure of an entity changes, bump its `version` and list the previous versions of the struct
+/// (from version 0 onwards) with the `migrations` helper attribute. Each version must implement `From` for the next one.
+/// Registering the entity will then migrate every record saved with an older version :
+/// 
+/// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// #[derive(Serialize,Deserialize,Entity)]
+/// #[entity(name = "user", version = 2,id = "email")]
+/// #[migrations(UserV0, UserV1)]
+/// struct User {
+///     email : String,
+///     username : String,
+///     last_login : i64,
+///     password_hash : String,
+/// }
+/// 
+/// impl From<UserV1> for User {
+///     fn from(user : UserV1) -> Self {
+///         User { email : user.email, username : user.username, last_login : user.last_login, password_hash : user.password_hash }
+///     }
+/// }
+/// # #[derive(Serialize,Deserialize,Entity)]
+/// # #[entity(name = "user", id = "email")]
+/// # struct UserV0 { email : String, username : String, password_hash : String }
+/// # #[derive(Serialize,Deserialize,Entity)]
+/// # #[entity(name = "user", version = 1, id = "email")]
+/// # #[migrations(UserV0)]
+/// # struct UserV1 { email : String, username : String, last_login : i64, password_hash : String }
+/// # impl From<UserV0> for UserV1 {
+/// #     fn from(user : UserV0) -> Self {
+/// #         UserV1 { email : user.email, username : user.username, last_login : 0, password_hash : user.password_hash }
+/// #     }
+/// # }
+/// # fn main() {}
+/// ```
+/// 
+/// Values are saved with `bincode` by default. Another serialization format can be chosen with the `codec` argument,
+/// among `"bincode"`, `"json"`, `"postcard"`, `"cbor"` and `"messagepack"` (the last three need the matching feature of `reindeer`) :
+/// 
+/// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// #[derive(Serialize,Deserialize,Entity)]
+/// #[entity(name = "settings", codec = "json")]
+/// struct Settings {
+///     id : u32,
+///     #[serde(default, skip_serializing_if = "Option::is_none")]
+///     theme : Option<String>,
+/// }
+/// # fn main() {}
+/// ```
+/// 
+/// Large values can be compressed with the `compress` argument, either `"zstd"` or `"lz4"` (with the matching feature of `reindeer`).
+/// Only values of at least `compress_threshold` bytes (512 by default) are compressed :
 /// 
-#[proc_macro_derive(Entity, attributes(entity,children,siblings))]
+/// ```rust,ignore
+/// #[derive(Serialize,Deserialize,Entity)]
+/// #[entity(name = "page", compress = "zstd", compress_threshold = 1024)]
+/// struct Page {
+///     id : u32,
+///     html : String,
+/// }
+/// ```
+/// 
+#[proc_macro_derive(Entity, attributes(entity,children,siblings,relations,migrations,index))]
 pub fn derive_entity(item : TokenStream) -> TokenStream {
     let ast = parse_macro_input!(item as DeriveInput);
     let mut errors = Vec::new();
     let mut result = construct_token_stream(&ast, &mut errors);
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         result.extend::<TokenStream>(errors.iter().map(|e| Into::<TokenStream>::into(e.to_compile_error())).collect());
     }
     result
@@ -142,7 +417,8 @@ fn construct_token_stream(input : &DeriveInput, errors : &mut Errors) -> TokenSt
 
     match &input.data {
         syn::Data::Struct(s) => {
-            let entity_data = EntityData::parse(&input.span(),&input.attrs,&s.fields, errors);
+            let mut entity_data = EntityData::parse(&input.span(),&input.attrs,&s.fields, errors);
+            entity_data.name.get_or_insert_with(|| input.ident.to_string());
             let attr_copy = entity_data.clone();
             result.extend([
                 generate_alias(&input.ident, entity_data.version.unwrap_or(0), &input.vis, &input.generics),
@@ -157,7 +433,7 @@ fn construct_token_stream(input : &DeriveInput, errors : &mut Errors) -> TokenSt
 
 fn generate_alias(name : &Ident,version : u32, vis : &Visibility, generics : &syn::Generics) -> TokenStream {
     let (_, ty_generics, _) = generics.split_for_impl();
-    let versionned_ident = Ident::new(&format!("{}_v{}",name.to_string(),version), Span::call_site());
+    let versionned_ident = Ident::new(&format!("{}_v{}",name,version), Span::call_site());
     quote ! {
         #vis type #versionned_ident #ty_generics = #name #ty_generics;
     }.into()
@@ -168,14 +444,115 @@ fn generate_impl(struct_name : &Ident,entity_data : &EntityData, generics : &syn
     if let (Some(store_name),Some(id_field),Some(key_type),crate_name) = (&entity_data.name,&entity_data.id,&entity_data.id_type,&entity_data.crate_name) {
         let crate_name = Ident::new(crate_name,Span::call_site());
         let children : Vec<proc_macro2::TokenStream> = entity_data.children.0.iter().map(|e| {
-            let (name,deletion) = (e.0.clone(),e.1.clone());
+            let (name,deletion) = (related_store_name(&crate_name, &e.0),e.1.clone());
             quote!{(#name,#crate_name::DeletionBehaviour::#deletion)}
         }).collect();
         let siblings: Vec<proc_macro2::TokenStream> = entity_data.siblings.0.iter().map(|e| {
-            let (name,deletion) = (e.0.clone(),e.1.clone());
+            let (name,deletion) = (related_store_name(&crate_name, &e.0),e.1.clone());
             quote!{(#name,#crate_name::DeletionBehaviour::#deletion)}
         }).collect();
+        let child_assertions = related_types(&entity_data.children).map(|ty| quote!{#crate_name::assert_child::<Self,#ty>();});
+        let sibling_assertions = related_types(&entity_data.siblings).map(|ty| quote!{#crate_name::assert_sibling::<Self,#ty>();});
+        let version = entity_data.version.unwrap_or(0);
+        let migrate = generate_migrate(&crate_name, &entity_data.migrations);
+        let codec = entity_data.codec.as_ref().map(|codec| quote!{
+            fn codec() -> #crate_name::Codec {
+                #crate_name::Codec::#codec
+            }
+        });
+        let compression = entity_data.compress.as_ref().map(|compress| quote!{
+            fn compression() -> Option<#crate_name::Compression> {
+                Some(#crate_name::Compression::#compress)
+            }
+        });
+        let compression_threshold = entity_data.compress_threshold.map(|threshold| quote!{
+            fn compression_threshold() -> usize {
+                #threshold
+            }
+        });
+        let indexes : Vec<proc_macro2::TokenStream> = entity_data.indexes.iter().map(|(field,_,unique)| {
+            let name = field.to_string();
+            quote!{(#name,#unique)}
+        }).collect();
+        let index_values : Vec<proc_macro2::TokenStream> = entity_data.indexes.iter().map(|(field,field_type,_)| {
+            let name = field.to_string();
+            // An entity whose optional field is `None` is not indexed for it, so that unique indexes accept several of them
+            if is_option(field_type) {
+                quote!{#name => Option::is_some(&self.#field).then(|| #crate_name::AsBytes::as_bytes(&self.#field)),}
+            }
+            else {
+                quote!{#name => Some(#crate_name::AsBytes::as_bytes(&self.#field)),}
+            }
+        }).collect();
+        let index_getters : Vec<proc_macro2::TokenStream> = entity_data.indexes.iter().map(|(field,field_type,_)| {
+            let name = field.to_string();
+            let get_by = Ident::new(&format!("get_by_{}",field),Span::call_site());
+            let find_by = Ident::new(&format!("find_by_{}",field),Span::call_site());
+            quote!{
+                /// Gets the first entity with the given value for this indexed field.
+                pub fn #get_by(value : &#field_type, db : &#crate_name::Db) -> #crate_name::Result<Option<Self>> {
+                    <Self as #crate_name::Entity>::get_by_index(#name, value, db)
+                }
+                /// Gets every entity with the given value for this indexed field.
+                pub fn #find_by(value : &#field_type, db : &#crate_name::Db) -> #crate_name::Result<Vec<Self>> {
+                    <Self as #crate_name::Entity>::find_by_index(#name, value, db)
+                }
+            }
+        }).collect();
+        let free_relations : Vec<proc_macro2::TokenStream> = entity_data.free_relations.0.iter().map(|r| {
+            let (store,name,self_to_other,other_to_self) = (related_store_name(&crate_name, &r.0),&r.1,&r.2,&r.3);
+            quote!{(#store,#name,#crate_name::DeletionBehaviour::#self_to_other,#crate_name::DeletionBehaviour::#other_to_self)}
+        }).collect();
+        let relation_accessors : Vec<proc_macro2::TokenStream> = entity_data.free_relations.0.iter().map(|r| {
+            let (store,name,self_to_other,other_to_self) = (related_store_label(&r.0),&r.1,&r.2,&r.3);
+            let suffix = format!("{}_{}",snake_case(&name.value()),snake_case(&store));
+            let add = Ident::new(&format!("add_{}",suffix),Span::call_site());
+            let get = Ident::new(&format!("{}s",suffix),Span::call_site());
+            let remove = Ident::new(&format!("remove_{}",suffix),Span::call_site());
+            let add_doc = format!("Creates a `{}` relation to an entity of store `{}`.",name.value(),store);
+            let get_doc = format!("Gets the entities of store `{}` related to this one by a `{}` relation.",store,name.value());
+            let remove_doc = format!("Removes the `{}` relation to an entity of store `{}`.",name.value(),store);
+            match &r.0 {
+                RelatedStore::Type(ty) => quote!{
+                    #[doc = #add_doc]
+                    pub fn #add(&self, other : &#ty, db : &#crate_name::Db) -> #crate_name::Result<()> {
+                        <Self as #crate_name::Entity>::create_relation(self, other, #crate_name::DeletionBehaviour::#self_to_other, #crate_name::DeletionBehaviour::#other_to_self, Some(#name), db)
+                    }
+                    #[doc = #get_doc]
+                    pub fn #get(&self, db : &#crate_name::Db) -> #crate_name::Result<Vec<#ty>> {
+                        <Self as #crate_name::Entity>::get_related_with_name::<#ty>(self, #name, db)
+                    }
+                    #[doc = #remove_doc]
+                    pub fn #remove(&self, other : &#ty, db : &#crate_name::Db) -> #crate_name::Result<()> {
+                        <Self as #crate_name::Entity>::remove_relation_with_name(self, other, #name, db)
+                    }
+                },
+                RelatedStore::Name(_) => quote!{
+                    #[doc = #add_doc]
+                    pub fn #add<E : #crate_name::Entity>(&self, other : &E, db : &#crate_name::Db) -> #crate_name::Result<()> {
+                        <Self as #crate_name::Entity>::check_declared_relation::<E>(#name)?;
+                        <Self as #crate_name::Entity>::create_relation(self, other, #crate_name::DeletionBehaviour::#self_to_other, #crate_name::DeletionBehaviour::#other_to_self, Some(#name), db)
+                    }
+                    #[doc = #get_doc]
+                    pub fn #get<E : #crate_name::Entity>(&self, db : &#crate_name::Db) -> #crate_name::Result<Vec<E>> {
+                        <Self as #crate_name::Entity>::check_declared_relation::<E>(#name)?;
+                        <Self as #crate_name::Entity>::get_related_with_name::<E>(self, #name, db)
+                    }
+                    #[doc = #remove_doc]
+                    pub fn #remove<E : #crate_name::Entity>(&self, other : &E, db : &#crate_name::Db) -> #crate_name::Result<()> {
+                        <Self as #crate_name::Entity>::check_declared_relation::<E>(#name)?;
+                        <Self as #crate_name::Entity>::remove_relation_with_name(self, other, #name, db)
+                    }
+                },
+            }
+        }).collect();
         let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        // Generic entities cannot be submitted to the registry, as they have no single type to register
+        let registration = generics.params.is_empty().then(|| quote!{
+            #crate_name::inventory::submit! {
+                #crate_name::EntityRegistration::new::<#struct_name>()
+            }
+        });
         quote!{
             impl #impl_generics #crate_name::Entity for #struct_name #ty_generics #where_clause {
                 type Key = #key_type;
@@ -189,15 +566,116 @@ fn generate_impl(struct_name : &Ident,entity_data : &EntityData, generics : &syn
                     self.#id_field = key.clone();
                 }
                 fn get_child_stores() -> Vec<(&'static str, #crate_name::DeletionBehaviour)> {
-                    vec![#(#children)*]
+                    #(#child_assertions)*
+                    vec![#(#children),*]
                 }
                 fn get_sibling_stores() -> Vec<(&'static str, #crate_name::DeletionBehaviour)> {
-                    vec![#(#siblings)*]
+                    #(#sibling_assertions)*
+                    vec![#(#siblings),*]
+                }
+                fn get_free_relations() -> Vec<(&'static str, &'static str, #crate_name::DeletionBehaviour, #crate_name::DeletionBehaviour)> {
+                    vec![#(#free_relations),*]
                 }
+                fn get_indexes() -> Vec<(&'static str, bool)> {
+                    vec![#(#indexes),*]
+                }
+                fn get_index_value(&self, field : &str) -> Option<Vec<u8>> {
+                    match field {
+                        #(#index_values)*
+                        _ => None,
+                    }
+                }
+                fn version() -> u32 {
+                    #version
+                }
+                #migrate
+                #codec
+                #compression
+                #compression_threshold
+            }
+            impl #impl_generics #struct_name #ty_generics #where_clause {
+                #(#index_getters)*
+                #(#relation_accessors)*
             }
+            #registration
         }.into()
     }
     else {
         TokenStream::new()
     }
-}
\ No newline at end of file
+}
+
+/// The store name of a relation, read from the entity type when one is given.
+fn related_store_name(crate_name : &Ident, store : &RelatedStore) -> proc_macro2::TokenStream {
+    match store {
+        RelatedStore::Name(name) => quote!{#name},
+        RelatedStore::Type(ty) => quote!{<#ty as #crate_name::Entity>::store_name()},
+    }
+}
+
+/// The name a related store is referred to in generated method names : the store name, or the name of the entity type.
+fn related_store_label(store : &RelatedStore) -> String {
+    match store {
+        RelatedStore::Name(name) => name.value(),
+        RelatedStore::Type(syn::Type::Path(path)) => match path.path.segments.last() {
+            Some(segment) => segment.ident.to_string(),
+            None => quote!{#path}.to_string(),
+        },
+        RelatedStore::Type(ty) => quote!{#ty}.to_string(),
+    }
+}
+
+/// Whether a field type is an `Option`, as far as its name tells.
+fn is_option(ty : &syn::Type) -> bool {
+    matches!(ty, syn::Type::Path(path) if path.path.segments.last().is_some_and(|segment| segment.ident == "Option"))
+}
+
+/// The entity types of the child or sibling relations given by type, which need their keys checked.
+fn related_types(relations : &Relations) -> impl Iterator<Item = &syn::Type> {
+    relations.0.iter().filter_map(|r| match &r.0 {
+        RelatedStore::Type(ty) => Some(ty),
+        RelatedStore::Name(_) => None,
+    })
+}
+
+/// Turns a store or relation name into a part of a method name, in snake case.
+fn snake_case(name : &str) -> String {
+    let mut result = String::new();
+    for (i,c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 && !result.ends_with('_') {
+            result.push('_');
+        }
+        if c.is_alphanumeric() {
+            result.extend(c.to_lowercase());
+        }
+        else if !result.ends_with('_') {
+            result.push('_');
+        }
+    }
+    result
+}
+
+fn generate_migrate(crate_name : &Ident, migrations : &[syn::Type]) -> proc_macro2::TokenStream {
+    if migrations.is_empty() {
+        return proc_macro2::TokenStream::new();
+    }
+    let arms : Vec<proc_macro2::TokenStream> = migrations.iter().enumerate().map(|(version,ty)| {
+        let version = version as u32;
+        let steps = &migrations[(version as usize + 1)..];
+        quote!{
+            #version => {
+                let value = Self::deserialize_legacy::<#ty>(data)?;
+                #(let value = <#steps>::from(value);)*
+                Ok(Self::from(value))
+            }
+        }
+    }).collect();
+    quote!{
+        fn migrate(from_version : u32, data : &[u8]) -> #crate_name::Result<Self> {
+            match from_version {
+                #(#arms)*
+                _ => Err(#crate_name::Error::new(#crate_name::ErrorKind::MigrationError, format!("No migration from version {} for store {}", from_version, Self::store_name()))),
+            }
+        }
+    }
+}
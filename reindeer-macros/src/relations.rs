@@ -2,30 +2,47 @@ use proc_macro2::{TokenTree, Ident};
 use quote::ToTokens;
 use syn::{parse::Parse, parenthesized, punctuated::Punctuated, Token, LitStr};
 
+/// The related store of a child or sibling relation : either its store name, or the entity type itself.
 #[derive(Clone)]
-pub struct Relation(pub syn::LitStr,pub syn::Ident);
+pub enum RelatedStore {
+    Name(syn::LitStr),
+    Type(syn::Type),
+}
+
+#[derive(Clone)]
+pub struct Relation(pub RelatedStore,pub syn::Ident);
+
+impl Relation {
+    fn parse_deletion(deletion : Ident) -> syn::Result<Ident> {
+        match &*deletion.to_string() {
+            "Cascade" | "BreakLink" | "Error" => Ok(deletion),
+            _ => Err(syn::Error::new_spanned(deletion, r#"The second part of the relation must be either Cascade, BreakLink or Error"#)),
+        }
+    }
+}
 
 impl Parse for Relation {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if !input.peek(syn::token::Paren) {
+            let entity = input.parse::<syn::Type>()?;
+            input.parse::<Token![=]>()?;
+            let deletion = input.parse::<Ident>()?;
+            return Ok(Self(RelatedStore::Type(entity),Self::parse_deletion(deletion)?));
+        }
         let content;
         parenthesized!(content in input);
         let result = Punctuated::<TokenTree,Token![,]>::parse_separated_nonempty(&content)?;
         if result.len() != 2 {
-            return Err(syn::Error::new(input.span(), format!(r#"A relation must respect the syntax ("store_name",Cascade) {}"#,result.len())))
+            return Err(syn::Error::new(input.span(), format!(r#"A relation must respect the syntax EntityType = Cascade or ("store_name",Cascade) {}"#,result.len())))
         }
         let res1 = result[0].clone().into_token_stream().into();
         let res2 = result[1].clone().into_token_stream().into();
         match (syn::parse::<LitStr>(res1),syn::parse::<Ident>(res2)) {
-            (Ok(name),Ok(deletion)) => {
-                match &*deletion.to_string() {
-                    "Cascade" | "BreakLink" | "Error" => Ok(Self(name,deletion)),
-                    _ => Err(syn::Error::new_spanned(deletion, r#"The second part of the relation must be either Cascade, BreakLink or Error"#)),
-                }
-            },
+            (Ok(name),Ok(deletion)) => Ok(Self(RelatedStore::Name(name),Self::parse_deletion(deletion)?)),
             (Err(e),Ok(_)) => Err(syn::Error::new(e.span(), r#"The first part of the relation must be the store name as a string."#)),
             (Ok(_),Err(e)) => Err(syn::Error::new(e.span(), r#"The second part of the relation must be either Cascade, BreakLink or Error"#)),
             _ => {
-                Err(syn::Error::new(input.span(), r#"A relation must respect the syntax ("store_name",Cascade)"#))
+                Err(syn::Error::new(input.span(), r#"A relation must respect the syntax EntityType = Cascade or ("store_name",Cascade)"#))
             }
         }
     }
@@ -40,3 +57,55 @@ impl Parse for Relations {
         Ok(Relations(result.into_iter().collect()))
     }
 }
+
+
+/// A free relation : the related store, the relation name and the deletion behaviours in both ways.
+#[derive(Clone)]
+pub struct FreeRelation(pub RelatedStore,pub syn::LitStr,pub syn::Ident,pub syn::Ident);
+
+impl Parse for FreeRelation {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        const SYNTAX : &str = r#"A free relation must respect the syntax (EntityType,"relation_name",Cascade,BreakLink) or ("store_name","relation_name",Cascade,BreakLink)"#;
+        let content;
+        parenthesized!(content in input);
+        let store = if content.peek(LitStr) {
+            let name = content.parse::<LitStr>()?;
+            if name.value().is_empty() {
+                return Err(syn::Error::new_spanned(name, r#"The store name of a free relation cannot be empty."#));
+            }
+            RelatedStore::Name(name)
+        }
+        else {
+            RelatedStore::Type(content.parse::<syn::Type>().map_err(|e| syn::Error::new(e.span(), SYNTAX))?)
+        };
+        content.parse::<Token![,]>().map_err(|e| syn::Error::new(e.span(), SYNTAX))?;
+        let name = match content.parse::<LitStr>() {
+            Ok(name) if !name.value().is_empty() => name,
+            Ok(name) => return Err(syn::Error::new_spanned(name, r#"The second part of a free relation must be the relation name as a non-empty string."#)),
+            Err(e) => return Err(syn::Error::new(e.span(), r#"The second part of a free relation must be the relation name as a non-empty string."#)),
+        };
+        let mut deletions = Vec::new();
+        for _ in 0..2 {
+            content.parse::<Token![,]>().map_err(|e| syn::Error::new(e.span(), SYNTAX))?;
+            match content.parse::<Ident>() {
+                Ok(deletion) if matches!(&*deletion.to_string(), "Cascade" | "BreakLink" | "Error") => deletions.push(deletion),
+                Ok(deletion) => return Err(syn::Error::new_spanned(deletion, r#"The last two parts of a free relation must be either Cascade, BreakLink or Error"#)),
+                Err(e) => return Err(syn::Error::new(e.span(), r#"The last two parts of a free relation must be either Cascade, BreakLink or Error"#)),
+            }
+        }
+        if !content.is_empty() {
+            return Err(syn::Error::new(content.span(), SYNTAX));
+        }
+        Ok(Self(store,name,deletions[0].clone(),deletions[1].clone()))
+    }
+}
+
+#[derive(Default,Clone)]
+pub struct FreeRelations(pub Vec<FreeRelation>);
+
+impl Parse for FreeRelations {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let result = Punctuated::<FreeRelation,Token!(,)>::parse_separated_nonempty(input)?;
+        Ok(FreeRelations(result.into_iter().collect()))
+    }
+}
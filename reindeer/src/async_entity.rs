@@ -0,0 +1,169 @@
+//! # Async Module
+//! This module provides an async counterpart to the [`Entity`](entity/trait.Entity.html) trait, available with the `async` feature.
+//!
+//! `sled` operations are blocking, so they are run on `tokio`'s blocking thread pool
+//! to avoid stalling the async runtime.
+
+use std::future::Future;
+
+use sled::Db;
+
+use crate::entity::Entity;
+use crate::error::Result;
+use crate::key::AsBytes;
+use crate::page::{Cursor, Page};
+use crate::query_builder::QueryBuilder;
+
+/// Runs `f` on `tokio`'s blocking thread pool with a handle to `db`, and returns its result.
+///
+/// This can be used to call any method of the [`Entity`](entity/trait.Entity.html) trait, or to
+/// run several operations at once, from async code.
+///
+/// ### Example
+/// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "parent")]
+/// # #[children(Child = Cascade)]
+/// # struct Parent { id : u32 }
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "child")]
+/// # struct Child { id : (u32, u32) }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// # Parent::register(&db)?;
+/// # Child::register(&db)?;
+/// # let parent = Parent { id : 1 };
+/// let children = reindeer::run_blocking(&db, move |db| parent.get_children::<Child>(db)).await?;
+/// # Ok(())
+/// # })
+/// # }
+/// ```
+pub fn run_blocking<R, F>(db: &Db, f: F) -> impl Future<Output = Result<R>> + Send + 'static
+where
+    R: Send + 'static,
+    F: FnOnce(&Db) -> Result<R> + Send + 'static,
+{
+    let db = db.clone();
+    async move {
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+/// Asynchronously flushes every pending write to disk, using `sled`'s `flush_async`.
+/// Returns the number of bytes flushed.
+pub async fn flush_async(db: &Db) -> Result<usize> {
+    Ok(db.flush_async().await?)
+}
+
+/// Async versions of the most common [`Entity`](entity/trait.Entity.html) methods, available with the `async` feature.
+///
+/// This trait is implemented for every `Entity` that can be sent across threads, and needs to be in scope.
+/// Other methods can be called from async code with [`run_blocking`](fn.run_blocking.html).
+///
+/// ### Example
+/// ```rust
+/// use reindeer::AsyncEntity;
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// # #[derive(Serialize, Deserialize, Entity, Clone)]
+/// # #[entity(name = "my_struct", id = "key")]
+/// # struct MyStruct { key : u32 }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// # MyStruct::register(&db)?;
+/// # let my_struct = MyStruct { key : 3 };
+///
+/// my_struct.save_async(&db).await?;
+/// let my_struct = MyStruct::get_async(&3, &db).await?;
+/// # Ok(())
+/// # })
+/// # }
+/// ```
+pub trait AsyncEntity: Entity<Key: Send> + Send + 'static {
+    /// Async version of [`Entity::get`](entity/trait.Entity.html#method.get)
+    fn get_async(key: &Self::Key, db: &Db) -> impl Future<Output = Result<Option<Self>>> + Send {
+        let key = key.clone();
+        run_blocking(db, move |db| Self::get(&key, db))
+    }
+
+    /// Async version of [`Entity::get_all`](entity/trait.Entity.html#method.get_all)
+    fn get_all_async(db: &Db) -> impl Future<Output = Result<Vec<Self>>> + Send {
+        run_blocking(db, Self::get_all)
+    }
+
+    /// Async version of [`Entity::get_with_filter`](entity/trait.Entity.html#method.get_with_filter)
+    fn get_with_filter_async<F: Fn(&Self) -> bool + Send + 'static>(
+        f: F,
+        db: &Db,
+    ) -> impl Future<Output = Result<Vec<Self>>> + Send {
+        run_blocking(db, move |db| Self::get_with_filter(f, db))
+    }
+
+    /// Async version of [`Entity::page_after`](entity/trait.Entity.html#method.page_after)
+    fn page_after_async<K: AsBytes + Send + 'static>(
+        cursor: Option<Cursor>,
+        count: usize,
+        parent: Option<K>,
+        db: &Db,
+    ) -> impl Future<Output = Result<Page<Self>>> + Send {
+        run_blocking(db, move |db| {
+            Self::page_after(cursor.as_ref(), count, parent, db)
+        })
+    }
+
+    /// Async version of [`Entity::exists`](entity/trait.Entity.html#method.exists)
+    fn exists_async(key: &Self::Key, db: &Db) -> impl Future<Output = Result<bool>> + Send {
+        let key = key.clone();
+        run_blocking(db, move |db| Self::exists(&key, db))
+    }
+
+    /// Async version of [`Entity::save`](entity/trait.Entity.html#method.save).
+    /// The entity is cloned to be saved on another thread.
+    fn save_async(&self, db: &Db) -> impl Future<Output = Result<()>> + Send
+    where
+        Self: Clone,
+    {
+        let entity = self.clone();
+        run_blocking(db, move |db| entity.save(db))
+    }
+
+    /// Async version of [`Entity::update`](entity/trait.Entity.html#method.update)
+    fn update_async<F: Fn(&mut Self) + Send + 'static>(
+        key: &Self::Key,
+        f: F,
+        db: &Db,
+    ) -> impl Future<Output = Result<()>> + Send {
+        let key = key.clone();
+        run_blocking(db, move |db| Self::update(&key, f, db))
+    }
+
+    /// Async version of [`Entity::remove`](entity/trait.Entity.html#method.remove)
+    fn remove_async(key: &Self::Key, db: &Db) -> impl Future<Output = Result<()>> + Send {
+        let key = key.clone();
+        run_blocking(db, move |db| Self::remove(&key, db))
+    }
+}
+
+impl<E> AsyncEntity for E where E: Entity<Key: Send> + Send + 'static {}
+
+impl<'a> QueryBuilder<'a> {
+    /// Async version of [`get`](struct.QueryBuilder.html#method.get), available with the `async` feature.
+    pub fn get_async<T: AsyncEntity>(&self, db: &Db) -> impl Future<Output = Result<Vec<T>>> + Send {
+        let query = self.to_owned_query();
+        run_blocking(db, move |db| query.get::<T>(db))
+    }
+
+    /// Async version of [`get_keys`](struct.QueryBuilder.html#method.get_keys), available with the `async` feature.
+    pub fn get_keys_async<T: AsyncEntity>(
+        &self,
+        db: &Db,
+    ) -> impl Future<Output = Result<Vec<T::Key>>> + Send {
+        let query = self.to_owned_query();
+        run_blocking(db, move |db| query.get_keys::<T>(db))
+    }
+}
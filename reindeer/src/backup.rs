@@ -0,0 +1,354 @@
+//! # Backup Module
+//! This module provides full-database backups, without the need to know the concrete type of every entity.
+//!
+//! [`export_all`](fn.export_all.html) walks the registry of the entities registered with
+//! [`Entity::register`](../entity/trait.Entity.html#method.register) and writes every store, along with
+//! its relations, indexes, family descriptor and schema version, to a single JSON archive.
+//! [`import_all`](fn.import_all.html) restores such an archive.
+//!
+//! ### Archive format
+//! An archive is a JSON object with two fields :
+//! - `manifest` : the [`Manifest`](struct.Manifest.html) of the archive, always written first,
+//! - `trees` : an object giving, for the name of each saved `sled` tree, the array of its entries in key order.
+//!   Each entry is a `[key, value]` pair of hexadecimal strings.
+//!
+//! Keys and values are the raw bytes of the database, exactly as they are stored : values are encoded with the
+//! codec of their entity, then compressed or [encrypted](../encryption/index.html) if their store or database is. An archive can therefore only be read by restoring it.
+//!
+//! ### Example
+//! ```rust,no_run
+//! # use std::fs::File;
+//! # fn main() -> reindeer::Result<()> {
+//! # let db = sled::Config::new().temporary(true).open()?;
+//! let manifest = reindeer::backup::export_all(&db, File::create("backup.json")?)?;
+//! /* ... */
+//! reindeer::backup::import_all(&db, File::open("backup.json")?)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_derive::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::cipher;
+use crate::error::Result;
+use crate::hex;
+use crate::index;
+use crate::layout;
+use crate::migration;
+use crate::registry;
+use crate::state;
+use crate::relation::{FamilyDescriptor, Relation};
+use crate::{Entity, Error, ErrorKind};
+
+/// Version of the archive format written by [`export_all`](fn.export_all.html).
+///
+/// Version 2 stores relations as one edge per link. Archives of version 1 can still be restored :
+/// their relations are converted when the entities are registered.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Description of the content of a backup archive.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// Version of the archive format
+    pub format_version: u32,
+    /// Every entity store saved in the archive
+    pub stores: Vec<StoreManifest>,
+}
+
+/// Description of an entity store saved in a backup archive.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct StoreManifest {
+    /// Name of the store
+    pub name: String,
+    /// Schema version of the store at the time of the backup, if one was recorded
+    pub version: Option<u32>,
+    /// Number of entities in the store
+    pub entities: usize,
+}
+
+/// Writes every registered entity store, its relations, indexes, family descriptor and schema version
+/// to `writer` as a single JSON archive, and returns the manifest of the archive.
+///
+/// Entries are written one at a time, as they are read. Every other write to the database waits until
+/// the export is done, so that the archive is a consistent snapshot of the database.
+///
+/// ⚠ Values are saved as they are stored : the archive of an [encrypted](../encryption/index.html) database
+/// can only be restored into a database using the same key.
+pub fn export_all(db: &Db, writer: impl Write) -> Result<Manifest> {
+    layout::ensure_checked(db)?;
+    let state = state::of(db)?;
+    let _snapshot = state.commit();
+    let mut stores = Vec::new();
+    let mut tree_names = vec![
+        String::from(FamilyDescriptor::store_name()),
+        String::from(migration::VERSION_TREE),
+        String::from(layout::LAYOUT_TREE),
+        String::from(cipher::ENCRYPTION_TREE),
+    ];
+    for descriptor in FamilyDescriptor::get_all(db)? {
+        let name = descriptor.tree_name;
+        stores.push(StoreManifest {
+            version: migration::get_store_version(&name, db)?,
+            entities: db.open_tree(&name)?.len(),
+            name: name.clone(),
+        });
+        tree_names.push(Relation::tree_name(&name));
+        tree_names.extend(index::tree_names(&name, db));
+        tree_names.push(name);
+    }
+    let manifest = Manifest {
+        format_version: FORMAT_VERSION,
+        stores,
+    };
+    // The archive is written one entry at a time, so that the database does not need to fit in memory
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(b"{\"manifest\":")?;
+    serde_json::to_writer(&mut writer, &manifest)?;
+    writer.write_all(b",\"trees\":{")?;
+    tree_names.sort();
+    tree_names.dedup();
+    for (i, name) in tree_names.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut writer, name)?;
+        writer.write_all(b":[")?;
+        for (j, elem) in db.open_tree(name)?.iter().enumerate() {
+            let (key, value) = elem?;
+            if j > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut writer, &(hex::encode(&key), hex::encode(&value)))?;
+        }
+        writer.write_all(b"]")?;
+    }
+    writer.write_all(b"}}")?;
+    writer.flush()?;
+    Ok(manifest)
+}
+
+/// Restores an archive written by [`export_all`](fn.export_all.html), and returns its manifest.
+///
+/// The trees of every store saved in the archive are replaced by their saved content. Stores that are not in
+/// the archive are left untouched, and remain registered.
+///
+/// The archive is read one entry at a time into staging trees, so that it does not need to fit in memory.
+/// Nothing is written to the stores until the whole archive has been read : an invalid or truncated archive
+/// leaves the database untouched. The staged entries are then copied over the stores while every other write
+/// to the database waits. If the process stops during this copy, the database may be partly restored :
+/// import the archive again.
+///
+/// Register your entities after the restoration so that stores saved with an older schema version are migrated.
+pub fn import_all(db: &Db, reader: impl Read) -> Result<Manifest> {
+    layout::ensure_checked(db)?;
+    drop_staging_trees(db)?;
+    let mut staging = Staging {
+        db,
+        trees: Vec::new(),
+        error: None,
+    };
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(reader));
+    let manifest = match (&mut staging).deserialize(&mut deserializer).and_then(|manifest| {
+        deserializer.end()?;
+        Ok(manifest)
+    }) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            let error = staging.error.take().unwrap_or_else(|| e.into());
+            drop_staging_trees(db)?;
+            return Err(error);
+        }
+    };
+    let trees = staging
+        .trees
+        .iter()
+        .map(|(name, staged)| Ok((name, db.open_tree(name)?, staged)))
+        .collect::<Result<Vec<_>>>()?;
+    let state = state::of(db)?;
+    let commit = state.commit();
+    for (name, tree, staged) in &trees {
+        // The registry trees are shared with the stores that are not in the archive, so they are merged instead
+        if !is_registry_tree(name) {
+            for key in tree.iter().keys() {
+                let key = key?;
+                if !staged.contains_key(&key)? {
+                    tree.remove(key)?;
+                }
+            }
+        }
+        for elem in staged.iter() {
+            let (key, value) = elem?;
+            tree.insert(key, value)?;
+        }
+    }
+    drop(commit);
+    registry::families_changed();
+    drop_staging_trees(db)?;
+    Ok(manifest)
+}
+
+/// Prefix of the trees an archive is read into before it is restored.
+const STAGING_PREFIX: &str = "__$import/";
+
+fn is_registry_tree(name: &str) -> bool {
+    name == FamilyDescriptor::store_name()
+        || name == migration::VERSION_TREE
+        || name == layout::LAYOUT_TREE
+        || name == cipher::ENCRYPTION_TREE
+}
+
+/// Drops the staging trees, including those left by an import that did not complete.
+fn drop_staging_trees(db: &Db) -> Result<()> {
+    for name in db.tree_names() {
+        if name.starts_with(STAGING_PREFIX.as_bytes()) {
+            db.drop_tree(name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads an archive into staging trees, one entry at a time.
+///
+/// Errors that are not about the archive itself are kept in `error`, as `serde` only reports its own errors.
+struct Staging<'a> {
+    db: &'a Db,
+    /// The name of every tree of the archive, with the staging tree holding its entries
+    trees: Vec<(String, Tree)>,
+    error: Option<Error>,
+}
+
+impl Staging<'_> {
+    fn fail<E: de::Error>(&mut self, error: Error) -> E {
+        let message = error.to_string();
+        self.error = Some(error);
+        E::custom(message)
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for &mut Staging<'_> {
+    type Value = Manifest;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Manifest, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for &mut Staging<'_> {
+    type Value = Manifest;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a backup archive")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<Manifest, A::Error> {
+        let mut manifest: Option<Manifest> = None;
+        while let Some(field) = map.next_key::<String>()? {
+            match field.as_str() {
+                "manifest" => {
+                    let read: Manifest = map.next_value()?;
+                    if read.format_version > FORMAT_VERSION {
+                        return Err(self.fail(Error::new(
+                            ErrorKind::SerializationError,
+                            format!(
+                                "Backup archive format version {} is not supported (latest is {})",
+                                read.format_version, FORMAT_VERSION
+                            ),
+                        )));
+                    }
+                    manifest = Some(read);
+                }
+                "trees" => map.next_value_seed(StagedTrees(&mut *self))?,
+                _ => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        manifest.ok_or_else(|| de::Error::missing_field("manifest"))
+    }
+}
+
+/// The `trees` object of an archive : the entries of each tree, by name.
+struct StagedTrees<'s, 'a>(&'s mut Staging<'a>);
+
+impl<'de> DeserializeSeed<'de> for StagedTrees<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StagedTrees<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the trees of a backup archive")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+        let staging = self.0;
+        while let Some(name) = map.next_key::<String>()? {
+            let tree = match staging.db.open_tree(format!("{}{}", STAGING_PREFIX, name)) {
+                Ok(tree) => tree,
+                Err(e) => return Err(staging.fail(e.into())),
+            };
+            map.next_value_seed(StagedEntries {
+                staging: &mut *staging,
+                name: &name,
+                tree: &tree,
+            })?;
+            staging.trees.push((name, tree));
+        }
+        Ok(())
+    }
+}
+
+/// The entries of a tree in an archive, as pairs of hexadecimal strings.
+struct StagedEntries<'s, 'a> {
+    staging: &'s mut Staging<'a>,
+    name: &'s str,
+    tree: &'s Tree,
+}
+
+impl<'de> DeserializeSeed<'de> for StagedEntries<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StagedEntries<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the entries of a tree")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        while let Some((key, value)) = seq.next_element::<(String, String)>()? {
+            let inserted = decode(&key, self.name).and_then(|key| {
+                self.tree.insert(key, decode(&value, self.name)?)?;
+                Ok(())
+            });
+            if let Err(e) = inserted {
+                return Err(self.staging.fail(e));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decode(s: &str, tree_name: &str) -> Result<Vec<u8>> {
+    hex::decode(s).ok_or_else(|| {
+        Error::new(
+            ErrorKind::SerializationError,
+            format!("Invalid entry in tree {} of the backup archive", tree_name),
+        )
+    })
+}
@@ -0,0 +1,103 @@
+//! # Cipher Module
+//! This module seals and opens the values of the encrypted trees of a database : entity stores,
+//! relation descriptors and family descriptors.
+//!
+//! Without the `encryption` feature, or when no key has been set for a database, values are left untouched.
+
+#[cfg(feature = "encryption")]
+use std::sync::Arc;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::ChaCha20Poly1305;
+use sled::{Db, IVec};
+
+use crate::error::Result;
+#[cfg(feature = "encryption")]
+use crate::state;
+use crate::state::DbState;
+
+/// Header byte of encrypted values. It does not overlap with codec tags or compression headers.
+pub(crate) const ENCRYPTED_TAG: u8 = 0xE0;
+
+/// Name of the tree holding the value used to check the encryption key of a database.
+pub(crate) const ENCRYPTION_TREE: &str = "__$encryption";
+
+/// The cipher used to seal and open the values of a database, with the key set for this database, if any.
+#[derive(Clone, Default)]
+pub(crate) struct Cipher {
+    #[cfg(feature = "encryption")]
+    aead: Option<Arc<ChaCha20Poly1305>>,
+}
+
+#[cfg(feature = "encryption")]
+impl Cipher {
+    pub(crate) fn new(aead: Option<Arc<ChaCha20Poly1305>>) -> Cipher {
+        Cipher { aead }
+    }
+
+    /// Gets the cipher for the key currently set for `db`.
+    pub(crate) fn of(db: &Db) -> Result<Cipher> {
+        Ok(Cipher::current(&*state::of(db)?))
+    }
+
+    /// Gets the cipher for the key currently set for the database of `state`.
+    ///
+    /// Values sealed with it must be written while holding [`DbState::write`](../state/struct.DbState.html),
+    /// so that the key cannot be rotated in between.
+    pub(crate) fn current(state: &DbState) -> Cipher {
+        Cipher::new(state.key.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+
+    /// Checks if this cipher still uses the key set for the database of `state`.
+    pub(crate) fn is_current(&self, state: &DbState) -> bool {
+        match (&self.aead, &*state.key.read().unwrap_or_else(|e| e.into_inner())) {
+            (Some(aead), Some(key)) => Arc::ptr_eq(aead, key),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn is_set(&self) -> bool {
+        self.aead.is_some()
+    }
+
+    /// Encrypts `value`, stored for `key` in `tree`, if a key is set.
+    /// The value can then only be opened for this key of this tree.
+    pub(crate) fn seal(&self, tree: &str, key: &[u8], value: IVec) -> Result<IVec> {
+        match &self.aead {
+            Some(aead) => crate::encryption::seal(aead, tree, key, &value),
+            None => Ok(value),
+        }
+    }
+
+    /// Decrypts `value`, stored for `key` in `tree`, if a key is set.
+    pub(crate) fn open(&self, tree: &str, key: &[u8], value: IVec) -> Result<IVec> {
+        match &self.aead {
+            Some(aead) => crate::encryption::open(aead, tree, key, &value),
+            None => Ok(value),
+        }
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+impl Cipher {
+    pub(crate) fn of(_db: &Db) -> Result<Cipher> {
+        Ok(Cipher {})
+    }
+
+    pub(crate) fn current(_state: &DbState) -> Cipher {
+        Cipher {}
+    }
+
+    pub(crate) fn is_current(&self, _state: &DbState) -> bool {
+        true
+    }
+
+    pub(crate) fn seal(&self, _tree: &str, _key: &[u8], value: IVec) -> Result<IVec> {
+        Ok(value)
+    }
+
+    pub(crate) fn open(&self, _tree: &str, _key: &[u8], value: IVec) -> Result<IVec> {
+        Ok(value)
+    }
+}
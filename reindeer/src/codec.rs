@@ -0,0 +1,133 @@
+//! # Codec Module
+//! This module provides the serialization formats that can be used to store entity values.
+//!
+//! Every value is stored with a leading byte identifying the format it was written with, so that
+//! values are always read with the right format, even after the codec of an entity has been changed,
+//! and stores using different formats can live in the same database.
+
+use serde::{de::DeserializeOwned, Serialize};
+use sled::IVec;
+
+use crate::cipher::ENCRYPTED_TAG;
+use crate::compression;
+use crate::error::Result;
+use crate::{Error, ErrorKind};
+
+/// The serialization format used to store the values of an entity store.
+///
+/// It is selected per entity with [`Entity::codec`](entity/trait.Entity.html#method.codec),
+/// or with `#[entity(codec = "json")]` with the derive macro.
+///
+///  - `Bincode` (the default) is compact and fast, but is not self-describing : it does not support
+///    `#[serde(skip_serializing_if)]`, untagged enums or reordered fields.
+///  - `Json` is self-describing, and readable, but larger.
+///  - `Postcard` (feature `postcard`) is the most compact, with the same limitations as `Bincode`.
+///  - `Cbor` (feature `cbor`) and `MessagePack` (feature `messagepack`) are compact, self-describing binary formats.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// [`bincode`](https://docs.rs/bincode) 1
+    Bincode,
+    /// [`serde_json`](https://docs.rs/serde_json)
+    Json,
+    /// [`postcard`](https://docs.rs/postcard) 1
+    #[cfg(feature = "postcard")]
+    Postcard,
+    /// CBOR, using [`serde_cbor`](https://docs.rs/serde_cbor)
+    #[cfg(feature = "cbor")]
+    Cbor,
+    /// MessagePack, using [`rmp-serde`](https://docs.rs/rmp-serde), with field names
+    #[cfg(feature = "messagepack")]
+    MessagePack,
+}
+
+impl Codec {
+    /// The byte identifying this codec, stored before every value.
+    pub fn tag(self) -> u8 {
+        match self {
+            Codec::Bincode => 0,
+            Codec::Json => 1,
+            #[cfg(feature = "postcard")]
+            Codec::Postcard => 2,
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => 3,
+            #[cfg(feature = "messagepack")]
+            Codec::MessagePack => 4,
+        }
+    }
+
+    /// Returns the codec identified by `tag`, if it is known and enabled.
+    pub fn from_tag(tag: u8) -> Option<Codec> {
+        match tag {
+            0 => Some(Codec::Bincode),
+            1 => Some(Codec::Json),
+            #[cfg(feature = "postcard")]
+            2 => Some(Codec::Postcard),
+            #[cfg(feature = "cbor")]
+            3 => Some(Codec::Cbor),
+            #[cfg(feature = "messagepack")]
+            4 => Some(Codec::MessagePack),
+            _ => None,
+        }
+    }
+
+    /// Serializes `value` with this codec, prefixed with the tag of the codec.
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<IVec> {
+        let mut bytes = vec![self.tag()];
+        match self {
+            Codec::Bincode => bincode::serialize_into(&mut bytes, value)?,
+            Codec::Json => serde_json::to_writer(&mut bytes, value)?,
+            #[cfg(feature = "postcard")]
+            Codec::Postcard => {
+                bytes = postcard::to_extend(value, bytes).map_err(serialization_error)?
+            }
+            #[cfg(feature = "cbor")]
+            Codec::Cbor => serde_cbor::to_writer(&mut bytes, value).map_err(serialization_error)?,
+            #[cfg(feature = "messagepack")]
+            Codec::MessagePack => {
+                rmp_serde::encode::write_named(&mut bytes, value).map_err(serialization_error)?
+            }
+        }
+        Ok(IVec::from(bytes))
+    }
+
+    /// Deserializes a value written by [`encode`](enum.Codec.html#method.encode), with the codec it was written with.
+    /// Values compressed with a [`Compression`](enum.Compression.html) algorithm are decompressed first.
+    pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        if bytes.first() == Some(&ENCRYPTED_TAG) {
+            return Err(Error::new(
+                ErrorKind::AuthenticationError,
+                String::from("This value is encrypted : set the encryption key of the database first (feature `encryption`)"),
+            ));
+        }
+        let bytes = compression::decompress(bytes)?;
+        let (tag, data) = bytes.split_first().ok_or_else(|| {
+            Error::new(
+                ErrorKind::SerializationError,
+                String::from("Empty value"),
+            )
+        })?;
+        match Codec::from_tag(*tag) {
+            Some(Codec::Bincode) => Ok(bincode::deserialize(data)?),
+            Some(Codec::Json) => Ok(serde_json::from_slice(data)?),
+            #[cfg(feature = "postcard")]
+            Some(Codec::Postcard) => postcard::from_bytes(data).map_err(serialization_error),
+            #[cfg(feature = "cbor")]
+            Some(Codec::Cbor) => serde_cbor::from_slice(data).map_err(serialization_error),
+            #[cfg(feature = "messagepack")]
+            Some(Codec::MessagePack) => rmp_serde::from_slice(data).map_err(serialization_error),
+            None => Err(Error::new(
+                ErrorKind::SerializationError,
+                format!(
+                    "Unknown codec tag {}. Is the matching feature of reindeer enabled ?",
+                    tag
+                ),
+            )),
+        }
+    }
+}
+
+#[cfg(any(feature = "postcard", feature = "cbor", feature = "messagepack"))]
+fn serialization_error(e: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::SerializationError, e.to_string())
+}
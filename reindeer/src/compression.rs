@@ -0,0 +1,116 @@
+//! # Compression Module
+//! This module provides the compression algorithms that can be applied to the serialized values of an entity store.
+//!
+//! A compressed value starts with a header byte identifying the algorithm, followed by the compressed
+//! value as written by its [`Codec`](enum.Codec.html). Header bytes do not overlap with codec tags,
+//! so compressed and uncompressed values can coexist in the same store.
+
+use std::borrow::Cow;
+
+use sled::IVec;
+
+use crate::error::Result;
+use crate::{Error, ErrorKind};
+
+/// First header byte used for compressed values. Lower bytes are codec tags.
+const COMPRESSED_TAGS: u8 = 0x80;
+#[cfg(feature = "zstd")]
+const ZSTD_TAG: u8 = COMPRESSED_TAGS;
+#[cfg(feature = "lz4")]
+const LZ4_TAG: u8 = COMPRESSED_TAGS + 1;
+
+/// Default size, in bytes, from which serialized values are compressed.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 512;
+
+/// A compression algorithm for the values of an entity store.
+///
+/// It is selected per entity with [`Entity::compression`](entity/trait.Entity.html#method.compression),
+/// or with `#[entity(compress = "zstd")]` with the derive macro.
+///
+///  - `Zstd` (feature `zstd`) gives the best compression ratio.
+///  - `Lz4` (feature `lz4`) is faster, but compresses less.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// [`zstd`](https://docs.rs/zstd), with its default level
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// LZ4, using [`lz4_flex`](https://docs.rs/lz4_flex)
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    /// The header byte identifying this algorithm, stored before every compressed value.
+    pub fn tag(self) -> u8 {
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => ZSTD_TAG,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => LZ4_TAG,
+        }
+    }
+
+    /// Returns the algorithm identified by `tag`, if it is known and enabled.
+    pub fn from_tag(tag: u8) -> Option<Compression> {
+        match tag {
+            #[cfg(feature = "zstd")]
+            ZSTD_TAG => Some(Compression::Zstd),
+            #[cfg(feature = "lz4")]
+            LZ4_TAG => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Compresses `value` if it is at least `threshold` bytes long, and if compressing it makes it smaller.
+    pub fn compress(self, value: IVec, threshold: usize) -> Result<IVec> {
+        if value.len() < threshold {
+            return Ok(value);
+        }
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(self.smallest(zstd::encode_all(value.as_ref(), 0)?, value)),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(self.smallest(lz4_flex::compress_prepend_size(&value), value)),
+        }
+    }
+
+    /// Returns the compressed value with its header, unless it is not smaller than the original value.
+    #[cfg(any(feature = "zstd", feature = "lz4"))]
+    fn smallest(self, compressed: Vec<u8>, value: IVec) -> IVec {
+        if compressed.len() + 1 < value.len() {
+            IVec::from([&[self.tag()], compressed.as_slice()].concat())
+        } else {
+            value
+        }
+    }
+
+    #[cfg(any(feature = "zstd", feature = "lz4"))]
+    fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::decode_all(data)?),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::decompress_size_prepended(data)
+                .map_err(|e| Error::new(ErrorKind::SerializationError, e.to_string())),
+        }
+    }
+}
+
+/// Returns `value` decompressed if it has a compression header, or `value` itself otherwise.
+pub(crate) fn decompress(value: &[u8]) -> Result<Cow<'_, [u8]>> {
+    match value.first() {
+        Some(tag) if *tag >= COMPRESSED_TAGS => match Compression::from_tag(*tag) {
+            #[cfg(any(feature = "zstd", feature = "lz4"))]
+            Some(compression) => Ok(Cow::Owned(compression.decompress(&value[1..])?)),
+            _ => Err(Error::new(
+                ErrorKind::SerializationError,
+                format!(
+                    "Unknown compression header {}. Is the matching feature of reindeer enabled ?",
+                    tag
+                ),
+            )),
+        },
+        _ => Ok(Cow::Borrowed(value)),
+    }
+}
@@ -0,0 +1,231 @@
+//! # Encryption Module
+//! This module provides at-rest encryption of a database, available with the `encryption` feature.
+//!
+//! Once a key is set for a database with [`set_key`](fn.set_key.html), the values of every entity store,
+//! relation descriptor and family descriptor are encrypted with ChaCha20-Poly1305 before being written,
+//! and authenticated when read, along with the name of their tree and their key. A value that cannot be
+//! authenticated, because the key is wrong, or because the value has been tampered with or moved to another entry,
+//! results in an [`ErrorKind::AuthenticationError`](../enum.ErrorKind.html).
+//!
+//! ⚠ Keys are not encrypted, since they are needed to order entities : do not use sensitive data as keys,
+//! nor as indexed fields or relation names.
+//!
+//! ### Example
+//! ```rust,no_run
+//! # use reindeer::{Entity, Serialize, Deserialize};
+//! # use reindeer::encryption::EncryptionKey;
+//! # #[derive(Serialize, Deserialize, Entity)]
+//! # #[entity(name = "my_struct", id = "key")]
+//! # struct MyStruct { key : u32 }
+//! # fn main() -> reindeer::Result<()> {
+//! # let key_bytes = [0u8; 32];
+//! let db = reindeer::open("./my-db")?;
+//! reindeer::encryption::set_key(&db, &EncryptionKey::from_bytes(key_bytes))?;
+//! MyStruct::register(&db)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{Db, IVec, Transactional};
+use zeroize::Zeroize;
+
+use crate::cipher::{Cipher, ENCRYPTED_TAG, ENCRYPTION_TREE};
+use crate::error::Result;
+use crate::registry::INTERNAL_STORE_PREFIX;
+use crate::relation::{edge, FamilyDescriptor, Relation};
+use crate::state;
+use crate::{Entity, Error, ErrorKind};
+
+const NONCE_SIZE: usize = 12;
+const CHECK_KEY: &str = "check";
+const CHECK_VALUE: &[u8] = b"reindeer";
+
+/// A 256-bit key used to encrypt a database. Its bytes are zeroed when it is dropped.
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Generates a new random key, using the random number generator of the operating system.
+    pub fn generate() -> EncryptionKey {
+        EncryptionKey(ChaCha20Poly1305::generate_key(&mut OsRng).into())
+    }
+
+    /// Creates a key from its bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> EncryptionKey {
+        EncryptionKey(bytes)
+    }
+
+    /// Returns the bytes of this key, to store it somewhere safe.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn cipher(&self) -> Arc<ChaCha20Poly1305> {
+        Arc::new(ChaCha20Poly1305::new(&self.0.into()))
+    }
+}
+
+impl Drop for EncryptionKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Sets the key used to encrypt and decrypt `db`. This must be done right after opening the database,
+/// before registering or using any entity.
+///
+/// The first time a key is set for a database, every value already saved in it is encrypted,
+/// including the values of stores whose entity has not been registered yet.
+/// Afterwards, the key is checked against the one the database has been encrypted with, and an
+/// [`ErrorKind::AuthenticationError`](../enum.ErrorKind.html) is returned if it does not match.
+///
+/// The key is kept in memory until [`forget_key`](fn.forget_key.html) is called, or until the database is closed.
+pub fn set_key(db: &Db, key: &EncryptionKey) -> Result<()> {
+    let aead = key.cipher();
+    match db.open_tree(ENCRYPTION_TREE)?.get(CHECK_KEY)? {
+        Some(check) => {
+            if open(&aead, ENCRYPTION_TREE, CHECK_KEY.as_bytes(), &check)? != CHECK_VALUE {
+                return Err(authentication_error());
+            }
+            *state::of(db)?.key.write().unwrap_or_else(|e| e.into_inner()) = Some(aead);
+            Ok(())
+        }
+        None => reencrypt(db, aead, false),
+    }
+}
+
+/// Removes the key of `db` from memory. Encrypted values of `db` can no longer be read
+/// until the key is set again.
+pub fn forget_key(db: &Db) {
+    if let Some(state) = state::get(db) {
+        *state.key.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+/// Re-encrypts every value of `db` with `new_key`, and uses it from now on.
+/// The current key must have been set with [`set_key`](fn.set_key.html).
+///
+/// Every value is rewritten in a single transaction. Writes to the database wait for the rotation to complete,
+/// so that no value can be written with the former key once the others have been re-encrypted.
+pub fn rotate_key(db: &Db, new_key: &EncryptionKey) -> Result<()> {
+    reencrypt(db, new_key.cipher(), true)
+}
+
+/// Rewrites every encrypted tree of `db` with `aead`, along with the key check value,
+/// then sets `aead` as the key of `db`. When `rotate` is set, a key must already be set.
+///
+/// Writes to `db` are blocked meanwhile : they read the key once they are allowed to write.
+fn reencrypt(db: &Db, aead: Arc<ChaCha20Poly1305>, rotate: bool) -> Result<()> {
+    let state = state::of(db)?;
+    let _writes = state.commit();
+    let current = Cipher::current(&state);
+    if rotate && !current.is_set() {
+        return Err(Error::new(
+            ErrorKind::AuthenticationError,
+            String::from("No encryption key is set for this database"),
+        ));
+    }
+    loop {
+        let check = db.open_tree(ENCRYPTION_TREE)?;
+        let mut entries = vec![vec![(
+            IVec::from(CHECK_KEY),
+            check.get(CHECK_KEY)?,
+            seal(&aead, ENCRYPTION_TREE, CHECK_KEY.as_bytes(), CHECK_VALUE)?,
+        )]];
+        let mut trees = vec![check];
+        for name in encrypted_tree_names(db) {
+            let tree = db.open_tree(&name)?;
+            let mut tree_entries = Vec::new();
+            for elem in tree.iter() {
+                let (key, value) = elem?;
+                let sealed = seal(&aead, &name, &key, &current.open(&name, &key, value.clone())?)?;
+                tree_entries.push((key, Some(value), sealed));
+            }
+            trees.push(tree);
+            entries.push(tree_entries);
+        }
+        // Values changed since they were read are not overwritten : the rotation is retried instead
+        let res: std::result::Result<(), TransactionError<()>> =
+            trees.as_slice().transaction(|views| {
+                for (view, entries) in views.iter().zip(&entries) {
+                    for (key, previous, sealed) in entries {
+                        if view.get(key)? != *previous {
+                            return Err(ConflictableTransactionError::Abort(()));
+                        }
+                        view.insert(key, sealed)?;
+                    }
+                }
+                Ok(())
+            });
+        match res {
+            Ok(()) => {
+                *state.key.write().unwrap_or_else(|e| e.into_inner()) = Some(aead);
+                return Ok(());
+            }
+            Err(TransactionError::Abort(())) => continue,
+            Err(TransactionError::Storage(e)) => return Err(e.into()),
+        }
+    }
+}
+
+/// Returns the names of the trees whose values are encrypted : every entity store, whether its entity has been
+/// registered or not, the family descriptors, and the relations, including those written by previous versions.
+fn encrypted_tree_names(db: &Db) -> Vec<String> {
+    let relations = Relation::tree_name("");
+    db.tree_names()
+        .into_iter()
+        .map(|name| String::from_utf8_lossy(&name).into_owned())
+        .filter(|name| {
+            !(name.starts_with(INTERNAL_STORE_PREFIX) || name.starts_with("__sled__"))
+                || name == FamilyDescriptor::store_name()
+                || name.starts_with(&relations)
+        })
+        .chain(edge::legacy_tree_names(db))
+        .collect()
+}
+
+/// Encrypts `value`, stored for `key` in `tree`, with a random nonce : the encrypted value is the header byte,
+/// the nonce and the ciphertext. The tree and the key are authenticated along with the value.
+pub(crate) fn seal(aead: &ChaCha20Poly1305, tree: &str, key: &[u8], value: &[u8]) -> Result<IVec> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let aad = associated_data(tree, key);
+    let payload = Payload { msg: value, aad: &aad };
+    let ciphertext = aead.encrypt(&nonce, payload).map_err(|_| {
+        Error::new(
+            ErrorKind::SerializationError,
+            String::from("Could not encrypt value"),
+        )
+    })?;
+    Ok(IVec::from([&[ENCRYPTED_TAG], nonce.as_slice(), &ciphertext].concat()))
+}
+
+/// Decrypts and authenticates a value encrypted by [`seal`](fn.seal.html) for `key` in `tree`.
+pub(crate) fn open(aead: &ChaCha20Poly1305, tree: &str, key: &[u8], value: &[u8]) -> Result<IVec> {
+    match value.split_first() {
+        Some((&ENCRYPTED_TAG, data)) if data.len() >= NONCE_SIZE => {
+            let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+            let aad = associated_data(tree, key);
+            let payload = Payload { msg: ciphertext, aad: &aad };
+            aead.decrypt(Nonce::from_slice(nonce), payload)
+                .map(IVec::from)
+                .map_err(|_| authentication_error())
+        }
+        _ => Err(authentication_error()),
+    }
+}
+
+/// The data authenticated along with a value : the name of its tree, prefixed with its length, and its key.
+fn associated_data(tree: &str, key: &[u8]) -> Vec<u8> {
+    [&(tree.len() as u64).to_be_bytes(), tree.as_bytes(), key].concat()
+}
+
+fn authentication_error() -> Error {
+    Error::new(
+        ErrorKind::AuthenticationError,
+        String::from("Could not authenticate encrypted value : the key is wrong, or the value has been tampered with"),
+    )
+}
@@ -2,14 +2,28 @@
 //! This module provides the `Entity` trait as well as other utilities to manipulate entities and entity stores.
 //! For relation-related definitions, take a look a the [`relation` module](relation/index.html).
 
-use std::{fs::File, mem::size_of};
+use std::{fs::File, iter::Rev, mem::size_of};
 
+use crate::cipher::Cipher;
+use crate::codec::Codec;
+use crate::compression::{self, Compression, DEFAULT_COMPRESSION_THRESHOLD};
 use crate::error::Result;
 use crate::import_export::JsonWrapper;
-use crate::relation::{DeletionBehaviour, EntityRelations, FamilyDescriptor, Relation};
+use crate::index;
+use crate::iter::EntityIter;
+use crate::key::{AsBytes, FromBytes};
+use crate::layout;
+use crate::migration;
+use crate::registry;
+use crate::state;
+use crate::page::{self, Cursor, Page};
+use crate::relation::{edge, DeletionBehaviour, EntityRelations, FamilyDescriptor, Relation};
+use crate::storage::Storage;
+use crate::subscription::Subscription;
+use crate::transaction::transaction;
 use crate::Error;
 use serde::{de::DeserializeOwned, Serialize};
-use sled::{Batch, Db, IVec, Tree};
+use sled::{Db, IVec, Tree};
 
 /// The `Entity` trait provides document store capabilities for any struct that implements it.
 ///
@@ -34,13 +48,30 @@ use sled::{Batch, Db, IVec, Tree};
 /// }
 /// ```
 ///
-/// ```rust
+/// ```rust,no_run
+/// # use reindeer::{Entity, Serialize, Deserialize, open};
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "my-struct", id = "key")]
+/// # struct MyStruct { pub key : u32, pub prop1 : String }
+/// # fn main() -> reindeer::Result<()> {
 /// let db = open("./my-db")?;
 /// let my_struct = MyStruct { key : 2 , prop1 : String::from("Hello, World!")};
 /// my_struct.save(&db)?;
+/// # Ok(())
+/// # }
 /// ```
 /// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "my-struct", id = "key")]
+/// # struct MyStruct { pub key : u32, pub prop1 : String }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # MyStruct::register(&db)?;
+/// # MyStruct { key : 2 , prop1 : String::from("Hello, World!")}.save(&db)?;
 /// let my_struct_0 = MyStruct::get(&2,&db)?;
+/// # Ok(())
+/// # }
 /// ```
 ///
 /// More information on how to use the trait is provided below.
@@ -48,14 +79,17 @@ use sled::{Batch, Db, IVec, Tree};
 pub trait Entity: Serialize + DeserializeOwned {
     /// The type of the Key for this document store.
     ///
-    /// It needs to implement the [`AsBytes`](entity/trait.AsBytes.html) and the Clone traits
+    /// It needs to implement the [`AsBytes`](trait.AsBytes.html), [`FromBytes`](trait.FromBytes.html) and the Clone traits
     /// which are already implemented for
-    ///  - `String`
-    ///  - `u32`
-    ///  - `u64`
-    ///  - `i32`
-    ///  - `i64`
-    type Key: AsBytes + Clone;
+    ///  - `String` and `Vec<u8>`
+    ///  - `u8`, `u16`, `u32`, `u64`, `u128`
+    ///  - `i8`, `i16`, `i32`, `i64`, `i128`
+    ///  - `bool`, `char` and `[u8; 16]` (UUIDs)
+    ///  - tuples of up to 6 of those types
+    ///
+    /// Keys are encoded so that entities are sorted by key, and so that the key of a parent is only
+    /// a prefix of the keys of its own children.
+    type Key: AsBytes + FromBytes + Clone;
 
     /// The name of the store, as a string.
     /// It represents a keyspace in the database. It needs to be unique for the struct that implements it.
@@ -63,7 +97,13 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// A recommendation is to return the name of the struct in `snake_case`.
     /// ### Example
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
     /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
     ///     fn store_name() -> &'static str {
     ///         "my_struct"
     ///     }
@@ -75,10 +115,16 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
     /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
     ///     fn get_key(&self) -> &Self::Key {
     ///         &self.key
     ///     }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
     /// }
     /// ```
     fn get_key(&self) -> &Self::Key;
@@ -91,7 +137,13 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
     /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
     ///     fn set_key(&mut self, key : &Self::Key) {
     ///         self.key = key.clone();
     ///     }
@@ -111,7 +163,14 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
     /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
     ///     fn get_sibling_stores() -> Vec<(&'static str, DeletionBehaviour)> {
     ///         vec![
     ///             ("sibling_struct_1",DeletionBehaviour::Cascade),
@@ -138,7 +197,14 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
     /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
     ///     fn get_child_stores() -> Vec<(&'static str, DeletionBehaviour)> {
     ///         vec![
     ///             ("child_struct",DeletionBehaviour::Cascade),
@@ -150,49 +216,324 @@ pub trait Entity: Serialize + DeserializeOwned {
         Vec::new()
     }
 
+    /// A function that returns the list of free relations this entity accepts : the related store,
+    /// the relation name, and the [`DeletionBehaviour`](relation/enum.DeletionBehaviour.html) in both ways
+    /// (what happens to the related entity if this one is removed, then what happens to this one if the related entity is removed).
+    ///
+    /// As soon as one of two entities declares free relations, creating a relation between them that neither
+    /// of them declares, or with other deletion behaviours than declared, fails with an
+    /// [`ErrorKind::UndeclaredRelation`](enum.ErrorKind.html) error.
+    /// Entities that declare no free relation accept any relation, as long as the other entity accepts it too.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct Student { key : u32 }
+    /// impl Entity for Student {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "student" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
+    ///     fn get_free_relations() -> Vec<(&'static str, &'static str, DeletionBehaviour, DeletionBehaviour)> {
+    ///         vec![
+    ///             ("club", "member", DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink),
+    ///         ]
+    ///     }
+    /// }
+    /// ```
+    fn get_free_relations() -> Vec<(&'static str, &'static str, DeletionBehaviour, DeletionBehaviour)> {
+        Vec::new()
+    }
+
+    #[doc(hidden)]
+    fn check_declared_relation<E: Entity>(name: &str) -> Result<()> {
+        if Self::get_free_relations()
+            .iter()
+            .any(|(store, n, _, _)| *store == E::store_name() && *n == name)
+        {
+            Ok(())
+        } else {
+            Err(Relation::undeclared_error::<Self, E>(Some(name)))
+        }
+    }
+
+    /// A function that returns the list of indexed fields, along with a boolean
+    /// telling if each index is unique.
+    ///
+    /// Override it along with [`get_index_value`](entity/trait.Entity.html#method.get_index_value)
+    /// to create secondary indexes, and look entities up with [`get_by_index`](entity/trait.Entity.html#method.get_by_index)
+    /// and [`find_by_index`](entity/trait.Entity.html#method.find_by_index) without iterating over the whole store.
+    ///
+    /// With the derive macro, use the `#[index]` or `#[index(unique)]` helper attributes on fields instead.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
+    /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
+    ///     fn get_indexes() -> Vec<(&'static str, bool)> {
+    ///         vec![("email", true), ("city", false)]
+    ///     }
+    /// }
+    /// ```
+    fn get_indexes() -> Vec<(&'static str, bool)> {
+        Vec::new()
+    }
+
+    /// A function that returns the binary representation of the value of an indexed field for this instance,
+    /// or `None` if this field is not indexed, or if this instance must not be indexed for it
+    /// (the derive macro does so for `Option` fields set to `None`).
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, AsBytes};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32, email : String, city : String }
+    /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
+    ///     fn get_index_value(&self, field: &str) -> Option<Vec<u8>> {
+    ///         match field {
+    ///             "email" => Some(AsBytes::as_bytes(&self.email)),
+    ///             "city" => Some(AsBytes::as_bytes(&self.city)),
+    ///             _ => None,
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn get_index_value(&self, _field: &str) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// The version of this entity's schema. It defaults to 0.
+    ///
+    /// Override it (or use `#[entity(version = N)]` with the derive macro) when the structure
+    /// of the entity changes, and provide a [`migrate`](entity/trait.Entity.html#method.migrate) implementation
+    /// to convert records saved with older versions.
+    fn version() -> u32 {
+        0
+    }
+
+    /// Converts a record saved with an older version of this entity into the current one.
+    ///
+    /// This is called by [`register`](entity/trait.Entity.html#method.register) for every record
+    /// of the store when the version recorded in the database is lower than [`version`](entity/trait.Entity.html#method.version).
+    /// The default implementation fails, as no migration path is known.
+    ///
+    /// With the derive macro, list the previous versions of the struct with the `migrations` helper attribute
+    /// and implement `From` between each consecutive version to generate this function.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, Error, ErrorKind, Result};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
+    /// # #[derive(Deserialize)]
+    /// # struct MyStructV0 { key : u32 }
+    /// # impl From<MyStructV0> for MyStruct {
+    /// #     fn from(v : MyStructV0) -> Self { MyStruct { key : v.key } }
+    /// # }
+    /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
+    ///     fn version() -> u32 {
+    ///         1
+    ///     }
+    ///     fn migrate(from_version: u32, data: &[u8]) -> Result<Self> {
+    ///         match from_version {
+    ///             0 => Ok(Self::from(Self::deserialize_legacy::<MyStructV0>(data)?)),
+    ///             _ => Err(Error::new(ErrorKind::MigrationError, String::from("Unknown version"))),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    fn migrate(from_version: u32, _data: &[u8]) -> Result<Self> {
+        Err(Error::new(
+            crate::ErrorKind::MigrationError,
+            format!(
+                "No migration from version {} to version {} for store {}",
+                from_version,
+                Self::version(),
+                Self::store_name()
+            ),
+        ))
+    }
+
+    /// Deserializes a record saved with an older version of this entity, as the type `T`
+    /// representing that version. Use this in [`migrate`](entity/trait.Entity.html#method.migrate).
+    ///
+    /// The record is read with the codec it was saved with.
+    fn deserialize_legacy<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        Codec::decode::<T>(data)
+    }
+
+    /// The serialization format used to save the entities of this store. It defaults to [`Codec::Bincode`](enum.Codec.html).
+    ///
+    /// Override it (or use `#[entity(codec = "json")]` with the derive macro) to use a self-describing format
+    /// for an evolving struct, or a more compact one. Entities saved with another codec can still be read,
+    /// so the codec of an existing store can be changed at any time.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, Codec};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
+    /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
+    ///     fn codec() -> Codec {
+    ///         Codec::Json
+    ///     }
+    /// }
+    /// ```
+    fn codec() -> Codec {
+        Codec::Bincode
+    }
+
+    /// The compression algorithm applied to the serialized entities of this store, if any. It defaults to `None`.
+    ///
+    /// Override it (or use `#[entity(compress = "zstd")]` with the derive macro) for stores of large, document-like entities.
+    /// Only values of at least [`compression_threshold`](entity/trait.Entity.html#method.compression_threshold) bytes
+    /// are compressed. Compressed and uncompressed values can coexist in a store : use
+    /// [`compact_store`](entity/trait.Entity.html#method.compact_store) to apply a new setting to existing values.
+    fn compression() -> Option<Compression> {
+        None
+    }
+
+    /// The size, in bytes, from which serialized entities are compressed. It defaults to
+    /// [`DEFAULT_COMPRESSION_THRESHOLD`](constant.DEFAULT_COMPRESSION_THRESHOLD.html).
+    fn compression_threshold() -> usize {
+        DEFAULT_COMPRESSION_THRESHOLD
+    }
+
+    /// Rewrites every value of this store according to the current [`compression`](entity/trait.Entity.html#method.compression)
+    /// settings : values are compressed, recompressed with another algorithm, or decompressed.
+    /// Returns the number of rewritten values.
+    ///
+    /// Values are not deserialized, and values changed concurrently are left untouched.
+    fn compact_store(db: &Db) -> Result<usize> {
+        let tree = Self::get_tree(db)?;
+        let state = state::of(db)?;
+        let mut rewritten = 0;
+        for key in tree.iter().keys() {
+            let key = key?;
+            // Each value is read and rewritten with the current key, which cannot be rotated in between
+            let _write = state.write();
+            let cipher = Cipher::current(&state);
+            let value = match tree.get(&key)? {
+                Some(value) => value,
+                None => continue,
+            };
+            let stored = cipher.open(Self::store_name(), &key, value.clone())?;
+            let raw = IVec::from(compression::decompress(&stored)?.as_ref());
+            let compacted = match Self::compression() {
+                Some(compression) => compression.compress(raw, Self::compression_threshold())?,
+                None => raw,
+            };
+            if compacted != stored
+                && tree
+                    .compare_and_swap(&key, Some(value), Some(cipher.seal(Self::store_name(), &key, compacted)?))?
+                    .is_ok()
+            {
+                rewritten += 1;
+            }
+        }
+        Ok(rewritten)
+    }
+
     /// Call this function once the database is opened on each Entity that you want to use.
     /// This is necessary to provide safe and type-agnostic deletion mechanisms.
     ///
+    /// If the store was saved with an older [`version`](entity/trait.Entity.html#method.version)
+    /// of the entity, every record is migrated to the current version.
+    ///
+    /// If the store was written by a previous version of `reindeer`, before keys were encoded in order,
+    /// its keys are encoded again first, along with the keys of its entities in the relations of other entities.
+    /// Until then, the store cannot be read or written.
+    ///
+    /// In [schema validation mode](fn.set_schema_validation.html), registering an entity whose children or siblings
+    /// make the schema inconsistent fails with an [`ErrorKind::IntegrityError`](enum.ErrorKind.html), and nothing is written.
+    ///
     /// ⚠ If this function is not called, deleting an entity of that type will result in an error.
     ///
     /// ### Example
     ///
     /// ```rust
-    /// impl Entity for MyStruct { /* ... */}
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct MyStruct { key : u32 }
+    /// impl Entity for MyStruct {
+    /// #     type Key = u32;
+    /// #     fn store_name() -> &'static str { "my_struct" }
+    /// #     fn get_key(&self) -> &Self::Key { &self.key }
+    /// #     fn set_key(&mut self, key : &Self::Key) { self.key = key.clone(); }
+    ///     /* ... */
+    /// }
     /// ```
     ///
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
     /// MyStruct::register(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn register(db: &Db) -> Result<()> {
-        let desc = FamilyDescriptor {
-            tree_name: String::from(Self::store_name()),
-            child_trees: Self::get_child_stores()
-                .iter()
-                .map(|e| (String::from(e.0), e.1))
-                .collect(),
-            sibling_trees: Self::get_sibling_stores()
-                .iter()
-                .map(|e| (String::from(e.0), e.1))
-                .collect(),
-        };
+        let desc = FamilyDescriptor::of::<Self>();
+        if registry::validates_schema(db) {
+            registry::check_schema(std::slice::from_ref(&desc), true, db)?;
+        }
+        layout::upgrade::<FamilyDescriptor>(db)?;
+        layout::upgrade::<Self>(db)?;
+        edge::migrate_legacy_trees(db)?;
         desc.save(db)?;
+        registry::families_changed();
+        migration::migrate_store::<Self>(db)?;
         Ok(())
     }
 
+    /// Drops every index of this store and indexes all of its entities again.
+    ///
+    /// Call this once after adding an index to an entity that already has data in the database.
+    /// This is done automatically when a store is migrated to a new version.
+    fn rebuild_indexes(db: &Db) -> Result<()> {
+        index::rebuild::<Self>(db)
+    }
+
     #[doc(hidden)]
     fn get_tree(db: &Db) -> Result<Tree> {
+        layout::ensure_current(Self::store_name(), db)?;
         Ok(db.open_tree(Self::store_name())?)
     }
 
     #[doc(hidden)]
     fn try_from_ivec(vec: IVec) -> Result<Self> {
-        Ok(bincode::deserialize::<Self>(vec.as_ref())?)
+        Codec::decode::<Self>(vec.as_ref())
     }
 
     #[doc(hidden)]
     fn try_into_ivec(&self) -> Result<IVec> {
-        Ok(IVec::from(bincode::serialize(self)?))
+        let value = Self::codec().encode(self)?;
+        match Self::compression() {
+            Some(compression) => compression.compress(value, Self::compression_threshold()),
+            None => Ok(value),
+        }
     }
 
     /// Retrieves an entity instance given its key.
@@ -202,9 +543,21 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// ### Example
     ///
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
     /// if let Some(my_struct_4) = MyStruct::get(&4,&db)? {
     ///     /* ... */
     /// }
+    /// # Ok(())
+    /// # }
     /// ```
     fn get(key: &Self::Key, db: &Db) -> Result<Option<Self>> {
         Self::get_from_u8_array(&key.as_bytes(), db)
@@ -219,39 +572,234 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// ### Example
     ///
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
     /// let entities = MyStruct::get_all(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_all(db: &Db) -> Result<Vec<Self>> {
-        Self::get_tree(db)?
-            .iter()
-            .map(|elem| -> Result<Self> { Self::try_from_ivec(elem?.1) })
-            .collect()
+        Self::iter(db)?.collect()
+    }
+
+    /// Returns a lazy iterator over all entities of a given type, in key order.
+    ///
+    /// Entities are only read and deserialized when the iterator is advanced, and each item
+    /// is a `Result`. The iterator is double-ended, so it can be reversed with `rev()`.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// for entity in MyStruct::iter(&db)? {
+    ///     let entity = entity?;
+    ///     /* ... */
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn iter(db: &Db) -> Result<EntityIter<Self>> {
+        Ok(EntityIter::new(Self::get_tree(db)?.iter(), Cipher::of(db)?))
+    }
+
+    /// Returns a lazy iterator over all entities of a given type, in reverse key order.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, Result};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let last_ten = MyStruct::iter_rev(&db)?.take(10).collect::<Result<Vec<MyStruct>>>()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn iter_rev(db: &Db) -> Result<Rev<EntityIter<Self>>> {
+        Ok(Self::iter(db)?.rev())
+    }
+
+    /// Returns a lazy iterator over the entities whose key starts with `prefix`, in key order.
+    /// This is mostly useful to iterate over the children of a parent entity.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "parent_struct")]
+    /// # #[children(ChildStruct = Cascade)]
+    /// # struct ParentStruct { id : String }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "child_struct")]
+    /// # struct ChildStruct { id : (String, u32) }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # ParentStruct::register(&db)?;
+    /// # ChildStruct::register(&db)?;
+    /// # let parent = ParentStruct { id : String::from("parent") };
+    /// # parent.save(&db)?;
+    /// # for i in 0..40 {
+    /// #     parent.save_child(&mut ChildStruct { id : (String::new(), i) }, &db)?;
+    /// # }
+    /// for child in ChildStruct::iter_prefix(parent.get_key(), &db)? {
+    ///     /* ... */
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn iter_prefix(prefix: &impl AsBytes, db: &Db) -> Result<EntityIter<Self>> {
+        Ok(EntityIter::new(
+            Self::get_tree(db)?.scan_prefix(prefix.as_bytes()),
+            Cipher::of(db)?,
+        ))
+    }
+
+    /// Returns a lazy iterator over the entities with keys from `start` (included) to `end` (excluded), in key order.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let count = MyStruct::iter_range(10u32, 30u32, &db)?.count();
+    /// # assert_eq!(count, 20);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn iter_range(start: impl AsBytes, end: impl AsBytes, db: &Db) -> Result<EntityIter<Self>> {
+        Ok(EntityIter::new(
+            Self::get_tree(db)?.range(start.as_bytes()..end.as_bytes()),
+            Cipher::of(db)?,
+        ))
     }
 
     /// Returns the number of saved instances for this entity type.
     ///
     /// ### Example
     /// ```rust
-    /// let count = MyStruct::get_count()?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let count = MyStruct::get_count(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_count(db: &Db) -> Result<usize> {
         Ok(Self::get_tree(db)?.len())
     }
 
+    /// Returns the keys of all entities of a given type, in order, without deserializing the entities.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let keys : Vec<u32> = MyStruct::get_keys(&db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_keys(db: &Db) -> Result<Vec<Self::Key>> {
+        Self::get_tree(db)?
+            .iter()
+            .keys()
+            .map(|key| -> Result<Self::Key> { Self::Key::from_bytes(&key?) })
+            .collect()
+    }
+
+    /// Returns the keys of the entities whose key starts with `prefix`, in order, without deserializing the entities.
+    /// This is mostly useful to list the keys of the children of a parent entity.
+    ///
+    /// ### Example
+    ///
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "parent_struct")]
+    /// # #[children(ChildStruct = Cascade)]
+    /// # struct ParentStruct { id : String }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "child_struct")]
+    /// # struct ChildStruct { id : (String, u32) }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # ParentStruct::register(&db)?;
+    /// # ChildStruct::register(&db)?;
+    /// # let parent = ParentStruct { id : String::from("parent") };
+    /// # parent.save(&db)?;
+    /// # for i in 0..40 {
+    /// #     parent.save_child(&mut ChildStruct { id : (String::new(), i) }, &db)?;
+    /// # }
+    /// let keys : Vec<(String, u32)> = ChildStruct::get_keys_with_prefix(parent.get_key(), &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_keys_with_prefix(prefix: &impl AsBytes, db: &Db) -> Result<Vec<Self::Key>> {
+        Self::get_tree(db)?
+            .scan_prefix(prefix.as_bytes())
+            .keys()
+            .map(|key| -> Result<Self::Key> { Self::Key::from_bytes(&key?) })
+            .collect()
+    }
+
     #[doc(hidden)]
     fn get_from_u8_array(key: &[u8], db: &Db) -> Result<Option<Self>> {
         Self::get_tree(db)?
             .get(key)?
-            .map(|vec| -> Result<Self> { Self::try_from_ivec(vec) })
+            .map(|vec| -> Result<Self> { Self::try_from_ivec(Cipher::of(db)?.open(Self::store_name(), key, vec)?) })
             .transpose()
     }
 
     #[doc(hidden)]
     fn get_with_prefix(key: &impl AsBytes, db: &Db) -> Result<Vec<Self>> {
-        Self::get_tree(db)?
-            .scan_prefix(key.as_bytes())
-            .map(|elem| -> Result<Self> { Self::try_from_ivec(elem?.1) })
-            .collect()
+        Self::iter_prefix(key, db)?.collect()
     }
 
     /// Gets entities in a range of keys with a min and max values
@@ -260,20 +808,41 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
-    /// let entities = MyStruct::get_in_range(10,30,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let entities = MyStruct::get_in_range(10u32,30u32,&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_in_range(start: impl AsBytes, end: impl AsBytes, db: &Db) -> Result<Vec<Self>> {
-        Self::get_tree(db)?
-            .range(start.as_bytes()..end.as_bytes())
-            .map(|elem| -> Result<Self> { Self::try_from_ivec(elem?.1) })
-            .collect()
+        Self::iter_range(start, end, db)?.collect()
     }
 
     /// Gets `count` entities starting at the instance at index `start` in the given store
     ///
     /// ### Example
     /// ```rust
-    /// let entities = MyStruct::get_from_start(10,20,None,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let entities = MyStruct::get_from_start(10,20,None::<u32>,&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     /// ## Child entities
     ///
@@ -281,7 +850,26 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
-    /// let entities = MyStruct::get_from_start(10,20,Some(parent.get_key().to_owned()),&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "parent_struct")]
+    /// # #[children(ChildStruct = Cascade)]
+    /// # struct ParentStruct { id : String }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "child_struct")]
+    /// # struct ChildStruct { id : (String, u32) }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # ParentStruct::register(&db)?;
+    /// # ChildStruct::register(&db)?;
+    /// # let parent = ParentStruct { id : String::from("parent") };
+    /// # parent.save(&db)?;
+    /// # for i in 0..40 {
+    /// #     parent.save_child(&mut ChildStruct { id : (String::new(), i) }, &db)?;
+    /// # }
+    /// let entities = ChildStruct::get_from_start(10,20,Some(parent.get_key().to_owned()),&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_from_start(
         start: usize,
@@ -289,23 +877,11 @@ pub trait Entity: Serialize + DeserializeOwned {
         parent: Option<impl AsBytes>,
         db: &Db,
     ) -> Result<Vec<Self>> {
-        let mut iter = if let Some(prefix) = parent {
-            Self::get_tree(db)?.scan_prefix(prefix.as_bytes())
-        } else {
-            Self::get_tree(db)?.iter()
+        let iter = match parent {
+            Some(prefix) => Self::iter_prefix(&prefix, db)?,
+            None => Self::iter(db)?,
         };
-        let mut result = Vec::new();
-        for i in 0..(start + count) {
-            match iter.next() {
-                Some(e) => {
-                    if i >= start {
-                        result.push(Self::try_from_ivec(e?.1)?);
-                    }
-                }
-                None => return Ok(result),
-            }
-        }
-        Ok(result)
+        iter.skip(start).take(count).collect()
     }
 
     /// Gets `count` entities starting at the instance at index `start` from the end of a given store
@@ -313,7 +889,19 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
-    /// let entities = MyStruct::get_from_end(10,20,None,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let entities = MyStruct::get_from_end(10,20,None::<u32>,&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     /// ## Child entities
     ///
@@ -321,7 +909,26 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
-    /// let entities = MyStruct::get_from_end(10,20,Some(parent.get_key().to_owned()),&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "parent_struct")]
+    /// # #[children(ChildStruct = Cascade)]
+    /// # struct ParentStruct { id : String }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "child_struct")]
+    /// # struct ChildStruct { id : (String, u32) }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # ParentStruct::register(&db)?;
+    /// # ChildStruct::register(&db)?;
+    /// # let parent = ParentStruct { id : String::from("parent") };
+    /// # parent.save(&db)?;
+    /// # for i in 0..40 {
+    /// #     parent.save_child(&mut ChildStruct { id : (String::new(), i) }, &db)?;
+    /// # }
+    /// let entities = ChildStruct::get_from_end(10,20,Some(parent.get_key().to_owned()),&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_from_end(
         start: usize,
@@ -329,26 +936,179 @@ pub trait Entity: Serialize + DeserializeOwned {
         prefix: Option<impl AsBytes>,
         db: &Db,
     ) -> Result<Vec<Self>> {
-        let mut iter = if let Some(prefix) = prefix {
-            Self::get_tree(db)?.scan_prefix(prefix.as_bytes())
-        } else {
-            Self::get_tree(db)?.iter()
+        let iter = match prefix {
+            Some(prefix) => Self::iter_prefix(&prefix, db)?,
+            None => Self::iter(db)?,
         };
-        let mut result = Vec::new();
-        for i in 0..(start + offset) {
-            match iter.next_back() {
-                Some(e) => {
-                    if i >= start {
-                        result.push(Self::try_from_ivec(e?.1)?);
-                    }
-                }
-                None => break,
-            }
-        }
+        let mut result = iter
+            .rev()
+            .skip(start)
+            .take(offset)
+            .collect::<Result<Vec<Self>>>()?;
         result.reverse();
         Ok(result)
     }
 
+    /// Gets the `count` entities following `cursor` in key order, or the first `count` entities if `cursor` is `None`.
+    ///
+    /// Unlike [`get_from_start`](entity/trait.Entity.html#method.get_from_start), this only reads the entities of the page,
+    /// and pages do not shift when entities are inserted or removed.
+    /// The returned [`Page`](struct.Page.html) holds the cursors to use to get the next and previous pages.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let page = MyStruct::page_after(None, 20, None::<u32>, &db)?;
+    /// let next_page = MyStruct::page_after(page.next.as_ref(), 20, None::<u32>, &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    /// ## Child entities
+    ///
+    /// A parent key can be supplied for child entities, to consider only children of a given parent.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "parent_struct")]
+    /// # #[children(ChildStruct = Cascade)]
+    /// # struct ParentStruct { id : String }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "child_struct")]
+    /// # struct ChildStruct { id : (String, u32) }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # ParentStruct::register(&db)?;
+    /// # ChildStruct::register(&db)?;
+    /// # let parent = ParentStruct { id : String::from("parent") };
+    /// # parent.save(&db)?;
+    /// # for i in 0..40 {
+    /// #     parent.save_child(&mut ChildStruct { id : (String::new(), i) }, &db)?;
+    /// # }
+    /// # let cursor : Option<reindeer::Cursor> = None;
+    /// let page = ChildStruct::page_after(cursor.as_ref(), 20, Some(parent.get_key().to_owned()), &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn page_after(
+        cursor: Option<&Cursor>,
+        count: usize,
+        parent: Option<impl AsBytes>,
+        db: &Db,
+    ) -> Result<Page<Self>> {
+        let prefix = parent.map(|p| p.as_bytes()).unwrap_or_default();
+        page::page_after(&Self::get_tree(db)?, &Cipher::of(db)?, &prefix, cursor, count)
+    }
+
+    /// Gets the `count` entities preceding `cursor` in key order, or the last `count` entities if `cursor` is `None`.
+    /// Same as [`page_after`](entity/trait.Entity.html#method.page_after), but going backwards. Entities are still returned in key order.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// # let page = MyStruct::page_after(None, 20, None::<u32>, &db)?;
+    /// let previous_page = MyStruct::page_before(page.previous.as_ref(), 20, None::<u32>, &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn page_before(
+        cursor: Option<&Cursor>,
+        count: usize,
+        parent: Option<impl AsBytes>,
+        db: &Db,
+    ) -> Result<Page<Self>> {
+        let prefix = parent.map(|p| p.as_bytes()).unwrap_or_default();
+        page::page_before(&Self::get_tree(db)?, &Cipher::of(db)?, &prefix, cursor, count)
+    }
+
+    /// Subscribes to the changes made to this store.
+    /// The returned [`Subscription`](struct.Subscription.html) yields an [`Event`](enum.Event.html)
+    /// every time an entity is saved or removed, including when it is removed in cascade or adopted by another parent.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, Event};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let subscription = MyStruct::subscribe(&db)?;
+    /// std::thread::spawn(move || -> reindeer::Result<()> {
+    ///     for event in subscription {
+    ///         match event? {
+    ///             Event::Inserted(my_struct) => { /* ... */ }
+    ///             Event::Removed(key) => { /* ... */ }
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn subscribe(db: &Db) -> Result<Subscription<Self>> {
+        Ok(Subscription::new(
+            Self::get_tree(db)?.watch_prefix(vec![]),
+            Cipher::of(db)?,
+        ))
+    }
+
+    /// Subscribes to the changes made to the entities of this store whose key starts with `prefix`.
+    /// This is mostly useful to watch the children of a given parent.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "parent_struct")]
+    /// # #[children(ChildStruct = Cascade)]
+    /// # struct ParentStruct { id : String }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "child_struct")]
+    /// # struct ChildStruct { id : (String, u32) }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # ParentStruct::register(&db)?;
+    /// # ChildStruct::register(&db)?;
+    /// # let parent = ParentStruct { id : String::from("parent") };
+    /// # parent.save(&db)?;
+    /// # for i in 0..40 {
+    /// #     parent.save_child(&mut ChildStruct { id : (String::new(), i) }, &db)?;
+    /// # }
+    /// let subscription = ChildStruct::subscribe_prefix(parent.get_key(), &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn subscribe_prefix(prefix: &impl AsBytes, db: &Db) -> Result<Subscription<Self>> {
+        Ok(Subscription::new(
+            Self::get_tree(db)?.watch_prefix(prefix.as_bytes()),
+            Cipher::of(db)?,
+        ))
+    }
+
     /// Gets all entities of a given store matching a condition materialized
     /// as a function returning a boolean
     ///
@@ -358,58 +1118,145 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
-    /// let entities = MyStruct::get_with_filter(|m_struct| m_struct.prop > 20,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let entities = MyStruct::get_with_filter(|m_struct| m_struct.prop2 > 20,&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_with_filter<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<Vec<Self>> {
-        Self::get_tree(db)?
-            .iter()
-            .map(|elem| -> Result<Self> { Self::try_from_ivec(elem?.1) })
-            .filter(|e| match e {
-                Ok(v) => f(v),
-                Err(_) => false,
-            })
+        Self::iter(db)?
+            .filter(|e| e.as_ref().map_or(true, &f))
             .collect()
     }
 
-    /// Gets several entites matching a collection of keys
+    /// Gets several entites matching a collection of keys, leaving out the missing ones.
     ///
     /// ⚠ This will call `get` as many times as the number of keys provided.
     ///
     /// ### Example
     /// ```rust
-    /// let entities = MyStruct::get_each(vec![4,8,9],&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let entities = MyStruct::get_each(&[4,8,9],&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
-    fn get_each(keys: &[Self::Key], db: &Db) -> Vec<Self> {
+    fn get_each(keys: &[Self::Key], db: &Db) -> Result<Vec<Self>> {
         keys.iter()
-            .map(|key| Self::get(key, db))
-            .filter_map(|res| match res {
-                Ok(opt) => opt,
-                Err(_) => None,
-            })
+            .filter_map(|key| Self::get(key, db).transpose())
             .collect()
     }
 
     #[doc(hidden)]
-    fn get_each_u8(keys: &[Vec<u8>], db: &Db) -> Vec<Self> {
+    fn get_each_u8(keys: &[Vec<u8>], db: &Db) -> Result<Vec<Self>> {
         keys.iter()
-            .map(|key| Self::get_from_u8_array(key, db))
-            .filter_map(|res| match res {
-                Ok(opt) => opt,
-                Err(_) => None,
-            })
+            .filter_map(|key| Self::get_from_u8_array(key, db).transpose())
             .collect()
     }
 
+    /// Gets the first entity whose indexed `field` has the given value.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "user")]
+    /// # struct User {
+    /// #     id : u32,
+    /// #     #[index(unique)]
+    /// #     email : String,
+    /// #     #[index]
+    /// #     city : String,
+    /// # }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # User::register(&db)?;
+    /// # User { id : 0, email : String::from("ada@example.com"), city : String::from("Paris") }.save(&db)?;
+    /// let user = User::get_by_index("email", &String::from("ada@example.com"), &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_by_index(field: &str, value: &impl AsBytes, db: &Db) -> Result<Option<Self>> {
+        match index::find_keys::<Self>(field, &value.as_bytes(), db)?.first() {
+            Some(key) => Self::get_from_u8_array(key, db),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets every entity whose indexed `field` has the given value.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "user")]
+    /// # struct User {
+    /// #     id : u32,
+    /// #     #[index(unique)]
+    /// #     email : String,
+    /// #     #[index]
+    /// #     city : String,
+    /// # }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # User::register(&db)?;
+    /// # User { id : 0, email : String::from("ada@example.com"), city : String::from("Paris") }.save(&db)?;
+    /// let users = User::find_by_index("city", &String::from("Paris"), &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn find_by_index(field: &str, value: &impl AsBytes, db: &Db) -> Result<Vec<Self>> {
+        Self::get_each_u8(&index::find_keys::<Self>(field, &value.as_bytes(), db)?, db)
+    }
+
     /// Saves an entity to the database, using its key provided by the`get_key` method.
     ///
+    /// If the entity has indexes, they are updated along with it.
+    /// ⚠ If a unique index value is already used by another entity, this will result in an error.
+    ///
     /// ### Example
     ///
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
     /// let my_struct = MyStruct { key : 0, prop1 : String::from("Hello"), prop2 : 554};
     /// my_struct.save(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn save(&self, db: &Db) -> Result<()> {
-        Self::get_tree(db)?.insert(&self.get_key().as_bytes(), bincode::serialize(self)?)?;
+        if !Self::get_indexes().is_empty() {
+            return transaction(db, |tx| tx.save(self));
+        }
+        registry::check_registered::<Self>(db, db)?;
+        let key = self.get_key().as_bytes();
+        let value = self.try_into_ivec()?;
+        let tree = Self::get_tree(db)?;
+        let state = state::of(db)?;
+        let _write = state.write();
+        tree.insert(&key, Cipher::current(&state).seal(Self::store_name(), &key, value)?)?;
         Ok(())
     }
 
@@ -418,21 +1265,50 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// ### Example
     /// This will get the `MyStruct` instance with key 3  and increment its `prop1` member
     /// ```rust
-    /// MyStruct::update(&3,|my_struct| my_struct.prop1++,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// MyStruct::update(&3,|my_struct| my_struct.prop2 += 1,&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn update<F: Fn(&mut Self)>(key: &Self::Key, f: F, db: &Db) -> Result<()> {
-        Self::get_tree(db)?.fetch_and_update(&key.as_bytes(), |e| {
-            Result::ok(
-                e.map(|u8_arr| -> Result<IVec> {
-                    let mut value = Self::try_from_ivec(IVec::from(u8_arr))?;
+        if !Self::get_indexes().is_empty() {
+            return transaction(db, |tx| tx.update(key, &f));
+        }
+        let key = key.as_bytes();
+        // An entity that cannot be read or written again is left unchanged, and the error returned
+        let mut error = None;
+        let tree = Self::get_tree(db)?;
+        let state = state::of(db)?;
+        let _write = state.write();
+        let cipher = Cipher::current(&state);
+        tree.fetch_and_update(&key, |stored| {
+            error = None;
+            let stored = stored?;
+            let updated = cipher
+                .open(Self::store_name(), &key, IVec::from(stored))
+                .and_then(Self::try_from_ivec)
+                .and_then(|mut value| {
                     f(&mut value);
-                    value.try_into_ivec()
-                })
-                .transpose(),
-            )
-            .flatten()
+                    cipher.seal(Self::store_name(), &key, value.try_into_ivec()?)
+                });
+            match updated {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    error = Some(e);
+                    Some(IVec::from(stored))
+                }
+            }
         })?;
-        Ok(())
+        error.map_or(Ok(()), Err)
     }
 
     /// Updates all entities that match a condition provided as a function
@@ -441,7 +1317,19 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// This will get all the `MyStruct` instances with prop1 greater than 100
     /// and change it to be 0 instead;
     /// ```rust
-    /// MyStruct::filter_update(|my_struct| mu_struct.prop1 > 100,|my_struct| {my_struct.prop1 = 0;},&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// MyStruct::filter_update(|my_struct| my_struct.prop2 > 100,|my_struct| {my_struct.prop2 = 0;},&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn filter_update<F: Fn(&Self) -> bool, M: Fn(&mut Self)>(
         filter: F,
@@ -467,24 +1355,15 @@ pub trait Entity: Serialize + DeserializeOwned {
     /// Use this to clean up side effects before removing it.
     /// If an entry cannot be removed (i.e. remaining constraints), this will not be called.
     ///
-    /// ⚠ Child, sibling and related entries will automatically be removed *before* this one.
+    /// ⚠ This is called right before the entry and its child, sibling and related entries are removed,
+    /// and is not called when the entry is removed inside a [`transaction`](fn.transaction.html).
     fn pre_remove_hook(&self, _db: &Db) -> Result<()> {
         Ok(())
     }
 
     #[doc(hidden)]
     fn pre_remove(key: &[u8], db: &Db) -> Result<()> {
-        let mut to_be_removed = EntityRelations::default();
-        Relation::can_be_deleted(Self::store_name(), key, &Vec::new(), &mut to_be_removed, db)?;
-        for (tree, keys) in &to_be_removed.related_entities {
-            let tree = db.open_tree(tree)?;
-            let mut batch = Batch::default();
-            keys.iter().for_each(|rd| batch.remove(rd.key.as_slice()));
-            tree.apply_batch(batch)?;
-        }
-        Relation::remove_entity_entry::<Self>(key, db)?;
-        Relation::remove_descriptor(Self::store_name(), key, db)?;
-        Ok(())
+        transaction(db, |tx| Relation::remove_related(Self::store_name(), key, tx))
     }
 
     #[doc(hidden)]
@@ -505,7 +1384,19 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
-    /// MyStruct::remove(&3, &db);
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// MyStruct::remove(&3, &db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn remove(key: &Self::Key, db: &Db) -> Result<()> {
         Self::remove_from_u8_array(&key.as_bytes(), db)
@@ -513,8 +1404,8 @@ pub trait Entity: Serialize + DeserializeOwned {
 
     #[doc(hidden)]
     fn remove_from_u8_array(key: &[u8], db: &Db) -> Result<()> {
-        Self::pre_remove(key, db)?;
         if Self::use_pre_remove_hook() {
+            Self::can_be_removed(key, db)?;
             Self::pre_remove_hook(
                 &Self::get_from_u8_array(key, db)?.ok_or(Error::new(
                     crate::ErrorKind::IntegrityError,
@@ -523,8 +1414,7 @@ pub trait Entity: Serialize + DeserializeOwned {
                 db,
             )?;
         }
-        Self::get_tree(db)?.remove(key)?;
-        Ok(())
+        transaction(db, |tx| tx.remove_from_u8_array(Self::store_name(), key))
     }
 
     #[doc(hidden)]
@@ -544,17 +1434,12 @@ pub trait Entity: Serialize + DeserializeOwned {
 
     #[doc(hidden)]
     fn remove_prefixed_in_tree(tree_name: &str, prefix: &[u8], db: &Db) -> Result<()> {
-        let tree = db.open_tree(tree_name)?;
-        let mut batch = Batch::default();
-        tree.scan_prefix(prefix).for_each(|elem| {
-            if let Ok((key, _)) = elem {
-                if Self::pre_remove(&key, db).is_ok() {
-                    batch.remove(key)
-                }
+        transaction(db, |tx| {
+            for (key, _) in Storage::scan_prefix(tx, tree_name, prefix)? {
+                tx.remove_from_u8_array(tree_name, &key)?;
             }
-        });
-        tree.apply_batch(batch)?;
-        Ok(())
+            Ok(())
+        })
     }
 
     /// Removes every entry of a store, given a condition in the form of a function returning a boolean
@@ -565,31 +1450,47 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Example
     /// ```rust
-    /// MyStruct::remove(&3, &db);
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
+    /// let removed = MyStruct::filter_remove(|my_struct| my_struct.prop2 > 100, &db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn filter_remove<F: Fn(&Self) -> bool>(f: F, db: &Db) -> Result<Vec<Self>> {
         let mut res = Self::get_with_filter(f, db)?;
-        let mut to_remove_from_result = Vec::new();
-        for (index, entity) in res.iter().enumerate() {
-            if Self::remove(entity.get_key(), db).is_err() {
-                to_remove_from_result.push(index)
-            };
-        }
-        for index in to_remove_from_result {
-            res.remove(index);
-        }
+        res.retain(|entity| Self::remove(entity.get_key(), db).is_ok());
         Ok(res)
     }
 
     /// Checks if an entity exists in a given store, without fetching it.
     /// ### Example
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// # for key in 0..40 {
+    /// #     MyStruct { key, prop1 : String::from("Hello"), prop2 : key * 10 }.save(&db)?;
+    /// # }
     /// if MyStruct::exists(&3, &db)? {
     ///     /* */
     /// }
+    /// # Ok(())
+    /// # }
     /// ```
     fn exists(key: &Self::Key, db: &Db) -> Result<bool> {
-        Ok(Self::get_tree(db)?.contains_key(&key.as_bytes())?)
+        Ok(Self::get_tree(db)?.contains_key(key.as_bytes())?)
     }
 
     /// Exports the entire store for this entity as a JSON file.
@@ -630,21 +1531,88 @@ pub trait Entity: Serialize + DeserializeOwned {
         name: Option<&str>,
         db: &Db,
     ) -> Result<()> {
-        Relation::create(self, other, self_to_other, other_to_self, name, db)
+        transaction(db, |tx| {
+            Relation::create(self, other, self_to_other, other_to_self, name, tx)
+        })
+    }
+
+    /// Creates a free relation between this entity and another one, carrying `data`.
+    ///
+    /// The data is stored on the link in both ways, and can be read from either entity with
+    /// [`get_related_with_data`](entity/trait.Entity.html#method.get_related_with_data).
+    /// Deletion behaviours and `name` work as in [`create_relation`](entity/trait.Entity.html#method.create_relation).
+    ///
+    /// ### Exemple
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "module")]
+    /// # struct Module { id : u32 }
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct Enrolment { grade : u32, role : String }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # Student::register(&db)?;
+    /// # Module::register(&db)?;
+    /// # let student = Student { id : 9 };
+    /// # student.save(&db)?;
+    /// # let module = Module { id : 1 };
+    /// # module.save(&db)?;
+    /// student.create_relation_with_data(
+    ///     &module,
+    ///     DeletionBehaviour::BreakLink,
+    ///     DeletionBehaviour::Cascade,
+    ///     Some("enrolment"),
+    ///     &Enrolment { grade: 15, role: String::from("student") },
+    ///     &db,
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn create_relation_with_data<E: Entity, D: Serialize>(
+        &self,
+        other: &E,
+        self_to_other: DeletionBehaviour,
+        other_to_self: DeletionBehaviour,
+        name: Option<&str>,
+        data: &D,
+        db: &Db,
+    ) -> Result<()> {
+        let data = bincode::serialize(data)?;
+        transaction(db, |tx| {
+            Relation::create_with_data(self, other, self_to_other, other_to_self, name, &data, tx)
+        })
+    }
+
+    /// Replaces the data carried by the relation named `name` between this entity and another one.
+    ///
+    /// An [`ErrorKind::NotFound`](enum.ErrorKind.html) error is returned if there is no such relation.
+    fn update_relation_data<E: Entity, D: Serialize>(
+        &self,
+        other: &E,
+        name: Option<&str>,
+        data: &D,
+        db: &Db,
+    ) -> Result<()> {
+        let data = bincode::serialize(data)?;
+        transaction(db, |tx| Relation::update_data(self, other, name, &data, tx))
     }
 
     /// Breaks an existing link between two entities.
     ///
     /// This will remove the relation in both ways.
     fn remove_relation<E: Entity>(&self, other: &E, db: &Db) -> Result<()> {
-        Relation::remove(self, other, db)
+        transaction(db, |tx| Relation::remove(self, other, tx))
     }
 
     /// Breaks an existing link between two entities with a specific name
     ///
     /// This will remove the relation in both ways.
     fn remove_relation_with_name<E: Entity>(&self, other: &E, name: &str, db: &Db) -> Result<()> {
-        Relation::remove_with_name(self, other, name, db)
+        transaction(db, |tx| Relation::remove_with_name(self, other, name, tx))
     }
 
     #[doc(hidden)]
@@ -656,8 +1624,25 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # let related = MyStruct2 { key : 3, prop9 : 32 };
+    /// # related.save(&db)?;
+    /// # MyStruct1 { key : 9 }.create_relation(&related, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
     /// let related_struct2s = m_struct_1.get_related::<MyStruct2>(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_related<E: Entity>(&self, db: &Db) -> Result<Vec<E>> {
         Relation::get::<Self, E>(self, db)
@@ -667,19 +1652,142 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # let related = MyStruct2 { key : 3, prop9 : 32 };
+    /// # related.save(&db)?;
+    /// # MyStruct1 { key : 9 }.create_relation(&related, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, Some("collection"), &db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
     /// let related_struct2s = m_struct_1.get_related_with_name::<MyStruct2>("collection",&db)?;
-    ///
+    /// # Ok(())
+    /// # }
+    /// ```
     fn get_related_with_name<E: Entity>(&self, name: &str, db: &Db) -> Result<Vec<E>> {
         Relation::get_with_name::<Self, E>(self, name, db)
     }
 
+    /// Gets all entities related to this one in another store along with the data carried by their relation.
+    /// Relations created without data are left out.
+    ///
+    /// ### Exemple
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "module")]
+    /// # struct Module { id : u32 }
+    /// # #[derive(Serialize, Deserialize)]
+    /// # struct Enrolment { grade : u32, role : String }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # Student::register(&db)?;
+    /// # Module::register(&db)?;
+    /// # let student = Student { id : 9 };
+    /// # student.save(&db)?;
+    /// # let module = Module { id : 1 };
+    /// # module.save(&db)?;
+    /// # student.create_relation_with_data(&module, DeletionBehaviour::BreakLink, DeletionBehaviour::Cascade, None, &Enrolment { grade : 15, role : String::from("student") }, &db)?;
+    /// let student = Student::get(&9,&db)?.unwrap();
+    /// let modules : Vec<(Module, Enrolment)> = student.get_related_with_data(&db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_related_with_data<E: Entity, D: DeserializeOwned>(&self, db: &Db) -> Result<Vec<(E, D)>> {
+        Relation::get_with_data::<Self, E, D>(self, db)
+    }
+
+    /// Gets the keys of all entities related to this one in another store, without fetching the entities.
+    ///
+    /// ### Exemple
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # let related = MyStruct2 { key : 3, prop9 : 32 };
+    /// # related.save(&db)?;
+    /// # MyStruct1 { key : 9 }.create_relation(&related, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let related_keys = m_struct_1.get_related_keys::<MyStruct2>(&db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_related_keys<E: Entity>(&self, db: &Db) -> Result<Vec<E::Key>> {
+        Relation::get_keys::<Self, E>(self, db)
+    }
+
+    /// Gets the keys of all the entities related to this one in another store with a given relation name,
+    /// without fetching the entities.
+    ///
+    /// ### Exemple
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # let related = MyStruct2 { key : 3, prop9 : 32 };
+    /// # related.save(&db)?;
+    /// # MyStruct1 { key : 9 }.create_relation(&related, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, Some("collection"), &db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let related_keys = m_struct_1.get_related_keys_with_name::<MyStruct2>("collection",&db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn get_related_keys_with_name<E: Entity>(&self, name: &str, db: &Db) -> Result<Vec<E::Key>> {
+        Relation::get_keys_with_name::<Self, E>(self, name, db)
+    }
+
     /// Gets the first entity related to this one in another store.
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # let related = MyStruct2 { key : 3, prop9 : 32 };
+    /// # related.save(&db)?;
+    /// # MyStruct1 { key : 9 }.create_relation(&related, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
     /// let m_struct_2 = m_struct_1.get_single_related::<MyStruct2>(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_single_related<E: Entity>(&self, db: &Db) -> Result<Option<E>> {
         Relation::get_one::<Self, E>(self, db)
@@ -689,8 +1797,25 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # let related = MyStruct2 { key : 3, prop9 : 32 };
+    /// # related.save(&db)?;
+    /// # MyStruct1 { key : 9 }.create_relation(&related, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, Some("main_book"), &db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
     /// let m_struct_2 = m_struct_1.get_single_related_with_name::<MyStruct2>("main_book",&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_single_related_with_name<E: Entity>(&self, name: &str, db: &Db) -> Result<Option<E>> {
         Relation::get_one_with_name::<Self, E>(self, name, db)
@@ -724,9 +1849,25 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let m_struct_2 = MyStruct2 { key : 0, prop9 : 32};
-    /// m_struct_1.save_sibling(m_struct_2,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # #[siblings(MyStruct2 = Cascade)]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # #[siblings(MyStruct1 = Error)]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let mut m_struct_2 = MyStruct2 { key : 0, prop9 : 32};
+    /// m_struct_1.save_sibling(&mut m_struct_2,&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn save_sibling<E: Entity<Key = Self::Key>>(&self, sibling: &mut E, db: &Db) -> Result<()> {
         sibling.set_key(self.get_key());
@@ -737,8 +1878,25 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # #[siblings(MyStruct2 = Cascade)]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # #[siblings(MyStruct1 = Error)]
+    /// # struct MyStruct2 { key : u32, prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # MyStruct2 { key : 9, prop9 : 32 }.save(&db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
     /// let m_struct_2 = m_struct_1.get_sibling::<MyStruct2>(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     fn get_sibling<E: Entity<Key = Self::Key>>(&self, db: &Db) -> Result<Option<E>> {
         E::get(self.get_key(), db)
@@ -752,9 +1910,26 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let m_struct_2 = MyStruct2 { key : (0,44), prop9 : 44};
-    /// m_struct1.save_child(m_struct2,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # #[children(MyStruct2 = Cascade)]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : (u32, u32), prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # MyStruct1 { key : 7 }.save(&db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let mut m_struct_2 = MyStruct2 { key : (0,44), prop9 : 44};
+    /// m_struct_1.save_child(&mut m_struct_2,&db)?;
+    /// # assert_eq!(m_struct_2.key, (9,44));
+    /// # Ok(())
+    /// # }
     /// ```
     fn save_child<E: Entity<Key = (Self::Key, T)>, T: Clone>(
         &self,
@@ -775,18 +1950,42 @@ pub trait Entity: Serialize + DeserializeOwned {
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let m_struct_2 = MyStruct2 { key : (0,0), prop9 : 44};
-    /// m_struct1.save_next_child(m_struct2,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # #[children(MyStruct2 = Cascade)]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : (u32, u32), prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # MyStruct1 { key : 7 }.save(&db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let mut m_struct_2 = MyStruct2 { key : (0,0), prop9 : 44};
+    /// m_struct_1.save_next_child(&mut m_struct_2,&db)?;
+    /// # assert_eq!(m_struct_2.key, (9,0));
+    /// # Ok(())
+    /// # }
     /// ```
     fn save_next_child<E: Entity<Key = (Self::Key, u32)>>(
         &self,
         child: &mut E,
         db: &Db,
     ) -> Result<E::Key> {
-        
+        let key = self.get_next_child_key::<E>(db)?;
+        child.set_key(&key);
+        child.save(db)?;
+        Ok(key)
+    }
+
+    #[doc(hidden)]
+    fn get_next_child_key<E: Entity<Key = (Self::Key, u32)>>(&self, db: &Db) -> Result<E::Key> {
         let increment = match E::get_tree(db)?
-            .scan_prefix(&self.get_key().as_bytes())
+            .scan_prefix(self.get_key().as_bytes())
             .flatten()
             .last()
         {
@@ -802,19 +2001,35 @@ pub trait Entity: Serialize + DeserializeOwned {
             }
             _ => Default::default(),
         };
-        let key = (self.get_key().clone(), increment);
-        child.set_key(&key);
-        child.save(db)?;
-        Ok(key)
+        Ok((self.get_key().clone(), increment))
     }
 
     /// Reparents a child to this entity and saves the result to the database.
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let m_struct_2 = MyStruct2::get(&(7,2),&db)?;
-    /// m_struct1.adopt_as_next_child(m_struct2,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # #[children(MyStruct2 = Cascade)]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : (u32, u32), prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # MyStruct1 { key : 7 }.save(&db)?;
+    /// # MyStruct2 { key : (9,1), prop9 : 44 }.save(&db)?;
+    /// # MyStruct2 { key : (7,2), prop9 : 44 }.save(&db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let mut m_struct_2 = MyStruct2::get(&(7,2),&db)?.unwrap();
+    /// m_struct_1.adopt_as_next_child(&mut m_struct_2,&db)?;
+    /// # assert_eq!(m_struct_2.key, (9,2));
+    /// # Ok(())
+    /// # }
     /// ```
     /// After this code, m_struct_2 now has key (9,2) instead of (7,2) and has changed
     /// accordingly in the database.
@@ -824,24 +2039,39 @@ pub trait Entity: Serialize + DeserializeOwned {
         db: &Db,
     ) -> Result<()> {
         let old_id = child.get_key().clone();
-        self.save_next_child(child, db)?;
-        Relation::change_entity_id(
-            E::store_name(),
-            &old_id.as_bytes(),
-            &child.get_key().as_bytes(),
-            db,
-        )?;
-        E::remove(&old_id, db)?;
-        Ok(())
+        child.set_key(&self.get_next_child_key::<E>(db)?);
+        let result = transaction(db, |tx| tx.move_entity(&*child, &old_id));
+        if result.is_err() {
+            child.set_key(&old_id);
+        }
+        result
     }
 
     /// Reparents a child to this entity and saves the result to the database.
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let m_struct_2 = MyStruct2::get(&(7,2),&db)?;
-    /// m_struct1.adopt_as_next_child(m_struct2,&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # #[children(MyStruct2 = Cascade)]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : (u32, u32), prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # MyStruct1 { key : 7 }.save(&db)?;
+    /// # MyStruct2 { key : (7,2), prop9 : 44 }.save(&db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let mut m_struct_2 = MyStruct2::get(&(7,2),&db)?.unwrap();
+    /// m_struct_1.adopt_child(&mut m_struct_2,&db)?;
+    /// # assert_eq!(m_struct_2.key, (9,2));
+    /// # Ok(())
+    /// # }
     /// ```
     /// After this code, m_struct_2 now has key (9,2) instead of (7,2) and has changed
     /// accordingly in the database.
@@ -857,26 +2087,40 @@ pub trait Entity: Serialize + DeserializeOwned {
             return Ok(());
         }
         let old_id = child.get_key().clone();
-        self.save_child(child, db)?;
-        Relation::change_entity_id(
-            E::store_name(),
-            &old_id.as_bytes(),
-            &child.get_key().as_bytes(),
-            db,
-        )?;
-        E::remove(&old_id, db)?;
-        Ok(())
+        child.set_key(&(self.get_key().clone(), old_id.1.clone()));
+        let result = transaction(db, |tx| tx.move_entity(&*child, &old_id));
+        if result.is_err() {
+            child.set_key(&old_id);
+        }
+        result
     }
 
     /// Gets children Entities from another store
     ///
     /// ### Exemple
     /// ```rust
-    /// let m_struct_1 = MyStruct1::get(&9,&db)?;
-    /// let children : Vec<MyStruct2> = _struct_1.get_children(&db)?;
+    /// # use reindeer::{Entity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_1", id = "key")]
+    /// # #[children(MyStruct2 = Cascade)]
+    /// # struct MyStruct1 { key : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct_2", id = "key")]
+    /// # struct MyStruct2 { key : (u32, u32), prop9 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct1::register(&db)?;
+    /// # MyStruct2::register(&db)?;
+    /// # MyStruct1 { key : 9 }.save(&db)?;
+    /// # MyStruct1 { key : 7 }.save(&db)?;
+    /// # MyStruct2 { key : (9,0), prop9 : 44 }.save(&db)?;
+    /// let m_struct_1 = MyStruct1::get(&9,&db)?.unwrap();
+    /// let children : Vec<MyStruct2> = m_struct_1.get_children(&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     /// Note : Due to [turbofish restrictions](https://github.com/rust-lang/rust/issues/83701),
-    /// `_struct_1.get_children::<MyStruct2>(&db)?` cannot be used [before rust 1.63](https://github.com/rust-lang/rust/issues/83701#issuecomment-1190578601)
+    /// `m_struct_1.get_children::<MyStruct2>(&db)?` cannot be used [before rust 1.63](https://github.com/rust-lang/rust/issues/83701#issuecomment-1190578601)
     fn get_children<E: Entity<Key = (Self::Key, impl AsBytes)>>(&self, db: &Db) -> Result<Vec<E>> {
         E::get_with_prefix(self.get_key(), db)
     }
@@ -894,11 +2138,21 @@ pub trait AutoIncrementEntity: Entity<Key = u32> {
     /// Saves the entity to the database after having modified its key to an auto-incremented one.
     /// ### Example
     /// ```rust
-    /// let m_struct = MyStruct { key : 0, prop9 : 44};
+    /// # use reindeer::{Entity, AutoIncrementEntity, Serialize, Deserialize};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "my_struct", id = "key")]
+    /// # struct MyStruct { key : u32, prop1 : String, prop2 : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # MyStruct::register(&db)?;
+    /// let mut m_struct = MyStruct { key : 0, prop1 : String::from("Hello"), prop2 : 44};
     /// m_struct.save_next(&db)?; // will have key 0
-    /// let m_struct_2 = MyStruct { key : 0, prop9 :59};
+    /// let mut m_struct_2 = MyStruct { key : 0, prop1 : String::from("World"), prop2 : 59};
     /// m_struct_2.save_next(&db)?; // creates a new entry with key 1, and so on
-    /// // m_struct2.key is now 1
+    /// // m_struct_2.key is now 1
+    /// # assert_eq!(m_struct_2.key, 1);
+    /// # Ok(())
+    /// # }
     /// ```
     fn save_next(&mut self, db: &Db) -> Result<u32>;
 }
@@ -921,62 +2175,3 @@ where
         Ok(next_key)
     }
 }
-
-/// Trait allowing values to be converted to `Vec<u8>`.
-/// This trait is not meant to be implemented, but you can if you need to.
-pub trait AsBytes {
-    /// Returns a new binary representation of `self` as a `Vec<u8>`
-    fn as_bytes(&self) -> Vec<u8>;
-}
-
-impl AsBytes for String {
-    fn as_bytes(&self) -> Vec<u8> {
-        self.as_bytes().to_owned()
-    }
-}
-
-impl AsBytes for u32 {
-    fn as_bytes(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
-    }
-}
-
-impl AsBytes for u64 {
-    fn as_bytes(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
-    }
-}
-
-impl AsBytes for i32 {
-    fn as_bytes(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
-    }
-}
-
-impl AsBytes for i64 {
-    fn as_bytes(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
-    }
-}
-
-impl AsBytes for Vec<u8> {
-    fn as_bytes(&self) -> Vec<u8> {
-        self.clone()
-    }
-}
-
-impl AsBytes for &[u8] {
-    fn as_bytes(&self) -> Vec<u8> {
-        self.to_vec()
-    }
-}
-
-impl<K1, K2> AsBytes for (K1, K2)
-where
-    K1: AsBytes,
-    K2: AsBytes,
-{
-    fn as_bytes(&self) -> Vec<u8> {
-        vec![self.0.as_bytes(), self.1.as_bytes()].concat()
-    }
-}
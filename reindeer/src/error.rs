@@ -16,6 +16,18 @@ pub enum ErrorKind {
     NotFound,
     /// An entity was used without being registered firts in the database
     UnregisteredEntity,
+    /// A store could not be migrated to the current version of its entity
+    MigrationError,
+    /// A unique index constraint has been violated while trying to save an entity
+    UniqueConstraint,
+    /// An encrypted value could not be authenticated : the encryption key is wrong or missing,
+    /// or the value has been tampered with
+    AuthenticationError,
+    /// A free relation was created, but the entities involved declare free relations and none of them declares this one
+    /// with these deletion behaviours
+    UndeclaredRelation,
+    /// A transaction kept conflicting with concurrent changes, and was given up
+    TransactionConflict,
 }
 
 /// Error type for `reindeer`
@@ -76,3 +88,12 @@ impl From<serde_json::Error> for Error {
         Error::new(ErrorKind::SerializationError, source.to_string())
     }
 }
+
+impl From<sled::transaction::TransactionError<Error>> for Error {
+    fn from(source: sled::transaction::TransactionError<Error>) -> Self {
+        match source {
+            sled::transaction::TransactionError::Abort(e) => e,
+            sled::transaction::TransactionError::Storage(e) => e.into(),
+        }
+    }
+}
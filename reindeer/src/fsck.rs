@@ -0,0 +1,414 @@
+//! # Fsck Module
+//! This module provides an integrity checker for the relations of a database, and a way to repair the
+//! inconsistencies it finds.
+
+use std::fmt;
+
+use sled::Db;
+
+use crate::error::Result;
+use crate::hex;
+use crate::migration::VERSION_TREE;
+use crate::relation::{edge, FamilyDescriptor, Relation};
+use crate::storage::Storage;
+use crate::transaction::transaction;
+use crate::{DeletionBehaviour, Entity, Error, ErrorKind};
+
+/// An inconsistency found by [`fsck`](fn.fsck.html). Keys are given in their binary representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckIssue {
+    /// The relation descriptor of an entity that does not exist anymore.
+    OrphanedDescriptor { store: String, key: Vec<u8> },
+    /// A link to an entity that does not exist anymore.
+    DanglingLink {
+        store: String,
+        key: Vec<u8>,
+        related_store: String,
+        related_key: Vec<u8>,
+    },
+    /// A link to an existing entity, which does not link back to this one.
+    AsymmetricLink {
+        store: String,
+        key: Vec<u8>,
+        related_store: String,
+        related_key: Vec<u8>,
+    },
+    /// A child whose parent does not exist anymore, although the parent store removes its children
+    /// (`DeletionBehaviour::Cascade`) or cannot be removed while it has children (`DeletionBehaviour::Error`).
+    OrphanedChild {
+        store: String,
+        key: Vec<u8>,
+        parent_store: String,
+    },
+    /// A sibling whose counterpart does not exist anymore, although the counterpart store cannot be removed while
+    /// it exists (`DeletionBehaviour::Error`).
+    ///
+    /// Siblings of a store that removes them (`DeletionBehaviour::Cascade`) are not reported : they can be saved
+    /// before their counterpart, or without it.
+    OrphanedSibling {
+        store: String,
+        key: Vec<u8>,
+        sibling_store: String,
+        behaviour: DeletionBehaviour,
+    },
+}
+
+impl fmt::Display for FsckIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FsckIssue::OrphanedDescriptor { store, key } => write!(
+                f,
+                "Relation descriptor of missing entity {} in store {}",
+                hex::encode(key),
+                store
+            ),
+            FsckIssue::DanglingLink {
+                store,
+                key,
+                related_store,
+                related_key,
+            } => write!(
+                f,
+                "Entity {} of store {} is linked to missing entity {} of store {}",
+                hex::encode(key),
+                store,
+                hex::encode(related_key),
+                related_store
+            ),
+            FsckIssue::AsymmetricLink {
+                store,
+                key,
+                related_store,
+                related_key,
+            } => write!(
+                f,
+                "Entity {} of store {} is linked to entity {} of store {}, which does not link back",
+                hex::encode(key),
+                store,
+                hex::encode(related_key),
+                related_store
+            ),
+            FsckIssue::OrphanedChild {
+                store,
+                key,
+                parent_store,
+            } => write!(
+                f,
+                "Entity {} of store {} has no parent in store {}",
+                hex::encode(key),
+                store,
+                parent_store
+            ),
+            FsckIssue::OrphanedSibling {
+                store,
+                key,
+                sibling_store,
+                behaviour,
+            } => write!(
+                f,
+                "Entity {} of store {} has no sibling in store {} ({:?})",
+                hex::encode(key),
+                store,
+                sibling_store,
+                behaviour
+            ),
+        }
+    }
+}
+
+/// Options of [`FsckReport::repair_with`](struct.FsckReport.html#method.repair_with).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepairOptions {
+    /// Removes the orphaned children and siblings, along with the entities that must be removed with them.
+    /// They are left untouched by default, as they hold data that may still be needed.
+    pub remove_orphans: bool,
+}
+
+/// The inconsistencies found by [`fsck`](fn.fsck.html).
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// Every inconsistency found, in the order they were found
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    /// Returns `true` if no inconsistency was found.
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Fixes the relations reported as inconsistent, in a single transaction :
+    ///  - orphaned descriptors are removed, along with the links pointing to them,
+    ///  - dangling and asymmetric links are removed.
+    ///
+    /// Orphaned children and siblings are left untouched : use
+    /// [`repair_with`](struct.FsckReport.html#method.repair_with) to remove them as well.
+    pub fn repair(&self, db: &Db) -> Result<()> {
+        self.repair_with(&RepairOptions::default(), db)
+    }
+
+    /// Fixes the inconsistencies of this report, in a single transaction, like
+    /// [`repair`](struct.FsckReport.html#method.repair). With `options.remove_orphans`, orphaned children
+    /// and siblings are also removed, along with the entities that must be removed with them.
+    ///
+    /// If an orphaned entity cannot be removed because of a `DeletionBehaviour::Error` constraint,
+    /// an `IntegrityError` is returned and nothing is changed.
+    pub fn repair_with(&self, options: &RepairOptions, db: &Db) -> Result<()> {
+        transaction(db, |tx| {
+            for issue in &self.issues {
+                match issue {
+                    FsckIssue::DanglingLink {
+                        store,
+                        key,
+                        related_store,
+                        related_key,
+                    }
+                    | FsckIssue::AsymmetricLink {
+                        store,
+                        key,
+                        related_store,
+                        related_key,
+                    } => Relation::remove_link_with_keys_and_tree_names(
+                        store,
+                        key,
+                        related_store,
+                        related_key,
+                        tx,
+                    )?,
+                    _ => {}
+                }
+            }
+            for issue in &self.issues {
+                if let FsckIssue::OrphanedDescriptor { store, key } = issue {
+                    Relation::remove_entity_entry(store, key, tx)?;
+                }
+            }
+            if !options.remove_orphans {
+                return Ok(());
+            }
+            for issue in &self.issues {
+                if let FsckIssue::OrphanedChild { store, key, .. }
+                | FsckIssue::OrphanedSibling { store, key, .. } = issue
+                {
+                    // Removing an orphan may already have removed the other ones
+                    if tx.contains_key(store, key)? {
+                        tx.remove_from_u8_array(store, key)?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Checks the integrity of the relations of `db`, and returns the inconsistencies found.
+///
+/// Every relation descriptor is checked for links to missing entities and for links that are not symmetric,
+/// and every registered family is checked for children and siblings that should not have outlived their
+/// parent or counterpart. Nothing is written : use [`FsckReport::repair`](struct.FsckReport.html#method.repair)
+/// to fix the inconsistencies.
+///
+/// ### Example
+/// ```rust
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// let report = reindeer::fsck(&db)?;
+/// for issue in &report.issues {
+///     println!("{}", issue);
+/// }
+/// report.repair(&db)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn fsck(db: &Db) -> Result<FsckReport> {
+    let mut report = FsckReport::default();
+    check_all_descriptors(db, &mut report)?;
+    for family in FamilyDescriptor::get_all(db)? {
+        check_family(&family, db, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Marks the databases already checked by [`check_case_folded_links`](fn.check_case_folded_links.html),
+/// with whether the links found have been returned yet.
+const CASE_FOLDED_LINKS_MARKER: &str = "__$case_folded_links";
+const CASE_FOLDED_LINKS_PENDING: u32 = 0;
+const CASE_FOLDED_LINKS_REPORTED: u32 = 1;
+
+/// Holds the links found when the relation descriptors written by previous versions were converted, until they
+/// are returned by [`check_case_folded_links`](fn.check_case_folded_links.html).
+const CASE_FOLDED_LINKS_TREE: &str = "__$case_folded_links_report";
+
+/// Detects the relation descriptors corrupted by the case-insensitive key comparison used by previous versions
+/// when removing relations. Removing a link to key `"ABC"` also removed the links to `"abc"`, or to any binary key
+/// that only differs by the case of its ASCII letters, from the descriptor of the entity on the other side.
+///
+/// The corrupted descriptors are reported as [`FsckIssue::AsymmetricLink`](enum.FsckIssue.html), from the
+/// side whose link has been kept, when the key of the entity that lost its link contains ASCII letters.
+/// Nothing is fixed : the lost links can be created again, or the remaining ones removed with
+/// [`FsckReport::repair`](struct.FsckReport.html#method.repair).
+///
+/// The check runs on its own when the last relation descriptors written by previous versions are converted, as
+/// entities are registered, and its report is kept until this function returns it. The relation descriptors
+/// that can be converted are converted first : if some remain, because the entities of their store have not been
+/// registered yet, a `MigrationError` is returned.
+///
+/// The report is only returned once per database : `None` is returned on subsequent calls.
+pub fn check_case_folded_links(db: &Db) -> Result<Option<FsckReport>> {
+    edge::migrate_legacy_trees(db)?;
+    let remaining = edge::legacy_tree_names(db);
+    if !remaining.is_empty() {
+        return Err(Error::new(
+            ErrorKind::MigrationError,
+            format!(
+                "Relation descriptors of a previous version remain in {} : register every entity first",
+                remaining.join(", ")
+            ),
+        ));
+    }
+    let versions = db.open_tree(VERSION_TREE)?;
+    let report = match versions.get(CASE_FOLDED_LINKS_MARKER)? {
+        None => case_folded_links(db)?,
+        Some(marker) if marker.as_ref() == CASE_FOLDED_LINKS_PENDING.to_be_bytes() => {
+            let mut report = FsckReport::default();
+            for value in db.open_tree(CASE_FOLDED_LINKS_TREE)?.iter().values() {
+                let (store, key, related_store, related_key) = bincode::deserialize(&value?)?;
+                report.issues.push(FsckIssue::AsymmetricLink {
+                    store,
+                    key,
+                    related_store,
+                    related_key,
+                });
+            }
+            report
+        }
+        Some(_) => return Ok(None),
+    };
+    versions.insert(CASE_FOLDED_LINKS_MARKER, &CASE_FOLDED_LINKS_REPORTED.to_be_bytes())?;
+    db.drop_tree(CASE_FOLDED_LINKS_TREE)?;
+    Ok(Some(report))
+}
+
+/// Looks for the links damaged by case folding, unless this has already been done, and keeps them until they are
+/// returned by [`check_case_folded_links`](fn.check_case_folded_links.html). Called once every relation
+/// descriptor written by previous versions has been converted.
+pub(crate) fn record_case_folded_links(db: &Db) -> Result<()> {
+    let versions = db.open_tree(VERSION_TREE)?;
+    if versions.contains_key(CASE_FOLDED_LINKS_MARKER)? {
+        return Ok(());
+    }
+    let pending = db.open_tree(CASE_FOLDED_LINKS_TREE)?;
+    for (i, issue) in case_folded_links(db)?.issues.iter().enumerate() {
+        if let FsckIssue::AsymmetricLink {
+            store,
+            key,
+            related_store,
+            related_key,
+        } = issue
+        {
+            let value = bincode::serialize(&(store, key, related_store, related_key))?;
+            pending.insert((i as u64).to_be_bytes(), value)?;
+        }
+    }
+    versions.insert(CASE_FOLDED_LINKS_MARKER, &CASE_FOLDED_LINKS_PENDING.to_be_bytes())?;
+    Ok(())
+}
+
+fn case_folded_links(db: &Db) -> Result<FsckReport> {
+    let mut report = FsckReport::default();
+    check_all_descriptors(db, &mut report)?;
+    report.issues.retain(|issue| {
+        matches!(issue, FsckIssue::AsymmetricLink { key, .. } if key.iter().any(u8::is_ascii_alphabetic))
+    });
+    Ok(report)
+}
+
+fn check_all_descriptors(db: &Db, report: &mut FsckReport) -> Result<()> {
+    for name in db.tree_names() {
+        if let Some(store) = name.strip_prefix(Relation::tree_name("").as_bytes()) {
+            let store = String::from_utf8_lossy(store).into_owned();
+            check_descriptors(&store, db, report)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_descriptors(store: &str, db: &Db, report: &mut FsckReport) -> Result<()> {
+    let entities = db.open_tree(store)?;
+    for edge in db.open_tree(Relation::tree_name(store))?.iter().keys() {
+        let (key, related_store, related_key, _) = edge::decode_key(&edge?)?;
+        if !entities.contains_key(&key)? {
+            // Edges are sorted by entity, so the links of a missing entity follow each other
+            if !matches!(report.issues.last(), Some(FsckIssue::OrphanedDescriptor { store: s, key: k }) if s == store && *k == key)
+            {
+                report.issues.push(FsckIssue::OrphanedDescriptor {
+                    store: String::from(store),
+                    key,
+                });
+            }
+            continue;
+        }
+        if !Storage::contains_key(db, &related_store, &related_key)? {
+            report.issues.push(FsckIssue::DanglingLink {
+                store: String::from(store),
+                key,
+                related_store,
+                related_key,
+            });
+        } else if !Storage::has_prefix(
+            db,
+            &Relation::tree_name(&related_store),
+            &edge::link_prefix(&related_key, store, &key),
+        )? {
+            report.issues.push(FsckIssue::AsymmetricLink {
+                store: String::from(store),
+                key,
+                related_store,
+                related_key,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_family(family: &FamilyDescriptor, db: &Db, report: &mut FsckReport) -> Result<()> {
+    let entities = db.open_tree(&family.tree_name)?;
+    for (child_store, behaviour) in &family.child_trees {
+        if *behaviour == DeletionBehaviour::BreakLink {
+            continue;
+        }
+        for key in db.open_tree(child_store)?.iter().keys() {
+            let key = key?;
+            // Key encodings are prefix-free, so the parent key is the greatest key lower than the child key, if any
+            let has_parent = entities
+                .range(..key.as_ref())
+                .next_back()
+                .transpose()?
+                .is_some_and(|(parent, _)| key.starts_with(&parent));
+            if !has_parent {
+                report.issues.push(FsckIssue::OrphanedChild {
+                    store: child_store.clone(),
+                    key: key.to_vec(),
+                    parent_store: family.tree_name.clone(),
+                });
+            }
+        }
+    }
+    for (sibling_store, behaviour) in &family.sibling_trees {
+        if *behaviour != DeletionBehaviour::Error {
+            continue;
+        }
+        for key in db.open_tree(sibling_store)?.iter().keys() {
+            let key = key?;
+            if !entities.contains_key(&key)? {
+                report.issues.push(FsckIssue::OrphanedSibling {
+                    store: sibling_store.clone(),
+                    key: key.to_vec(),
+                    sibling_store: family.tree_name.clone(),
+                    behaviour: *behaviour,
+                });
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,20 @@
+//! Hexadecimal representation of binary values, used where bytes need to be written as text.
+
+/// Writes `bytes` as a lowercase hexadecimal string.
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Reads bytes back from an hexadecimal string, returning `None` if it is not valid.
+pub(crate) fn decode(s: &str) -> Option<Vec<u8>> {
+    let pairs = s.as_bytes().chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| {
+            let digit = |byte: u8| char::from(byte).to_digit(16);
+            Some((digit(pair[0])? * 16 + digit(pair[1])?) as u8)
+        })
+        .collect()
+}
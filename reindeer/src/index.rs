@@ -0,0 +1,187 @@
+use serde_derive::{Deserialize, Serialize};
+use sled::Db;
+
+use crate::entity::Entity;
+use crate::error::Result;
+use crate::key::AsBytes;
+use crate::storage::Storage;
+use crate::{Error, ErrorKind};
+
+/// An index entry of an entity, as recorded in the index keys tree of its store
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    field: String,
+    value: Vec<u8>,
+    unique: bool,
+}
+
+fn tree_name(store_name: &str, field: &str) -> String {
+    format!("__$idx_{}_{}", store_name, field)
+}
+
+fn keys_tree_name(store_name: &str) -> String {
+    format!("__$idxk_{}", store_name)
+}
+
+/// Returns the names of the existing index trees of a store, including its index keys tree.
+pub(crate) fn tree_names(store_name: &str, db: &Db) -> Vec<String> {
+    let prefix = tree_name(store_name, "");
+    let keys_tree = keys_tree_name(store_name);
+    db.tree_names()
+        .into_iter()
+        .filter_map(|name| String::from_utf8(name.to_vec()).ok())
+        .filter(|name| name.starts_with(&prefix) || *name == keys_tree)
+        .collect()
+}
+
+/// Key of an entry in a non-unique index : the length of the indexed value, the value itself, then the key of the entity.
+/// This makes sure that a value is never the prefix of another one.
+fn multiple_key(value: &[u8], key: &[u8]) -> Vec<u8> {
+    [&(value.len() as u32).to_be_bytes(), value, key].concat()
+}
+
+fn multiple_prefix(value: &[u8]) -> Vec<u8> {
+    multiple_key(value, &[])
+}
+
+fn is_unique<E: Entity>(field: &str) -> Result<bool> {
+    E::get_indexes()
+        .iter()
+        .find(|(name, _)| *name == field)
+        .map(|(_, unique)| *unique)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("No index on field {} for store {}", field, E::store_name()),
+            )
+        })
+}
+
+/// Returns the keys of the entities of `E` whose indexed `field` has the given value
+pub(crate) fn find_keys<E: Entity>(
+    field: &str,
+    value: &[u8],
+    db: &impl Storage,
+) -> Result<Vec<Vec<u8>>> {
+    let tree = tree_name(E::store_name(), field);
+    if is_unique::<E>(field)? {
+        Ok(db
+            .get(&tree, value)?
+            .map(|key| vec![key.to_vec()])
+            .unwrap_or_default())
+    } else {
+        Ok(db
+            .scan_prefix(&tree, &multiple_prefix(value))?
+            .into_iter()
+            .map(|(_, key)| key.to_vec())
+            .collect())
+    }
+}
+
+/// Updates every index of `entity`'s store to reflect its current values.
+/// Fails with `ErrorKind::UniqueConstraint` if a unique value is already used by another entity.
+pub(crate) fn save_entries<E: Entity>(entity: &E, db: &impl Storage) -> Result<()> {
+    let indexes = E::get_indexes();
+    if indexes.is_empty() {
+        return Ok(());
+    }
+    let key = entity.get_key().as_bytes();
+    let entries = indexes
+        .into_iter()
+        .filter_map(|(field, unique)| {
+            entity.get_index_value(field).map(|value| IndexEntry {
+                field: String::from(field),
+                value,
+                unique,
+            })
+        })
+        .collect::<Vec<IndexEntry>>();
+    for entry in entries.iter().filter(|entry| entry.unique) {
+        if let Some(owner) = db.get(&tree_name(E::store_name(), &entry.field), &entry.value)? {
+            if owner != key {
+                return Err(Error::new(
+                    ErrorKind::UniqueConstraint,
+                    format!(
+                        "Value of unique field {} is already used in store {}",
+                        entry.field,
+                        E::store_name()
+                    ),
+                ));
+            }
+        }
+    }
+    remove_entries(E::store_name(), &key, db)?;
+    insert_entries(E::store_name(), &key, &entries, db)
+}
+
+fn insert_entries(
+    store_name: &str,
+    key: &[u8],
+    entries: &[IndexEntry],
+    db: &impl Storage,
+) -> Result<()> {
+    for entry in entries {
+        let tree = tree_name(store_name, &entry.field);
+        if entry.unique {
+            db.insert(&tree, &entry.value, key.into())?;
+        } else {
+            db.insert(&tree, &multiple_key(&entry.value, key), key.into())?;
+        }
+    }
+    db.insert(
+        &keys_tree_name(store_name),
+        key,
+        bincode::serialize(entries)?.into(),
+    )
+}
+
+fn take_entries(store_name: &str, key: &[u8], db: &impl Storage) -> Result<Vec<IndexEntry>> {
+    let entries = match db.get(&keys_tree_name(store_name), key)? {
+        Some(entries) => bincode::deserialize::<Vec<IndexEntry>>(&entries)?,
+        None => return Ok(Vec::new()),
+    };
+    for entry in &entries {
+        let tree = tree_name(store_name, &entry.field);
+        if entry.unique {
+            if db.get(&tree, &entry.value)?.as_deref() == Some(key) {
+                db.remove(&tree, &entry.value)?;
+            }
+        } else {
+            db.remove(&tree, &multiple_key(&entry.value, key))?;
+        }
+    }
+    db.remove(&keys_tree_name(store_name), key)?;
+    Ok(entries)
+}
+
+/// Removes the index entries of the entity with key `key` in store `store_name`, if any.
+pub(crate) fn remove_entries(store_name: &str, key: &[u8], db: &impl Storage) -> Result<()> {
+    take_entries(store_name, key, db)?;
+    Ok(())
+}
+
+/// Makes the index entries of an entity point to its new key.
+pub(crate) fn move_entries(
+    store_name: &str,
+    old_key: &[u8],
+    new_key: &[u8],
+    db: &impl Storage,
+) -> Result<()> {
+    let entries = take_entries(store_name, old_key, db)?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    insert_entries(store_name, new_key, &entries, db)
+}
+
+/// Drops every index of the store of `E` and indexes all of its entities again.
+pub(crate) fn rebuild<E: Entity>(db: &Db) -> Result<()> {
+    for (field, _) in E::get_indexes() {
+        db.open_tree(tree_name(E::store_name(), field))?.clear()?;
+    }
+    db.open_tree(keys_tree_name(E::store_name()))?.clear()?;
+    for entity in E::get_all(db)? {
+        save_entries(&entity, db)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! # Iterator Module
+//! This module provides lazy iterators over the entities of a store, so that entities
+//! are only read and deserialized when they are actually consumed.
+
+use std::marker::PhantomData;
+
+use crate::cipher::Cipher;
+use crate::entity::Entity;
+use crate::error::Result;
+
+/// A lazy, double-ended iterator over the entities of a store, in key order.
+///
+/// Every entity is deserialized on demand, and each item is a `Result`, so that a storage or
+/// deserialization error on one entity does not prevent reading the other ones.
+///
+/// It is obtained through [`Entity::iter`](entity/trait.Entity.html#method.iter),
+/// [`Entity::iter_prefix`](entity/trait.Entity.html#method.iter_prefix) or
+/// [`Entity::iter_range`](entity/trait.Entity.html#method.iter_range).
+pub struct EntityIter<E> {
+    iter: sled::Iter,
+    cipher: Cipher,
+    entity: PhantomData<E>,
+}
+
+impl<E: Entity> EntityIter<E> {
+    pub(crate) fn new(iter: sled::Iter, cipher: Cipher) -> EntityIter<E> {
+        EntityIter {
+            iter,
+            cipher,
+            entity: PhantomData,
+        }
+    }
+}
+
+impl<E: Entity> Iterator for EntityIter<E> {
+    type Item = Result<E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|elem| -> Result<E> {
+                let (key, value) = elem?;
+                E::try_from_ivec(self.cipher.open(E::store_name(), &key, value)?)
+            })
+    }
+}
+
+impl<E: Entity> DoubleEndedIterator for EntityIter<E> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next_back()
+            .map(|elem| -> Result<E> {
+                let (key, value) = elem?;
+                E::try_from_ivec(self.cipher.open(E::store_name(), &key, value)?)
+            })
+    }
+}
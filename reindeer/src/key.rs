@@ -0,0 +1,352 @@
+//! # Key Module
+//! This module provides the binary encoding of entity keys.
+//!
+//! Keys are encoded so that the binary order of encoded keys matches the natural order of the values
+//! (negative integers sort before positive ones, `"ab"` sorts before `"abc"`), and so that every encoded
+//! value delimits itself : the key of a parent is always a prefix of the keys of its children, and
+//! never a prefix of the key of another parent.
+//!
+//!  - Unsigned integers are written in big-endian order
+//!  - Signed integers are written in big-endian order, with their sign bit flipped
+//!  - `bool` is written as a single byte, `char` as a `u32`, and `[u8; 16]` (UUIDs) as is
+//!  - `Option` is written as `0x00` for `None`, or as `0x01` followed by the value for `Some`
+//!  - `String` is written with its `0x00` bytes escaped as `0x00 0xFF`, and terminated by `0x00 0x01`
+//!  - `Vec<u8>` and `&[u8]` are taken as already encoded, and written as is : they do not delimit themselves,
+//!    and can only be the last element of a composite key
+//!  - Tuples are written as the concatenation of their elements
+
+use crate::error::Result;
+use crate::{Error, ErrorKind};
+
+const ESCAPE: u8 = 0x00;
+const ESCAPED_NULL: u8 = 0xFF;
+const TERMINATOR: u8 = 0x01;
+
+/// Trait allowing values to be converted to `Vec<u8>`.
+/// This trait is not meant to be implemented, but you can if you need to.
+///
+/// The binary representation must preserve the order of the values, and must delimit itself
+/// so that it can be followed by other values in composite keys.
+pub trait AsBytes {
+    /// Returns a new binary representation of `self` as a `Vec<u8>`
+    fn as_bytes(&self) -> Vec<u8>;
+}
+
+/// Trait allowing values to be read back from their [`AsBytes`](trait.AsBytes.html) representation.
+/// This trait is not meant to be implemented, but you can if you need to.
+pub trait FromBytes: Sized {
+    /// Reads a value at the start of `bytes`, and advances `bytes` past it.
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self>;
+
+    /// Reads a value from its whole binary representation.
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut remaining = bytes;
+        let value = Self::read_bytes(&mut remaining)?;
+        if !remaining.is_empty() {
+            return Err(invalid_key(format!(
+                "{} trailing bytes after the end of the key",
+                remaining.len()
+            )));
+        }
+        Ok(value)
+    }
+
+    /// Reads a value from its whole binary representation as written by previous versions of `reindeer`,
+    /// where strings and byte vectors were neither escaped nor terminated, and signed integers were written
+    /// in two's complement. This is used to upgrade the keys of the stores written by these versions.
+    ///
+    /// Types that did not exist as keys in these versions read their current representation.
+    #[doc(hidden)]
+    fn from_legacy_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes(bytes)
+    }
+
+    /// The length of the representation read by [`from_legacy_bytes`](#method.from_legacy_bytes),
+    /// if it does not depend on the value.
+    #[doc(hidden)]
+    fn legacy_len() -> Option<usize> {
+        None
+    }
+}
+
+fn invalid_key(message: String) -> Error {
+    Error::new(
+        ErrorKind::SerializationError,
+        format!("Invalid key : {}", message),
+    )
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N]> {
+    if bytes.len() < N {
+        return Err(invalid_key(format!(
+            "expected {} bytes, found {}",
+            N,
+            bytes.len()
+        )));
+    }
+    let (value, remaining) = bytes.split_at(N);
+    *bytes = remaining;
+    Ok(value.try_into().unwrap())
+}
+
+/// Reads an element of a tuple written by a previous version, which is `variable_len` bytes long
+/// if its length depends on its value, and advances `bytes` past it.
+fn take_legacy<T: FromBytes>(bytes: &mut &[u8], variable_len: usize) -> Result<T> {
+    let (value, remaining) = bytes.split_at(T::legacy_len().unwrap_or(variable_len).min(bytes.len()));
+    *bytes = remaining;
+    T::from_legacy_bytes(value)
+}
+
+/// Writes raw bytes so that they delimit themselves, as strings are.
+pub(crate) fn escape(value: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(value.len() + 2);
+    for byte in value {
+        result.push(*byte);
+        if *byte == ESCAPE {
+            result.push(ESCAPED_NULL);
+        }
+    }
+    result.extend([ESCAPE, TERMINATOR]);
+    result
+}
+
+/// Reads bytes written by [`escape`](fn.escape.html), and advances `bytes` past them.
+pub(crate) fn unescape(bytes: &mut &[u8]) -> Result<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    loop {
+        match bytes.get(i) {
+            Some(&ESCAPE) => match bytes.get(i + 1) {
+                Some(&TERMINATOR) => {
+                    *bytes = &bytes[i + 2..];
+                    return Ok(result);
+                }
+                Some(&ESCAPED_NULL) => {
+                    result.push(ESCAPE);
+                    i += 2;
+                }
+                _ => return Err(invalid_key(String::from("invalid escape sequence"))),
+            },
+            Some(byte) => {
+                result.push(*byte);
+                i += 1;
+            }
+            None => return Err(invalid_key(String::from("missing terminator"))),
+        }
+    }
+}
+
+macro_rules! unsigned_key {
+    ($($t:ty),*) => {
+        $(
+            impl AsBytes for $t {
+                fn as_bytes(&self) -> Vec<u8> {
+                    self.to_be_bytes().to_vec()
+                }
+            }
+
+            impl FromBytes for $t {
+                fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+                    Ok(<$t>::from_be_bytes(take(bytes)?))
+                }
+
+                fn legacy_len() -> Option<usize> {
+                    Some(std::mem::size_of::<$t>())
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! signed_key {
+    ($(($t:ty, $u:ty)),*) => {
+        $(
+            impl AsBytes for $t {
+                fn as_bytes(&self) -> Vec<u8> {
+                    ((*self as $u) ^ (1 << (<$u>::BITS - 1))).to_be_bytes().to_vec()
+                }
+            }
+
+            impl FromBytes for $t {
+                fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+                    Ok((<$u>::from_be_bytes(take(bytes)?) ^ (1 << (<$u>::BITS - 1))) as $t)
+                }
+
+                fn from_legacy_bytes(bytes: &[u8]) -> Result<Self> {
+                    let mut remaining = bytes;
+                    let value = <$t>::from_be_bytes(take(&mut remaining)?);
+                    if !remaining.is_empty() {
+                        return Err(invalid_key(format!(
+                            "{} trailing bytes after the end of the key",
+                            remaining.len()
+                        )));
+                    }
+                    Ok(value)
+                }
+
+                fn legacy_len() -> Option<usize> {
+                    Some(std::mem::size_of::<$t>())
+                }
+            }
+        )*
+    };
+}
+
+unsigned_key!(u8, u16, u32, u64, u128);
+signed_key!((i8, u8), (i16, u16), (i32, u32), (i64, u64), (i128, u128));
+
+impl AsBytes for bool {
+    fn as_bytes(&self) -> Vec<u8> {
+        vec![u8::from(*self)]
+    }
+}
+
+impl FromBytes for bool {
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+        match take::<1>(bytes)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            [other] => Err(invalid_key(format!("{} is not a boolean", other))),
+        }
+    }
+}
+
+impl AsBytes for char {
+    fn as_bytes(&self) -> Vec<u8> {
+        u32::from(*self).as_bytes()
+    }
+}
+
+impl FromBytes for char {
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+        let value = u32::read_bytes(bytes)?;
+        char::from_u32(value).ok_or_else(|| invalid_key(format!("{} is not a char", value)))
+    }
+}
+
+impl AsBytes for [u8; 16] {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl FromBytes for [u8; 16] {
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+        take(bytes)
+    }
+}
+
+impl AsBytes for String {
+    fn as_bytes(&self) -> Vec<u8> {
+        escape(str::as_bytes(self))
+    }
+}
+
+impl FromBytes for String {
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+        String::from_utf8(unescape(bytes)?).map_err(|e| invalid_key(e.to_string()))
+    }
+
+    fn from_legacy_bytes(bytes: &[u8]) -> Result<Self> {
+        String::from_utf8(bytes.to_vec()).map_err(|e| invalid_key(e.to_string()))
+    }
+}
+
+impl AsBytes for Vec<u8> {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl AsBytes for &[u8] {
+    fn as_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl FromBytes for Vec<u8> {
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+        Ok(std::mem::take(bytes).to_vec())
+    }
+
+    fn from_legacy_bytes(bytes: &[u8]) -> Result<Self> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl<T: AsBytes> AsBytes for Option<T> {
+    fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            None => vec![0],
+            Some(value) => [vec![1], value.as_bytes()].concat(),
+        }
+    }
+}
+
+impl<T: FromBytes> FromBytes for Option<T> {
+    fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+        match take::<1>(bytes)? {
+            [0] => Ok(None),
+            [1] => Ok(Some(T::read_bytes(bytes)?)),
+            [other] => Err(invalid_key(format!("{} is not an option tag", other))),
+        }
+    }
+}
+
+macro_rules! tuple_key {
+    ($(($($name:ident : $index:tt),+)),*) => {
+        $(
+            impl<$($name),+> AsBytes for ($($name,)+)
+            where
+                $($name: AsBytes,)+
+            {
+                fn as_bytes(&self) -> Vec<u8> {
+                    [$(self.$index.as_bytes()),+].concat()
+                }
+            }
+
+            impl<$($name),+> FromBytes for ($($name,)+)
+            where
+                $($name: FromBytes,)+
+            {
+                fn read_bytes(bytes: &mut &[u8]) -> Result<Self> {
+                    Ok(($($name::read_bytes(bytes)?,)+))
+                }
+
+                fn from_legacy_bytes(bytes: &[u8]) -> Result<Self> {
+                    let lens = [$($name::legacy_len()),+];
+                    if lens.iter().filter(|len| len.is_none()).count() > 1 {
+                        return Err(invalid_key(String::from(
+                            "a key written by a previous version with several elements of variable length cannot be read",
+                        )));
+                    }
+                    let variable_len = bytes
+                        .len()
+                        .checked_sub(lens.iter().flatten().sum())
+                        .ok_or_else(|| invalid_key(format!("{} bytes are too few for this key", bytes.len())))?;
+                    let mut remaining = bytes;
+                    let value = ($(take_legacy::<$name>(&mut remaining, variable_len)?,)+);
+                    if !remaining.is_empty() {
+                        return Err(invalid_key(format!(
+                            "{} trailing bytes after the end of the key",
+                            remaining.len()
+                        )));
+                    }
+                    Ok(value)
+                }
+
+                fn legacy_len() -> Option<usize> {
+                    Some(0 $(+ $name::legacy_len()?)+)
+                }
+            }
+        )*
+    };
+}
+
+tuple_key!(
+    (K1: 0, K2: 1),
+    (K1: 0, K2: 1, K3: 2),
+    (K1: 0, K2: 1, K3: 2, K4: 3),
+    (K1: 0, K2: 1, K3: 2, K4: 3, K5: 4),
+    (K1: 0, K2: 1, K3: 2, K4: 3, K5: 4, K6: 5)
+);
@@ -0,0 +1,183 @@
+//! # Layout Module
+//! This module upgrades the stores written by previous versions of `reindeer`, where keys were not encoded
+//! in order and values were not prefixed with their codec.
+//!
+//! The first time a database is used by this version, every registered store already holding data is recorded as
+//! written in the legacy layout. Stores created afterwards are written in the current layout from the start, whether their
+//! entity has been registered or not. A legacy store cannot be read or written until its entity is registered,
+//! which upgrades it.
+
+use std::collections::HashSet;
+
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{Db, IVec, Transactional};
+
+use crate::cipher::Cipher;
+use crate::codec::Codec;
+use crate::error::Result;
+use crate::key::{AsBytes, FromBytes};
+use crate::migration::VERSION_TREE;
+use crate::registry::INTERNAL_STORE_PREFIX;
+use crate::relation::{edge, FamilyDescriptor};
+use crate::state;
+use crate::{Entity, Error, ErrorKind};
+
+/// Name of the tree listing the stores still in the legacy layout.
+pub(crate) const LAYOUT_TREE: &str = "__$store_layout";
+
+/// Marks the databases whose stores have already been checked.
+const CHECKED_MARKER: &str = "__$checked";
+
+/// Entries of a tree rewritten by an upgrade : the former key, the new key and the new value.
+pub(crate) type Rewrites = Vec<(IVec, Vec<u8>, IVec)>;
+
+/// Records every store of `db` holding data as written in the legacy layout, unless this has already been done.
+///
+/// Only the stores with a family descriptor are considered : the other trees of the database were not written
+/// by `reindeer`. The keys of the descriptors are still in the legacy layout at this point.
+pub(crate) fn check(db: &Db) -> Result<()> {
+    let layouts = db.open_tree(LAYOUT_TREE)?;
+    if layouts.contains_key(CHECKED_MARKER)? {
+        return Ok(());
+    }
+    let families = db.open_tree(FamilyDescriptor::store_name())?;
+    let mut legacy = Vec::new();
+    if !families.is_empty() {
+        legacy.push(String::from(FamilyDescriptor::store_name()));
+    }
+    for name in families.iter().keys() {
+        let name = String::from_legacy_bytes(&name?)?;
+        if !name.starts_with(INTERNAL_STORE_PREFIX)
+            && db.tree_names().iter().any(|tree| tree == name.as_bytes())
+            && !db.open_tree(&name)?.is_empty()
+        {
+            legacy.push(name);
+        }
+    }
+    // Stores created by another thread once the database has been checked must not be recorded
+    let res: std::result::Result<(), TransactionError<()>> = layouts.transaction(|layouts| {
+        if layouts.get(CHECKED_MARKER)?.is_none() {
+            for name in &legacy {
+                layouts.insert(name.as_bytes(), &[])?;
+            }
+            layouts.insert(CHECKED_MARKER, &[])?;
+        }
+        Ok(())
+    });
+    match res {
+        Ok(()) | Err(TransactionError::Abort(())) => Ok(()),
+        Err(TransactionError::Storage(e)) => Err(e.into()),
+    }
+}
+
+/// Calls `f` with the names of the stores of `db` still in the legacy layout, checking the database first if needed.
+///
+/// They are read once and kept with the state of the database, as they are needed on every access to a store.
+fn with_legacy_stores<R>(db: &Db, f: impl Fn(&HashSet<String>) -> R) -> Result<R> {
+    let state = state::of(db)?;
+    if let Some(stores) = &*state.legacy_stores.read().unwrap_or_else(|e| e.into_inner()) {
+        return Ok(f(stores));
+    }
+    let mut cached = state.legacy_stores.write().unwrap_or_else(|e| e.into_inner());
+    if let Some(stores) = &*cached {
+        return Ok(f(stores));
+    }
+    check(db)?;
+    let stores = db
+        .open_tree(LAYOUT_TREE)?
+        .iter()
+        .keys()
+        .filter(|key| !matches!(key, Ok(key) if key == CHECKED_MARKER.as_bytes()))
+        .map(|key| Ok(String::from_utf8_lossy(&key?).into_owned()))
+        .collect::<Result<HashSet<String>>>()?;
+    Ok(f(cached.insert(stores)))
+}
+
+/// Checks the stores of `db`, if this has not been done yet, before anything is written to it.
+pub(crate) fn ensure_checked(db: &Db) -> Result<()> {
+    with_legacy_stores(db, |_| ())
+}
+
+/// Returns the names of the stores of `db` still in the legacy layout.
+pub(crate) fn legacy_stores(db: &Db) -> Result<HashSet<String>> {
+    with_legacy_stores(db, HashSet::clone)
+}
+
+/// Checks if the store `tree_name` is still in the legacy layout.
+pub(crate) fn is_legacy(tree_name: &str, db: &Db) -> Result<bool> {
+    with_legacy_stores(db, |stores| stores.contains(tree_name))
+}
+
+/// Returns an error if the store `tree_name` is still in the legacy layout, as its entries cannot be read
+/// and new entries would be mixed with legacy ones.
+pub(crate) fn ensure_current(tree_name: &str, db: &Db) -> Result<()> {
+    if is_legacy(tree_name, db)? {
+        return Err(Error::new(
+            ErrorKind::MigrationError,
+            format!(
+                "Store {} was written by a previous version of reindeer : register its entity to upgrade it",
+                tree_name
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Upgrades the store of `E` if it is in the legacy layout : its keys are read with
+/// [`FromBytes::from_legacy_bytes`](trait.FromBytes.html) and encoded again, its values are prefixed
+/// with the `Bincode` tag, and the keys of its entities are encoded again in the relation descriptors
+/// written by previous versions. The store is then recorded at version 0.
+///
+/// Everything is rewritten in a single transaction.
+pub(crate) fn upgrade<E: Entity>(db: &Db) -> Result<()> {
+    if !is_legacy(E::store_name(), db)? {
+        return Ok(());
+    }
+    let state = state::of(db)?;
+    let _write = state.write();
+    let cipher = Cipher::current(&state);
+    let tree = db.open_tree(E::store_name())?;
+    let mut entries = Rewrites::new();
+    for elem in tree.iter() {
+        let (key, value) = elem?;
+        let new_key = E::Key::from_legacy_bytes(&key)?.as_bytes();
+        let value = cipher.open(E::store_name(), &key, value)?;
+        let value = IVec::from([&[Codec::Bincode.tag()], value.as_ref()].concat());
+        let value = cipher.seal(E::store_name(), &new_key, value)?;
+        entries.push((key, new_key, value));
+    }
+    let mut trees = vec![
+        db.open_tree(LAYOUT_TREE)?,
+        db.open_tree(VERSION_TREE)?,
+        tree,
+    ];
+    let mut rewrites = vec![entries];
+    for (relations, relation_entries) in edge::upgrade_legacy_keys::<E>(db, &cipher)? {
+        trees.push(relations);
+        rewrites.push(relation_entries);
+    }
+    // Held until the store is removed from the cached legacy stores, so that they cannot be read in between
+    let mut cached = state.legacy_stores.write().unwrap_or_else(|e| e.into_inner());
+    let res: std::result::Result<(), TransactionError<Error>> =
+        trees.as_slice().transaction(|views| {
+            let (layouts, versions) = (&views[0], &views[1]);
+            for (view, entries) in views[2..].iter().zip(&rewrites) {
+                for (key, _, _) in entries {
+                    view.remove(key)?;
+                }
+                for (_, new_key, value) in entries {
+                    view.insert(new_key.as_slice(), value)?;
+                }
+            }
+            if versions.get(E::store_name())?.is_none() {
+                versions.insert(E::store_name(), &0u32.to_be_bytes())?;
+            }
+            layouts.remove(E::store_name())?;
+            Ok::<(), ConflictableTransactionError<Error>>(())
+        });
+    res?;
+    if let Some(stores) = cached.as_mut() {
+        stores.remove(E::store_name());
+    }
+    Ok(())
+}
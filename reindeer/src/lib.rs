@@ -15,7 +15,12 @@
 //!  - the [`Entity::save`](entity/trait.Entity.html#method.save) method to save your struct instance to the database
 //!  - the [`Entity::get`](entity/trait.Entity.html#method.get) method to get any entity from the database using its unique key
 //!  - the [`Entity::get_all`](entity/trait.Entity.html#method.get_all) method to get all entities from the database using its unique key
+//!  - the [`Entity::iter`](entity/trait.Entity.html#method.iter) method to lazily iterate over all entities, reading them one at a time
+//!  - the [`Entity::page_after`](entity/trait.Entity.html#method.page_after) method to paginate through entities with a [`Cursor`](struct.Cursor.html)
+//!  - the [`Entity::subscribe`](entity/trait.Entity.html#method.subscribe) method to be notified of every change made to a store
+//!  - the [`Entity::get_keys`](entity/trait.Entity.html#method.get_keys) method to list the keys of all entities without reading them
 //!  - the [`Entity::get_with_filter`](entity/trait.Entity.html#method.get_with_filter) method to get all entities that match a condition (O(n))
+//!  - the [`Entity::find_by_index`](entity/trait.Entity.html#method.find_by_index) method to get all entities with a given value for an indexed field
 //!  - ... And [much more](entity/trait.Entity.html)!
 //!
 //! If the [`Key`](entity/trait.Entity.html#associatedtype.Key) associated type is `u32`, then your entity can be auto-incremented
@@ -24,7 +29,7 @@
 //!
 //! Three types of relationships can be achieved :
 //!  - Sibling relationship : two or more `Entity` structs that share the same key type for which each entity has 0 or 1 counterpart
-//! in their sibling Entity stores (one-to-zero-or-one)
+//!    in their sibling Entity stores (one-to-zero-or-one)
 //!  - Parent-Child relationship : An entity has a collection of matching entities in another Entity Store (one-to-many)
 //!  - Free relationship : Any two entities can be linked together as a two-way link. (many-to-many)
 //!
@@ -32,18 +37,78 @@
 //!  - `DeletionBehaviour::Cascade` : related entities are also removed if this one is removed
 //!  - `DeletionBehaviour::Error` : Trying to remove this entity as related entities still exist will cause an error and abort
 //!  - `DeletionBehaviour::BreakLink` : Remove this entity and the links with its related entites, leaving the other ones untouched
+//!
+//! The consistency of relations can be checked with [`fsck`](fn.fsck.html), which can also repair what it finds.
+//!
+//! To read or write many entities of the same type, open a [`Store`](struct.Store.html) handle once with
+//! [`db.store::<T>()`](trait.OpenStore.html), which keeps the trees of the store open.
+//!
+//! Free relations can be followed over several hops, searched breadth-first or queried for shortest paths
+//! with a [`Traversal`](struct.Traversal.html).
+//!
+//! Entities implementing `Entity` through the `derive` macro can all be registered at once with [`register_all`](fn.register_all.html),
+//! and [`set_strict`](fn.set_strict.html) makes saving an entity of an unregistered store fail right away.
+//! The families of registered entities can be checked for coherence with [`validate_schema`](fn.validate_schema.html),
+//! and [`set_schema_validation`](fn.set_schema_validation.html) makes registering entities that break it fail.
+//!
+//! Removing an entity and its related entities is atomic. To save or remove several entities, siblings, children and relations
+//! atomically, use the [`transaction`](fn.transaction.html) function.
 
+#[cfg(feature = "async")]
+mod async_entity;
+pub mod backup;
+mod cipher;
+mod codec;
+mod compression;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 mod entity;
 mod error;
+mod fsck;
+mod hex;
 mod import_export;
+mod index;
+mod iter;
+mod key;
+mod layout;
+mod migration;
+mod page;
 mod query_builder;
+mod registry;
 mod relation;
+mod schema;
+mod state;
+mod storage;
+mod store;
+mod subscription;
+mod transaction;
+mod traversal;
+#[cfg(feature = "async")]
+pub use async_entity::{flush_async, run_blocking, AsyncEntity};
+pub use codec::Codec;
+pub use compression::{Compression, DEFAULT_COMPRESSION_THRESHOLD};
 pub use entity::AutoIncrementEntity;
-pub use entity::{AsBytes, Entity};
+pub use entity::Entity;
+pub use fsck::{check_case_folded_links, fsck, FsckIssue, FsckReport, RepairOptions};
+pub use iter::EntityIter;
+pub use key::{AsBytes, FromBytes};
+pub use page::{Cursor, Page};
 pub use reindeer_macros::Entity;
 
 pub use query_builder::*;
+pub use registry::{is_strict, register_all, set_schema_validation, set_strict, validates_schema};
 pub use relation::DeletionBehaviour;
+pub use schema::{validate_schema, SchemaIssue, SchemaReport};
+#[doc(hidden)]
+pub use inventory;
+#[doc(hidden)]
+pub use registry::EntityRegistration;
+#[doc(hidden)]
+pub use relation::{assert_child, assert_sibling, ChildKey};
+pub use store::{OpenStore, Store};
+pub use subscription::{Event, Subscription};
+pub use transaction::{transaction, Transaction};
+pub use traversal::Traversal;
 pub use serde_derive::{Deserialize, Serialize};
 
 pub use error::{Error, ErrorKind, Result};
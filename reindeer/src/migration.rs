@@ -0,0 +1,82 @@
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{Db, IVec, Transactional};
+
+use crate::cipher::Cipher;
+use crate::error::Result;
+use crate::index;
+use crate::state;
+use crate::{Entity, Error, ErrorKind};
+
+pub(crate) const VERSION_TREE: &str = "__$store_version";
+
+/// Returns the schema version recorded for a store, if any.
+pub(crate) fn get_store_version(tree_name: &str, db: &Db) -> Result<Option<u32>> {
+    let tree = db.open_tree(VERSION_TREE)?;
+    match tree.get(tree_name)? {
+        Some(bytes) => Ok(Some(bytes_to_version(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Brings the store of `E` up to `E::version()`, rewriting every record that was
+/// saved with an older version using `E::migrate`, then records the new version.
+///
+/// A store without a recorded version is assumed to be at version 0 if it already holds records, since they may
+/// have been saved before any version was recorded, and at the current version if it is empty.
+pub(crate) fn migrate_store<E: Entity>(db: &Db) -> Result<()> {
+    let current = E::version();
+    let tree = E::get_tree(db)?;
+    let versions = db.open_tree(VERSION_TREE)?;
+    let stored = match get_store_version(E::store_name(), db)? {
+        Some(version) => version,
+        None if tree.is_empty() => current,
+        None => 0,
+    };
+    if stored > current {
+        return Err(Error::new(
+            ErrorKind::MigrationError,
+            format!(
+                "Store {} is at version {}, which is newer than the current entity version {}",
+                E::store_name(),
+                stored,
+                current
+            ),
+        ));
+    }
+    let state = state::of(db)?;
+    let write = state.write();
+    let mut migrated = Vec::new();
+    if stored < current {
+        let cipher = Cipher::current(&state);
+        for elem in tree.iter() {
+            let (key, value) = elem?;
+            let entity = E::migrate(stored, &cipher.open(E::store_name(), &key, value)?)?;
+            let value = cipher.seal(E::store_name(), &key, entity.try_into_ivec()?)?;
+            migrated.push((key, value));
+        }
+    }
+    let res: std::result::Result<(), TransactionError<Error>> =
+        (&tree, &versions).transaction(|(tree, versions)| {
+            for (key, value) in &migrated {
+                tree.insert(key, value)?;
+            }
+            versions.insert(E::store_name(), &current.to_be_bytes())?;
+            Ok::<(), ConflictableTransactionError<Error>>(())
+        });
+    res?;
+    drop(write);
+    if stored < current && !E::get_indexes().is_empty() {
+        index::rebuild::<E>(db)?;
+    }
+    Ok(())
+}
+
+fn bytes_to_version(bytes: &IVec) -> Result<u32> {
+    match <[u8; 4]>::try_from(bytes.as_ref()) {
+        Ok(bytes) => Ok(u32::from_be_bytes(bytes)),
+        Err(_) => Err(Error::new(
+            ErrorKind::MigrationError,
+            "Stored version is not a valid u32".to_string(),
+        )),
+    }
+}
@@ -0,0 +1,249 @@
+//! # Page Module
+//! This module provides keyset pagination : a page starts right after (or ends right before) the key
+//! of the last entity of the previous page, so that reading a page only costs the size of the page,
+//! and pages do not shift when entities are inserted or removed.
+
+use std::fmt;
+use std::ops::Bound;
+use std::str::FromStr;
+
+use serde_derive::{Deserialize, Serialize};
+use sled::{IVec, Tree};
+
+use crate::cipher::Cipher;
+use crate::entity::Entity;
+use crate::error::Result;
+use crate::hex;
+use crate::key::AsBytes;
+use crate::{Error, ErrorKind};
+
+/// An opaque position in an entity store, used to request the page following or preceding it.
+///
+/// A cursor can be serialized with `serde`, or converted to and from an hexadecimal string
+/// with `to_string()` and `parse()`, to be handed over to an HTTP client for instance.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Cursor(Vec<u8>);
+
+impl Cursor {
+    /// Creates a cursor positioned on the entity with the given key.
+    pub fn from_key(key: &impl AsBytes) -> Cursor {
+        Cursor(key.as_bytes())
+    }
+
+    /// Returns the binary representation of this cursor.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The position before every key : the page following it is the first one.
+    pub(crate) fn start() -> Cursor {
+        Cursor(Vec::new())
+    }
+
+    /// The position right after this cursor : the page preceding it includes the entity at this cursor.
+    pub(crate) fn successor(&self) -> Cursor {
+        Cursor([self.0.as_slice(), &[0]].concat())
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0))
+    }
+}
+
+impl FromStr for Cursor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Cursor> {
+        hex::decode(s).map(Cursor).ok_or_else(|| {
+            Error::new(
+                ErrorKind::SerializationError,
+                format!("Invalid cursor : {}", s),
+            )
+        })
+    }
+}
+
+/// A page of entities, along with the cursors needed to get the surrounding pages.
+///
+/// ### Example
+/// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize};
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "my_struct", id = "key")]
+/// # struct MyStruct { key : u32 }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # MyStruct::register(&db)?;
+/// # for key in 0..40 {
+/// #     MyStruct { key }.save(&db)?;
+/// # }
+/// let first_page = MyStruct::page_after(None, 20, None::<u32>, &db)?;
+/// if let Some(next) = &first_page.next {
+///     let second_page = MyStruct::page_after(Some(next), 20, None::<u32>, &db)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Page<T> {
+    /// The entities of this page, in key order.
+    pub items: Vec<T>,
+    /// A cursor to pass to `page_after` to get the next page, if there are entities after this page.
+    pub next: Option<Cursor>,
+    /// A cursor to pass to `page_before` to get the previous page, if there are entities before this page.
+    pub previous: Option<Cursor>,
+}
+
+type Entries<'a> = Box<dyn Iterator<Item = sled::Result<(IVec, IVec)>> + 'a>;
+
+/// Gets the `count` entities of `tree` whose key starts with `prefix` and comes after `cursor`.
+pub(crate) fn page_after<E: Entity>(
+    tree: &Tree,
+    cipher: &Cipher,
+    prefix: &[u8],
+    cursor: Option<&Cursor>,
+    count: usize,
+) -> Result<Page<E>> {
+    page_after_where(tree, cipher, prefix, cursor, count, &|_| true)
+}
+
+/// Same as [`page_after`], but only keeps the entities whose key is accepted by `keep`.
+pub(crate) fn page_after_where<E: Entity>(
+    tree: &Tree,
+    cipher: &Cipher,
+    prefix: &[u8],
+    cursor: Option<&Cursor>,
+    count: usize,
+    keep: &dyn Fn(&[u8]) -> bool,
+) -> Result<Page<E>> {
+    let entries: Entries = match cursor {
+        Some(cursor) if cursor.as_bytes() >= prefix => Box::new(
+            tree.range::<&[u8], _>((Bound::Excluded(cursor.as_bytes()), Bound::Unbounded)),
+        ),
+        _ => Box::new(tree.scan_prefix(prefix)),
+    };
+    // Nothing follows the cursor : the previous page ends with the entity at the cursor
+    let empty_beyond = cursor
+        .filter(|cursor| cursor.as_bytes() >= prefix)
+        .map(Cursor::successor);
+    collect_page(read(entries, prefix, cipher, keep), count, true, empty_beyond, |key| {
+        for previous in tree.range(prefix..key).keys().rev() {
+            if keep(&previous?) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    })
+}
+
+/// Gets the `count` entities of `tree` whose key starts with `prefix` and comes before `cursor`.
+pub(crate) fn page_before<E: Entity>(
+    tree: &Tree,
+    cipher: &Cipher,
+    prefix: &[u8],
+    cursor: Option<&Cursor>,
+    count: usize,
+) -> Result<Page<E>> {
+    page_before_where(tree, cipher, prefix, cursor, count, &|_| true)
+}
+
+/// Same as [`page_before`], but only keeps the entities whose key is accepted by `keep`.
+pub(crate) fn page_before_where<E: Entity>(
+    tree: &Tree,
+    cipher: &Cipher,
+    prefix: &[u8],
+    cursor: Option<&Cursor>,
+    count: usize,
+    keep: &dyn Fn(&[u8]) -> bool,
+) -> Result<Page<E>> {
+    let entries: Entries = match cursor {
+        Some(cursor) if cursor.as_bytes() <= prefix => Box::new(std::iter::empty()),
+        Some(cursor) if cursor.as_bytes().starts_with(prefix) => {
+            Box::new(tree.range(prefix..cursor.as_bytes()).rev())
+        }
+        _ => Box::new(tree.scan_prefix(prefix).rev()),
+    };
+    // Nothing precedes the cursor : the next page starts with the first entity of the range
+    let empty_beyond = cursor.map(|_| Cursor(prefix.to_vec()));
+    collect_page(read(entries, prefix, cipher, keep), count, false, empty_beyond, |key| {
+        for next in tree
+            .range::<&[u8], _>((Bound::Excluded(key), Bound::Unbounded))
+            .keys()
+        {
+            let next = next?;
+            if !next.starts_with(prefix) {
+                break;
+            }
+            if keep(&next) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    })
+}
+
+fn read<'a, E: Entity>(
+    entries: Entries<'a>,
+    prefix: &'a [u8],
+    cipher: &'a Cipher,
+    keep: &'a dyn Fn(&[u8]) -> bool,
+) -> impl Iterator<Item = Result<(Vec<u8>, E)>> + 'a {
+    entries
+        .take_while(move |elem| match elem {
+            Ok((key, _)) => key.starts_with(prefix),
+            Err(_) => true,
+        })
+        .filter(move |elem| elem.as_ref().map_or(true, |(key, _)| keep(key)))
+        .map(|elem| -> Result<(Vec<u8>, E)> {
+            let (key, value) = elem?;
+            let value = cipher.open(E::store_name(), &key, value)?;
+            Ok((key.to_vec(), E::try_from_ivec(value)?))
+        })
+}
+
+/// Builds a page from the entities following (`forward`) or preceding the cursor, closest first.
+/// `has_beyond` tells if there are entities before (`forward`) or after the given key,
+/// on the side of the page opposite to the reading direction.
+/// `empty_beyond` is the cursor leading to these entities when the page is empty.
+pub(crate) fn collect_page<E>(
+    entries: impl Iterator<Item = Result<(Vec<u8>, E)>>,
+    count: usize,
+    forward: bool,
+    empty_beyond: Option<Cursor>,
+    has_beyond: impl Fn(&[u8]) -> Result<bool>,
+) -> Result<Page<E>> {
+    let mut entries = entries
+        .take(count + 1)
+        .collect::<Result<Vec<(Vec<u8>, E)>>>()?;
+    let more = entries.len() > count;
+    entries.truncate(count);
+    if !forward {
+        entries.reverse();
+    }
+    let (start, end) = (entries.first(), entries.last());
+    let (near, far) = if forward { (start, end) } else { (end, start) };
+    let beyond = match near {
+        Some((key, _)) if has_beyond(key)? => Some(Cursor(key.clone())),
+        Some(_) => None,
+        None => match empty_beyond {
+            Some(cursor) if has_beyond(cursor.as_bytes())? => Some(cursor),
+            _ => None,
+        },
+    };
+    let further = match far {
+        Some((key, _)) if more => Some(Cursor(key.clone())),
+        _ => None,
+    };
+    let (previous, next) = if forward {
+        (beyond, further)
+    } else {
+        (further, beyond)
+    };
+    Ok(Page {
+        items: entries.into_iter().map(|(_, entity)| entity).collect(),
+        next,
+        previous,
+    })
+}
@@ -1,26 +1,75 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
 use sled::Db;
 
-use crate::{relation::Relation, AsBytes, Entity, Result};
+use crate::cipher::Cipher;
+use crate::page::{self, Cursor, Page};
+use crate::{relation::Relation, AsBytes, Entity, FromBytes, Result};
 
 /// `QueryBuilder` is a convenient way to build query to target several conditions without the need to immediately
 /// serialize/deserialize data from disk.
 /// For simplicity's sake, ids are expressed as `&[u8]`. To get the key of an entity using this type,
 /// just use `&entity.get_key().as_bytes()`.
+/// Ids must have the exact type of the key of the entity they designate : `2i32` and `2u32` are not encoded the same way.
+///
+/// Conditions combine : an entity must match every relation condition, one of the alternatives added to a condition
+/// with the `or_` methods, and none of the relation conditions added with the `without_` methods.
 ///
-/// Execute the query with `get` or `get_single`, providing the Db instance to run it on.
+/// Execute the query with `get` or `get_single`, providing the Db instance to run it on, or use [`select`](#method.select)
+/// to filter, sort and paginate the matching entities.
 /// 
 /// For instance, this will list all students older than 18 belonging to a given school and members of a given club.
 /// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize, QueryBuilder, RelationGroup};
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "school")]
+/// # #[children(Student = Cascade)]
+/// # struct School { id : u32 }
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "student")]
+/// # struct Student { id : (u32, u32), name : String, age : u32 }
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "club")]
+/// # struct Club { id : u32 }
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "event")]
+/// # struct Event { id : u32 }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # School::register(&db)?;
+/// # Student::register(&db)?;
+/// # Club::register(&db)?;
+/// # Event::register(&db)?;
+/// # let school = School { id : 1 };
+/// # school.save(&db)?;
+/// # let (school_id, club_id, chess_club_id, go_club_id, event_id) = (1u32, 1u32, 1u32, 2u32, 1u32);
+/// # let cursor : Option<reindeer::Cursor> = None;
 /// let students = QueryBuilder::new()
 ///     .with_parent(&school_id)
 ///     .with_named_relation_to::<Club>(&club_id, "member")
-///     .get_with_filter(|s : &Student| s.age > 18,&data.db)?;
+///     .get_with_filter(|s : &Student| s.age > 18,&db)?;
+/// # Ok(())
+/// # }
 /// ```
 /// 
+#[derive(Clone)]
 pub struct QueryBuilder<'a> {
     ids: Vec<Vec<u8>>,
     parent: Option<Vec<u8>>,
-    related_to: Vec<(&'a str, Vec<u8>, Option<&'a str>)>,
+    related_to: Vec<Vec<RelationCondition>>,
+    not_related_to: Vec<RelationCondition>,
+    lifetime: PhantomData<&'a str>,
+}
+
+/// A relation to an entity of a given store, with a given name or without a name.
+type RelationCondition = (&'static str, Vec<u8>, Option<String>);
+
+impl<'a> Default for QueryBuilder<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<'a> QueryBuilder<'a> {
@@ -30,6 +79,8 @@ impl<'a> QueryBuilder<'a> {
             ids: Vec::new(),
             parent: None,
             related_to: Vec::new(),
+            not_related_to: Vec::new(),
+            lifetime: PhantomData,
         }
     }
 
@@ -65,97 +116,699 @@ impl<'a> QueryBuilder<'a> {
     }
 
     /// Specifies that a named relation to another entity has to exist.
-    /// This can be used multiple times to specify several conditions.
+    /// This can be used multiple times to specify several conditions, which must all be met.
     pub fn with_named_relation_to<OT: Entity>(
         &mut self,
         id: &impl AsBytes,
         name: &'a str,
     ) -> &mut QueryBuilder<'a> {
         self.related_to
-            .push((OT::store_name(), id.as_bytes(), Some(name)));
+            .push(vec![(OT::store_name(), id.as_bytes(), Some(String::from(name)))]);
         self
     }
 
     /// Specifies that an unnamed relation to another entity has to exist.
-    /// This can be used multiple times to specify several conditions.
+    /// This can be used multiple times to specify several conditions, which must all be met.
     pub fn with_relation_to<OT: Entity>(&mut self, id: &impl AsBytes) -> &mut QueryBuilder<'a> {
+        self.related_to.push(vec![(OT::store_name(), id.as_bytes(), None)]);
+        self
+    }
+
+    /// Adds an alternative to the last relation condition : the condition is met if either a named relation to
+    /// this entity or one of the previous alternatives exists. Starts a new condition if there is none.
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, QueryBuilder, RelationGroup};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "school")]
+    /// # #[children(Student = Cascade)]
+    /// # struct School { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : (u32, u32), name : String, age : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "club")]
+    /// # struct Club { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "event")]
+    /// # struct Event { id : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # School::register(&db)?;
+    /// # Student::register(&db)?;
+    /// # Club::register(&db)?;
+    /// # Event::register(&db)?;
+    /// # let school = School { id : 1 };
+    /// # school.save(&db)?;
+    /// # let (school_id, club_id, chess_club_id, go_club_id, event_id) = (1u32, 1u32, 1u32, 2u32, 1u32);
+    /// # let cursor : Option<reindeer::Cursor> = None;
+    /// // Members of either club, who attend the event
+    /// let students = QueryBuilder::new()
+    ///     .with_named_relation_to::<Club>(&chess_club_id, "member")
+    ///     .or_named_relation_to::<Club>(&go_club_id, "member")
+    ///     .with_relation_to::<Event>(&event_id)
+    ///     .get::<Student>(&db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn or_named_relation_to<OT: Entity>(
+        &mut self,
+        id: &impl AsBytes,
+        name: &'a str,
+    ) -> &mut QueryBuilder<'a> {
+        self.add_alternative((OT::store_name(), id.as_bytes(), Some(String::from(name))));
+        self
+    }
+
+    /// Adds an alternative to the last relation condition : the condition is met if either an unnamed relation to
+    /// this entity or one of the previous alternatives exists. Starts a new condition if there is none.
+    pub fn or_relation_to<OT: Entity>(&mut self, id: &impl AsBytes) -> &mut QueryBuilder<'a> {
+        self.add_alternative((OT::store_name(), id.as_bytes(), None));
+        self
+    }
+
+    /// Specifies that a named relation to another entity must not exist.
+    ///
+    /// If this is the only kind of condition of the query, every entity of the store is considered.
+    pub fn without_named_relation_to<OT: Entity>(
+        &mut self,
+        id: &impl AsBytes,
+        name: &'a str,
+    ) -> &mut QueryBuilder<'a> {
+        self.not_related_to
+            .push((OT::store_name(), id.as_bytes(), Some(String::from(name))));
+        self
+    }
+
+    /// Specifies that an unnamed relation to another entity must not exist.
+    ///
+    /// If this is the only kind of condition of the query, every entity of the store is considered.
+    pub fn without_relation_to<OT: Entity>(&mut self, id: &impl AsBytes) -> &mut QueryBuilder<'a> {
+        self.not_related_to.push((OT::store_name(), id.as_bytes(), None));
+        self
+    }
+
+    /// Specifies that every relation of `group` has to exist.
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, QueryBuilder, RelationGroup};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "school")]
+    /// # #[children(Student = Cascade)]
+    /// # struct School { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : (u32, u32), name : String, age : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "club")]
+    /// # struct Club { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "event")]
+    /// # struct Event { id : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # School::register(&db)?;
+    /// # Student::register(&db)?;
+    /// # Club::register(&db)?;
+    /// # Event::register(&db)?;
+    /// # let school = School { id : 1 };
+    /// # school.save(&db)?;
+    /// # let (school_id, club_id, chess_club_id, go_club_id, event_id) = (1u32, 1u32, 1u32, 2u32, 1u32);
+    /// # let cursor : Option<reindeer::Cursor> = None;
+    /// let students = QueryBuilder::new()
+    ///     .all_of(RelationGroup::new().named_relation_to::<Club>(&chess_club_id, "member").relation_to::<Event>(&event_id))
+    ///     .none_of(RelationGroup::new().named_relation_to::<Club>(&go_club_id, "member"))
+    ///     .get::<Student>(&db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn all_of(&mut self, group: RelationGroup<'a>) -> &mut QueryBuilder<'a> {
         self.related_to
-            .push((OT::store_name(), id.as_bytes(), None));
+            .extend(group.conditions.into_iter().map(|condition| vec![condition]));
         self
     }
 
+    /// Specifies that at least one relation of `group` has to exist. An empty group matches nothing.
+    pub fn any_of(&mut self, group: RelationGroup<'a>) -> &mut QueryBuilder<'a> {
+        self.related_to.push(group.conditions);
+        self
+    }
+
+    /// Specifies that none of the relations of `group` must exist.
+    ///
+    /// If this is the only kind of condition of the query, every entity of the store is considered.
+    pub fn none_of(&mut self, group: RelationGroup<'a>) -> &mut QueryBuilder<'a> {
+        self.not_related_to.extend(group.conditions);
+        self
+    }
+
+    fn add_alternative(&mut self, condition: RelationCondition) {
+        match self.related_to.last_mut() {
+            Some(alternatives) => alternatives.push(condition),
+            None => self.related_to.push(vec![condition]),
+        }
+    }
+
+    /// Turns this query into a [`Query`](struct.Query.html) on the entities of `T`, which can be filtered,
+    /// sorted and paginated.
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, QueryBuilder, RelationGroup};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "school")]
+    /// # #[children(Student = Cascade)]
+    /// # struct School { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : (u32, u32), name : String, age : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "club")]
+    /// # struct Club { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "event")]
+    /// # struct Event { id : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # School::register(&db)?;
+    /// # Student::register(&db)?;
+    /// # Club::register(&db)?;
+    /// # Event::register(&db)?;
+    /// # let school = School { id : 1 };
+    /// # school.save(&db)?;
+    /// # let (school_id, club_id, chess_club_id, go_club_id, event_id) = (1u32, 1u32, 1u32, 2u32, 1u32);
+    /// # let cursor : Option<reindeer::Cursor> = None;
+    /// let names = QueryBuilder::new()
+    ///     .with_parent(&school_id)
+    ///     .select::<Student>()
+    ///     .filter(|s| s.age > 18)
+    ///     .order_by(|s| s.age)
+    ///     .limit(10)
+    ///     .map(|s| s.name, &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn select<T: Entity>(&self) -> Query<'a, T> {
+        Query {
+            builder: self.clone(),
+            filters: Vec::new(),
+            orderings: Vec::new(),
+            offset: 0,
+            limit: None,
+        }
+    }
+
     /// Executes the query and returns the result as a Vec of the chosen entity.
     pub fn get<T: Entity>(&self, db: &Db) -> Result<Vec<T>> {
-        Ok(T::get_each_u8(&self.get_ids::<T>(db)?, db))
+        self.iter::<T>(db)?.collect()
+    }
+
+    /// Executes the query and returns a lazy iterator over the matching entities.
+    ///
+    /// Only the keys of the matching entities are computed up front : entities are read and deserialized
+    /// when the iterator is advanced. Keys of related entities that no longer exist are skipped.
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, QueryBuilder, RelationGroup};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "school")]
+    /// # #[children(Student = Cascade)]
+    /// # struct School { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : (u32, u32), name : String, age : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "club")]
+    /// # struct Club { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "event")]
+    /// # struct Event { id : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # School::register(&db)?;
+    /// # Student::register(&db)?;
+    /// # Club::register(&db)?;
+    /// # Event::register(&db)?;
+    /// # let school = School { id : 1 };
+    /// # school.save(&db)?;
+    /// # let (school_id, club_id, chess_club_id, go_club_id, event_id) = (1u32, 1u32, 1u32, 2u32, 1u32);
+    /// # let cursor : Option<reindeer::Cursor> = None;
+    /// for student in QueryBuilder::new().with_parent(&school_id).iter::<Student>(&db)? {
+    ///     let student = student?;
+    ///     /* ... */
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn iter<'b, T: Entity>(
+        &self,
+        db: &'b Db,
+    ) -> Result<impl DoubleEndedIterator<Item = Result<T>> + 'b> {
+        Ok(self
+            .get_ids::<T>(db)?
+            .into_iter()
+            .filter_map(move |id| T::get_from_u8_array(&id, db).transpose()))
+    }
+
+    /// Executes the query and returns the keys of the matching entities, without fetching the entities.
+    ///
+    /// ⚠ Entities are not read from the database, so keys of related entities that no longer exist may be returned.
+    pub fn get_keys<T: Entity>(&self, db: &Db) -> Result<Vec<T::Key>> {
+        self.get_ids::<T>(db)?
+            .iter()
+            .map(|id| T::Key::from_bytes(id))
+            .collect()
     }
 
     /// Executes the query, filters it given a filter function, and returns the result as a Vec of the chosen entity.
     /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, QueryBuilder, RelationGroup};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "school")]
+    /// # #[children(Student = Cascade)]
+    /// # struct School { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : (u32, u32), name : String, age : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "club")]
+    /// # struct Club { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "event")]
+    /// # struct Event { id : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # School::register(&db)?;
+    /// # Student::register(&db)?;
+    /// # Club::register(&db)?;
+    /// # Event::register(&db)?;
+    /// # let school = School { id : 1 };
+    /// # school.save(&db)?;
+    /// # let (school_id, club_id, chess_club_id, go_club_id, event_id) = (1u32, 1u32, 1u32, 2u32, 1u32);
+    /// # let cursor : Option<reindeer::Cursor> = None;
     /// let students = QueryBuilder::new()
     ///     .with_parent(&school_id)
-    ///     .get_with_filter(|s : &Student| s.age > 18,&data.db)?;
+    ///     .get_with_filter(|s : &Student| s.age > 18,&db)?;
+    /// # Ok(())
+    /// # }
     /// ```
     /// Note that we precise the type of the closure's parameter to let Rust infer the generic types of this function.
     pub fn get_with_filter<T : Entity, F : Fn(&T) -> bool>(&self, filter : F, db : &Db) -> Result<Vec<T>>  {
-        let result = self.get::<T>(db)?;
-        Ok(result.into_iter().filter(filter).collect())
+        self.iter::<T>(db)?
+            .filter(|e| e.as_ref().map_or(true, &filter))
+            .collect()
     }
 
-    /// Executes the query and returns the first entity matching the query.
+    /// Executes the query and returns the `count` matching entities following `cursor` in key order,
+    /// or the first `count` ones if `cursor` is `None`. See [`Entity::page_after`](entity/trait.Entity.html#method.page_after).
+    ///
+    /// Queries with only a parent or `without_` conditions read the store from the cursor on.
+    /// Otherwise, the ids matching the relation conditions are read and sorted before the page is cut.
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, QueryBuilder, RelationGroup};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "school")]
+    /// # #[children(Student = Cascade)]
+    /// # struct School { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "student")]
+    /// # struct Student { id : (u32, u32), name : String, age : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "club")]
+    /// # struct Club { id : u32 }
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "event")]
+    /// # struct Event { id : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # School::register(&db)?;
+    /// # Student::register(&db)?;
+    /// # Club::register(&db)?;
+    /// # Event::register(&db)?;
+    /// # let school = School { id : 1 };
+    /// # school.save(&db)?;
+    /// # let (school_id, club_id, chess_club_id, go_club_id, event_id) = (1u32, 1u32, 1u32, 2u32, 1u32);
+    /// # let cursor : Option<reindeer::Cursor> = None;
+    /// let page = QueryBuilder::new()
+    ///     .with_parent(&school_id)
+    ///     .page_after::<Student>(cursor.as_ref(), 20, &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn page_after<T: Entity>(
+        &self,
+        cursor: Option<&Cursor>,
+        count: usize,
+        db: &Db,
+    ) -> Result<Page<T>> {
+        if let Some(prefix) = self.seekable_prefix() {
+            let excluded = self.get_excluded_ids::<T>(db)?;
+            return page::page_after_where(
+                &T::get_tree(db)?,
+                &Cipher::of(db)?,
+                prefix,
+                cursor,
+                count,
+                &|key| !excluded.contains(key),
+            );
+        }
+        let ids = self.get_sorted_ids::<T>(db)?;
+        let start = cursor.map_or(0, |c| ids.partition_point(|id| id.as_slice() <= c.as_bytes()));
+        let tree = T::get_tree(db)?;
+        page::collect_page(
+            Self::fetch::<T>(ids[start..].iter(), db),
+            count,
+            true,
+            cursor.map(Cursor::successor),
+            |key| {
+                for id in ids[..ids.partition_point(|id| id.as_slice() < key)].iter() {
+                    if tree.contains_key(id)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+        )
+    }
+
+    /// Executes the query and returns the `count` matching entities preceding `cursor` in key order,
+    /// or the last `count` ones if `cursor` is `None`. See [`Entity::page_before`](entity/trait.Entity.html#method.page_before).
+    pub fn page_before<T: Entity>(
+        &self,
+        cursor: Option<&Cursor>,
+        count: usize,
+        db: &Db,
+    ) -> Result<Page<T>> {
+        if let Some(prefix) = self.seekable_prefix() {
+            let excluded = self.get_excluded_ids::<T>(db)?;
+            return page::page_before_where(
+                &T::get_tree(db)?,
+                &Cipher::of(db)?,
+                prefix,
+                cursor,
+                count,
+                &|key| !excluded.contains(key),
+            );
+        }
+        let ids = self.get_sorted_ids::<T>(db)?;
+        let end = cursor.map_or(ids.len(), |c| ids.partition_point(|id| id.as_slice() < c.as_bytes()));
+        let tree = T::get_tree(db)?;
+        page::collect_page(
+            Self::fetch::<T>(ids[..end].iter().rev(), db),
+            count,
+            false,
+            cursor.map(|_| Cursor::start()),
+            |key| {
+                for id in ids[ids.partition_point(|id| id.as_slice() <= key)..].iter() {
+                    if tree.contains_key(id)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            },
+        )
+    }
+
+    fn fetch<'b, T: Entity>(
+        ids: impl Iterator<Item = &'b Vec<u8>> + 'b,
+        db: &'b Db,
+    ) -> impl Iterator<Item = Result<(Vec<u8>, T)>> + 'b {
+        ids.filter_map(move |id| {
+            T::get_from_u8_array(id, db)
+                .map(|e| e.map(|e| (id.clone(), e)))
+                .transpose()
+        })
+    }
+
+    /// Returns the prefix of the keys matching this query if they can be read from the store itself,
+    /// that is when the query has no id nor relation condition to intersect, so that pages can seek to their cursor.
+    fn seekable_prefix(&self) -> Option<&[u8]> {
+        if self.ids.is_empty()
+            && self.related_to.is_empty()
+            && (self.parent.is_some() || !self.not_related_to.is_empty())
+        {
+            Some(self.parent.as_deref().unwrap_or_default())
+        } else {
+            None
+        }
+    }
+
+    fn get_sorted_ids<T: Entity>(&self, db: &Db) -> Result<Vec<Vec<u8>>> {
+        let mut ids = self.get_ids::<T>(db)?;
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Executes the query and returns the first entity matching the query, or `None` if there is none.
     pub fn get_single<T: Entity>(&self, db: &Db) -> Result<Option<T>> {
-        T::get_from_u8_array(&self.get_ids::<T>(db)?[0], db)
+        self.iter::<T>(db)?.next().transpose()
+    }
+
+    /// Returns a copy of this query that does not borrow anything, so that it can be sent to another thread.
+    #[cfg(feature = "async")]
+    pub(crate) fn to_owned_query(&self) -> QueryBuilder<'static> {
+        QueryBuilder {
+            ids: self.ids.clone(),
+            parent: self.parent.clone(),
+            related_to: self.related_to.clone(),
+            not_related_to: self.not_related_to.clone(),
+            lifetime: PhantomData,
+        }
     }
 
     fn get_ids<T: Entity>(&self, db: &Db) -> Result<Vec<Vec<u8>>> {
-        let target_ids = match (self.ids.len(), self.related_to.len(), &self.parent) {
-            (0, 0, None) => {
-                return Ok(Vec::new());
-            }
-            (0, 0, Some(p)) => {
-                let mut result: Vec<Vec<u8>> = Vec::new();
-                T::get_tree(db)?.scan_prefix(p).for_each(|elem| {
-                    if let Ok((key, _)) = elem {
-                        result.push(key.to_vec());
-                    }
-                });
-                return Ok(result);
+        let mut lists = Vec::new();
+        if !self.ids.is_empty() {
+            lists.push(self.ids.clone());
+        }
+        for alternatives in &self.related_to {
+            let mut related_ids = Vec::new();
+            for condition in alternatives {
+                related_ids.extend(Self::get_related_ids::<T>(condition, db)?);
             }
-            (0, _, _) => self.get_related_ids::<T>(db)?,
-            (_, _, _) => {
-                let related_ids = self.get_related_ids::<T>(db)?;
-                let mut target_ids = Vec::new();
-                for related_id in related_ids {
-                    if self.ids.contains(&related_id.as_ref()) {
-                        target_ids.push(related_id.clone());
-                    }
-                }
-                target_ids
+            if related_ids.is_empty() {
+                return Ok(Vec::new());
             }
+            lists.push(related_ids);
+        }
+        // The intersection starts from the smallest list, so that as few ids as possible are looked up
+        lists.sort_by_key(Vec::len);
+        let mut lists = lists.into_iter();
+        let candidates = lists.next().map(|smallest| {
+            lists.fold(smallest, |ids, list| {
+                let list = list.into_iter().collect::<HashSet<Vec<u8>>>();
+                ids.into_iter().filter(|id| list.contains(id)).collect()
+            })
+        });
+        let mut target_ids = match (candidates, &self.parent) {
+            (Some(ids), Some(parent)) => ids.into_iter().filter(|id| id.starts_with(parent)).collect(),
+            (Some(ids), None) => ids,
+            (None, Some(parent)) => T::get_tree(db)?
+                .scan_prefix(parent)
+                .keys()
+                .map(|key| key.map(|key| key.to_vec()))
+                .collect::<std::result::Result<Vec<Vec<u8>>, sled::Error>>()?,
+            (None, None) if !self.not_related_to.is_empty() => T::get_tree(db)?
+                .iter()
+                .keys()
+                .map(|key| key.map(|key| key.to_vec()))
+                .collect::<std::result::Result<Vec<Vec<u8>>, sled::Error>>()?,
+            (None, None) => return Ok(Vec::new()),
         };
+        let excluded = self.get_excluded_ids::<T>(db)?;
+        let mut seen = HashSet::new();
+        target_ids.retain(|id| !excluded.contains(id) && seen.insert(id.clone()));
+        Ok(target_ids)
+    }
 
-        if let Some(parent) = &self.parent {
-            Ok(target_ids
-                .iter()
-                .filter(|id| id.starts_with(parent))
-                .cloned()
-                .collect())
+    fn get_excluded_ids<T: Entity>(&self, db: &Db) -> Result<HashSet<Vec<u8>>> {
+        let mut excluded = HashSet::new();
+        for condition in &self.not_related_to {
+            excluded.extend(Self::get_related_ids::<T>(condition, db)?);
+        }
+        Ok(excluded)
+    }
+
+    fn get_related_ids<T: Entity>(
+        (tree_name, id, relation_name): &RelationCondition,
+        db: &Db,
+    ) -> Result<Vec<Vec<u8>>> {
+        Ok(Relation::links_to_store_with_tree_names(tree_name, id, T::store_name(), db)?
+            .into_iter()
+            .filter(|rel| rel.name == *relation_name)
+            .map(|rel| rel.key)
+            .collect())
+    }
+}
+
+/// A group of relation conditions, combined with [`QueryBuilder::all_of`](struct.QueryBuilder.html#method.all_of),
+/// [`QueryBuilder::any_of`](struct.QueryBuilder.html#method.any_of) or [`QueryBuilder::none_of`](struct.QueryBuilder.html#method.none_of).
+#[derive(Clone, Default)]
+pub struct RelationGroup<'a> {
+    conditions: Vec<RelationCondition>,
+    lifetime: PhantomData<&'a str>,
+}
+
+impl<'a> RelationGroup<'a> {
+    /// Creates an empty group.
+    pub fn new() -> RelationGroup<'a> {
+        RelationGroup::default()
+    }
+
+    /// Adds an unnamed relation to another entity to this group.
+    pub fn relation_to<OT: Entity>(mut self, id: &impl AsBytes) -> RelationGroup<'a> {
+        self.conditions.push((OT::store_name(), id.as_bytes(), None));
+        self
+    }
+
+    /// Adds a named relation to another entity to this group.
+    pub fn named_relation_to<OT: Entity>(mut self, id: &impl AsBytes, name: &'a str) -> RelationGroup<'a> {
+        self.conditions
+            .push((OT::store_name(), id.as_bytes(), Some(String::from(name))));
+        self
+    }
+}
+
+type Filter<'a, T> = Box<dyn Fn(&T) -> bool + 'a>;
+type Comparator<'a, T> = Box<dyn Fn(&T, &T) -> Ordering + 'a>;
+
+/// A query on the entities of `T`, obtained with [`QueryBuilder::select`](struct.QueryBuilder.html#method.select).
+///
+/// Matching entities are filtered with every function given to [`filter`](#method.filter), sorted with the
+/// orderings given to the `order_by` methods, the first ones being applied first, and in key order otherwise.
+/// [`offset`](#method.offset) and [`limit`](#method.limit) are applied last.
+pub struct Query<'a, T: Entity> {
+    builder: QueryBuilder<'a>,
+    filters: Vec<Filter<'a, T>>,
+    orderings: Vec<Comparator<'a, T>>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl<'a, T: Entity> Query<'a, T> {
+    /// Only keeps the entities for which `filter` returns `true`. This can be used multiple times.
+    pub fn filter(mut self, filter: impl Fn(&T) -> bool + 'a) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Sorts the entities by the value returned by `field`, in ascending order.
+    /// Entities with the same value are sorted by the next orderings.
+    pub fn order_by<K: Ord>(mut self, field: impl Fn(&T) -> K + 'a) -> Self {
+        self.orderings.push(Box::new(move |a, b| field(a).cmp(&field(b))));
+        self
+    }
+
+    /// Sorts the entities by the value returned by `field`, in descending order.
+    /// Entities with the same value are sorted by the next orderings.
+    pub fn order_by_desc<K: Ord>(mut self, field: impl Fn(&T) -> K + 'a) -> Self {
+        self.orderings.push(Box::new(move |a, b| field(b).cmp(&field(a))));
+        self
+    }
+
+    /// Sorts the entities by key, in ascending order.
+    pub fn order_by_key(mut self) -> Self {
+        self.orderings
+            .push(Box::new(|a, b| a.get_key().as_bytes().cmp(&b.get_key().as_bytes())));
+        self
+    }
+
+    /// Sorts the entities by key, in descending order.
+    pub fn order_by_key_desc(mut self) -> Self {
+        self.orderings
+            .push(Box::new(|a, b| b.get_key().as_bytes().cmp(&a.get_key().as_bytes())));
+        self
+    }
+
+    /// Skips the first `offset` entities.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns at most `limit` entities.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Executes the query and returns the matching entities.
+    pub fn get(&self, db: &Db) -> Result<Vec<T>> {
+        self.run(self.limit, db)
+    }
+
+    /// Executes the query and returns the first matching entity, if any.
+    pub fn first(&self, db: &Db) -> Result<Option<T>> {
+        Ok(self.run(Some(self.limit.map_or(1, |limit| limit.min(1))), db)?.pop())
+    }
+
+    /// Executes the query and returns the keys of the matching entities.
+    pub fn keys(&self, db: &Db) -> Result<Vec<T::Key>> {
+        Ok(self.get(db)?.iter().map(|e| e.get_key().clone()).collect())
+    }
+
+    /// Executes the query and returns the value computed by `projection` for each matching entity.
+    pub fn map<R>(&self, projection: impl Fn(T) -> R, db: &Db) -> Result<Vec<R>> {
+        Ok(self.get(db)?.into_iter().map(projection).collect())
+    }
+
+    /// Executes the query and returns the number of matching entities, within `offset` and `limit`.
+    ///
+    /// Entities are only read from the database if the query has filters.
+    pub fn count(&self, db: &Db) -> Result<usize> {
+        if !self.filters.is_empty() {
+            return Ok(self.run(self.limit, db)?.len());
+        }
+        let tree = T::get_tree(db)?;
+        let mut count = 0usize;
+        for id in self.builder.get_ids::<T>(db)? {
+            if tree.contains_key(id)? {
+                count += 1;
+            }
+        }
+        let count = count.saturating_sub(self.offset);
+        Ok(self.limit.map_or(count, |limit| count.min(limit)))
+    }
+
+    /// Executes the query and checks if at least one entity matches it.
+    pub fn exists(&self, db: &Db) -> Result<bool> {
+        if self.filters.is_empty() {
+            Ok(self.count(db)? > 0)
         } else {
-            Ok(target_ids)
+            Ok(self.first(db)?.is_some())
         }
     }
 
-    fn get_related_ids<T: Entity>(&self, db: &Db) -> Result<Vec<Vec<u8>>> {
-        let mut target_ids = Vec::new();
-        for (tree_name, id, relation_name) in &self.related_to {
-            let descriptor = Relation::get_descriptor_with_key_and_tree_name(tree_name, id, db)?;
-            if let Some(list) = descriptor.related_entities.get(T::store_name()) {
-                for rel in list {
-                    if rel.name.as_deref() == relation_name.as_deref() {
-                        target_ids.push(rel.key.clone());
-                    }
-                }
+    fn run(&self, limit: Option<usize>, db: &Db) -> Result<Vec<T>> {
+        let ids = self.builder.get_sorted_ids::<T>(db)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        let mut entities = Vec::new();
+        // Without orderings, entities come in key order : paging can be applied while reading them
+        let (mut skip, take) = if self.orderings.is_empty() {
+            (self.offset, limit)
+        } else {
+            (0, usize::MAX)
+        };
+        for elem in QueryBuilder::fetch::<T>(ids.iter(), db) {
+            if entities.len() >= take {
+                break;
+            }
+            let (_, entity) = elem?;
+            if !self.filters.iter().all(|filter| filter(&entity)) {
+                continue;
             }
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            entities.push(entity);
         }
-        Ok(target_ids)
+        if self.orderings.is_empty() {
+            return Ok(entities);
+        }
+        entities.sort_by(|a, b| {
+            self.orderings
+                .iter()
+                .map(|ordering| ordering(a, b))
+                .find(|order| order.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+        Ok(entities.into_iter().skip(self.offset).take(limit).collect())
     }
 }
@@ -0,0 +1,206 @@
+//! # Registry Module
+//! This module keeps track of the entities implementing `Entity` through the `derive` macro, so that they can
+//! all be registered at once with [`register_all`](fn.register_all.html), and provides a strict mode where saving
+//! an entity to a store that has not been registered fails right away, as well as a schema validation mode where
+//! registering entities that make the schema inconsistent fails.
+//!
+//! ### Example
+//! ```rust,no_run
+//! # fn main() -> reindeer::Result<()> {
+//! let db = reindeer::open("./my-db")?;
+//! reindeer::set_strict(&db, true)?;
+//! reindeer::set_schema_validation(&db, true)?;
+//! reindeer::register_all(&db)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use sled::Db;
+
+use crate::error::Result;
+use crate::key::AsBytes;
+use crate::layout;
+use crate::relation::FamilyDescriptor;
+use crate::schema::{self, SchemaIssue};
+use crate::state;
+use crate::storage::Storage;
+use crate::{Entity, Error, ErrorKind};
+
+/// Prefix of the names of the trees used internally by `reindeer`.
+pub(crate) const INTERNAL_STORE_PREFIX: &str = "__$";
+
+/// An entity submitted to the registry by the `derive` macro.
+#[doc(hidden)]
+pub struct EntityRegistration {
+    store_name: fn() -> &'static str,
+    version: fn() -> u32,
+    family: fn() -> FamilyDescriptor,
+    register: fn(&Db) -> Result<()>,
+}
+
+impl EntityRegistration {
+    pub const fn new<E: Entity>() -> EntityRegistration {
+        EntityRegistration {
+            store_name: E::store_name,
+            version: E::version,
+            family: FamilyDescriptor::of::<E>,
+            register: E::register,
+        }
+    }
+}
+
+inventory::collect!(EntityRegistration);
+
+/// Incremented each time families are written to any database, so that the families cached by
+/// [`Store`](struct.Store.html) handles are read again.
+static FAMILIES_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Registers every entity of the binary that implements `Entity` through the `derive` macro, in store name order.
+///
+/// When several entities share the same store, as the previous versions of an entity listed in its `migrations`
+/// attribute do, only the one with the highest version is registered. Two entities with the same store and version
+/// result in an [`ErrorKind::IntegrityError`](enum.ErrorKind.html), and nothing is registered.
+///
+/// In [schema validation mode](fn.set_schema_validation.html), the schema made of the families already registered
+/// and of the families about to be registered is checked first with the same rules as
+/// [`validate_schema`](fn.validate_schema.html). If it is inconsistent, an
+/// [`ErrorKind::IntegrityError`](enum.ErrorKind.html) is returned and nothing is registered.
+///
+/// Generic entities and entities implementing `Entity` manually are not known to the registry,
+/// and must still be registered with [`Entity::register`](trait.Entity.html#method.register),
+/// before calling `register_all` in strict or schema validation mode.
+pub fn register_all(db: &Db) -> Result<()> {
+    let mut latest: HashMap<&str, &EntityRegistration> = HashMap::new();
+    for registration in inventory::iter::<EntityRegistration> {
+        let store_name = (registration.store_name)();
+        let version = (registration.version)();
+        match latest.get(store_name).map(|other| (other.version)()) {
+            Some(other_version) if other_version > version => {}
+            Some(other_version) if other_version == version => {
+                return Err(Error::new(
+                    ErrorKind::IntegrityError,
+                    format!(
+                        "Several entities are declared for store {} at version {}",
+                        store_name, version
+                    ),
+                ));
+            }
+            _ => {
+                latest.insert(store_name, registration);
+            }
+        }
+    }
+    let mut registrations = latest.into_iter().collect::<Vec<(&str, &EntityRegistration)>>();
+    registrations.sort_by_key(|(store_name, _)| *store_name);
+    if validates_schema(db) {
+        let families = registrations
+            .iter()
+            .map(|(_, registration)| (registration.family)())
+            .collect::<Vec<FamilyDescriptor>>();
+        check_schema(&families, false, db)?;
+    }
+    for (_, registration) in registrations {
+        (registration.register)(db)?;
+    }
+    Ok(())
+}
+
+/// Checks the schema `db` would have once `families` are registered, returning an error if it is inconsistent.
+///
+/// When `partial` is set, only the inconsistencies brought by `families` are reported, and stores that are not
+/// registered yet are not, as the entities of a schema are registered one at a time.
+pub(crate) fn check_schema(families: &[FamilyDescriptor], partial: bool, db: &Db) -> Result<()> {
+    layout::upgrade::<FamilyDescriptor>(db)?;
+    let registered = FamilyDescriptor::get_all(db)?;
+    let mut schema = registered
+        .iter()
+        .filter(|family| !families.iter().any(|other| other.tree_name == family.tree_name))
+        .cloned()
+        .collect::<Vec<FamilyDescriptor>>();
+    schema.extend(families.iter().cloned());
+    let mut issues = schema::validate_families(&schema).issues;
+    if partial {
+        let known = schema::validate_families(&registered).issues;
+        issues.retain(|issue| {
+            !matches!(issue, SchemaIssue::MissingStore { .. }) && !known.contains(issue)
+        });
+    }
+    if issues.is_empty() {
+        return Ok(());
+    }
+    Err(Error::new(
+        ErrorKind::IntegrityError,
+        format!(
+            "Inconsistent schema : {}",
+            issues
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect::<Vec<String>>()
+                .join(" ; ")
+        ),
+    ))
+}
+
+/// Turns strict mode on or off for `db`. In strict mode, saving an entity to a store that has not been registered
+/// results in an [`ErrorKind::UnregisteredEntity`](enum.ErrorKind.html) error instead of writing it.
+///
+/// Strict mode is not persisted : it must be set again each time the database is opened.
+pub fn set_strict(db: &Db, strict: bool) -> Result<()> {
+    state::of(db)?.strict.store(strict, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Checks whether `db` is in strict mode.
+pub fn is_strict(db: &Db) -> bool {
+    state::get(db).is_some_and(|state| state.strict.load(Ordering::SeqCst))
+}
+
+/// Turns schema validation mode on or off for `db`, independently of [strict mode](fn.set_strict.html).
+///
+/// In schema validation mode, [`register_all`](fn.register_all.html) checks the whole schema before registering
+/// anything, and [`Entity::register`](trait.Entity.html#method.register) fails with an
+/// [`ErrorKind::IntegrityError`](enum.ErrorKind.html) if the family of the entity brings an inconsistency
+/// reported by [`validate_schema`](fn.validate_schema.html), other than stores that are not registered yet.
+///
+/// Schema validation mode is not persisted : it must be set again each time the database is opened.
+pub fn set_schema_validation(db: &Db, validate: bool) -> Result<()> {
+    state::of(db)?.schema_validation.store(validate, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Checks whether `db` is in schema validation mode.
+pub fn validates_schema(db: &Db) -> bool {
+    state::get(db).is_some_and(|state| state.schema_validation.load(Ordering::SeqCst))
+}
+
+/// Records that families have been written, once they are.
+pub(crate) fn families_changed() {
+    FAMILIES_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// The number of times families have been written. Read it before reading a family to cache it.
+pub(crate) fn families_generation() -> u64 {
+    FAMILIES_GENERATION.load(Ordering::SeqCst)
+}
+
+/// Fails if `db` is in strict mode and the store of `E` has not been registered, reading through `storage`.
+/// The internal stores of `reindeer` are never registered, and always accepted.
+pub(crate) fn check_registered<E: Entity>(db: &Db, storage: &impl Storage) -> Result<()> {
+    if is_strict(db)
+        && !E::store_name().starts_with(INTERNAL_STORE_PREFIX)
+        && !storage.contains_key(
+            FamilyDescriptor::store_name(),
+            &AsBytes::as_bytes(&String::from(E::store_name())),
+        )?
+    {
+        return Err(Error::new(
+            ErrorKind::UnregisteredEntity,
+            format!("Trying to save unregistered entity {}", E::store_name()),
+        ));
+    }
+    Ok(())
+}
+
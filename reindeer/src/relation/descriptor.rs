@@ -3,8 +3,10 @@ use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::hash::BuildHasherDefault;
 
-use crate::entity::AsBytes;
 use crate::Entity;
+use crate::error::Result;
+use crate::key::AsBytes;
+use crate::storage::Storage;
 
 use super::DeletionBehaviour;
 
@@ -23,10 +25,13 @@ pub struct RelationDescriptor {
     pub key: Vec<u8>,
     pub deletion_behaviour: DeletionBehaviour,
     pub name: Option<String>,
+    /// Data stored on the link, serialized with `bincode`
+    #[serde(default)]
+    pub data: Option<Vec<u8>>,
 }
 
 impl RelationDescriptor {
-    fn new(
+    pub(crate) fn new(
         key: &[u8],
         deletion_behaviour: DeletionBehaviour,
         name: Option<&str>,
@@ -35,30 +40,48 @@ impl RelationDescriptor {
             key: key.to_owned(),
             deletion_behaviour,
             name: name.map(|s| s.to_owned()),
+            data: None,
         }
     }
 }
 
 #[doc(hidden)]
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct FamilyDescriptor {
     pub tree_name: String,
     pub sibling_trees: Vec<(String, DeletionBehaviour)>,
     pub child_trees: Vec<(String, DeletionBehaviour)>,
 }
 
-#[doc(hidden)]
-impl EntityRelations {
-    pub fn add_related<E: Entity>(
-        &mut self,
-        e: &E,
-        behaviour: DeletionBehaviour,
-        name: Option<&str>,
-    ) {
-        let key = e.get_key().as_bytes();
-        self.add_related_by_key(E::store_name(), &key, behaviour, name);
+impl FamilyDescriptor {
+    /// The family descriptor declared by `E`.
+    pub(crate) fn of<E: Entity>() -> FamilyDescriptor {
+        FamilyDescriptor {
+            tree_name: String::from(E::store_name()),
+            child_trees: E::get_child_stores()
+                .iter()
+                .map(|e| (String::from(e.0), e.1))
+                .collect(),
+            sibling_trees: E::get_sibling_stores()
+                .iter()
+                .map(|e| (String::from(e.0), e.1))
+                .collect(),
+        }
     }
 
+    pub(crate) fn get_from_storage(
+        tree_name: &str,
+        db: &impl Storage,
+    ) -> Result<Option<FamilyDescriptor>> {
+        let key = AsBytes::as_bytes(&String::from(tree_name));
+        db.get(Self::store_name(), &key)?
+            .map(|value| Self::try_from_ivec(db.cipher()?.open(Self::store_name(), &key, value)?))
+            .transpose()
+    }
+}
+
+#[doc(hidden)]
+impl EntityRelations {
     pub fn add_related_by_key(
         &mut self,
         tree_name: &str,
@@ -78,52 +101,6 @@ impl EntityRelations {
             );
         }
     }
-
-    pub fn remove_related_by_key<E: Entity>(&mut self, e: &[u8]) {
-        self.remove_related_by_key_and_tree_name(E::store_name(), e)
-    }
-
-    pub fn remove_related_by_key_with_name<E: Entity>(&mut self, e: &[u8], name: &str) {
-        self.remove_related_by_key_and_tree_name_with_name(E::store_name(), e, name)
-    }
-
-    pub fn remove_related_by_key_and_tree_name(&mut self, tree: &str, e: &[u8]) {
-        if let Some(v) = self.related_entities.get_mut(tree) {
-            v.retain(|rd| rd.key.to_ascii_lowercase() != e.to_ascii_lowercase());
-        }
-    }
-
-    pub fn remove_related_by_key_and_tree_name_with_name(
-        &mut self,
-        tree: &str,
-        e: &[u8],
-        name: &str,
-    ) {
-        if let Some(v) = self.related_entities.get_mut(tree) {
-            v.retain(|rd| {
-                rd.key.to_ascii_lowercase() != e.to_ascii_lowercase()
-                    && if let Some(r_name) = &rd.name {
-                        name == r_name
-                    } else {
-                        false
-                    }
-            });
-        }
-    }
-
-    pub fn replace_id(&mut self, tree: &str, old_id: &[u8], new_id: &[u8]) {
-        self.related_entities
-            .iter_mut()
-            .for_each(|(tree_name, entities)| {
-                if tree_name == tree {
-                    entities.iter_mut().for_each(|desc| {
-                        if desc.key == old_id {
-                            desc.key = new_id.to_owned();
-                        }
-                    });
-                }
-            })
-    }
 }
 
 #[doc(hidden)]
@@ -0,0 +1,206 @@
+//! Every link between two entities is stored under its own key, in the edge tree of the store of the
+//! entity it starts from. Edge keys are the concatenation of the key of the entity, the name of the related store,
+//! the key of the related entity and the relation name, so that the links of an entity, its links to a store
+//! and its links to another entity are all prefix ranges. Values hold the deletion behaviour of the link.
+//!
+//! Entity keys are written as `Vec<u8>` rather than in their own encoding, so that edge keys can be read back
+//! without knowing the type of the entities.
+
+use std::collections::HashMap;
+
+use sled::transaction::{ConflictableTransactionError, TransactionError};
+use sled::{Db, IVec, Transactional, Tree};
+
+use crate::cipher::Cipher;
+use crate::error::Result;
+use crate::fsck;
+use crate::key::{self, AsBytes, FromBytes};
+use crate::layout::{self, Rewrites};
+use crate::state;
+use crate::{Entity, Error};
+
+use super::{DeletionBehaviour, Relation, RelationDescriptor};
+
+/// Prefix of the trees where relation descriptors were stored as a single value per entity.
+const LEGACY_TREE_PREFIX: &str = "__$rel_";
+
+/// Layout of the relation descriptors written by previous versions : the key, deletion behaviour
+/// and name of every link of an entity, by related store.
+type LegacyRelations = HashMap<String, Vec<(Vec<u8>, DeletionBehaviour, Option<String>)>>;
+
+/// Prefix of every link of the entity with key `key`.
+pub(crate) fn entity_prefix(key: &[u8]) -> Vec<u8> {
+    key::escape(key)
+}
+
+/// Prefix of every link of the entity with key `key` to the entities of `related_tree`.
+pub(crate) fn store_prefix(key: &[u8], related_tree: &str) -> Vec<u8> {
+    [entity_prefix(key), AsBytes::as_bytes(&String::from(related_tree))].concat()
+}
+
+/// Prefix of every link of the entity with key `key` to the entity of `related_tree` with key `related_key`,
+/// whatever its name.
+pub(crate) fn link_prefix(key: &[u8], related_tree: &str, related_key: &[u8]) -> Vec<u8> {
+    [store_prefix(key, related_tree), key::escape(related_key)].concat()
+}
+
+/// Key of the link named `name` from the entity with key `key` to the entity of `related_tree` with key `related_key`.
+pub(crate) fn key(key: &[u8], related_tree: &str, related_key: &[u8], name: Option<&str>) -> Vec<u8> {
+    [
+        link_prefix(key, related_tree, related_key),
+        name.map(String::from).as_bytes(),
+    ]
+    .concat()
+}
+
+/// The parts of an edge key : the key of the entity, the related store, the key of the related entity,
+/// and the relation name.
+pub(crate) type EdgeKey = (Vec<u8>, String, Vec<u8>, Option<String>);
+
+/// Reads an edge key.
+pub(crate) fn decode_key(mut edge: &[u8]) -> Result<EdgeKey> {
+    let key = key::unescape(&mut edge)?;
+    let related_tree = String::read_bytes(&mut edge)?;
+    let related_key = key::unescape(&mut edge)?;
+    Ok((key, related_tree, related_key, Option::<String>::from_bytes(edge)?))
+}
+
+/// Reads an edge and its opened value as the related store and the link it holds.
+pub(crate) fn decode(edge: &[u8], value: &[u8]) -> Result<(String, RelationDescriptor)> {
+    let (_, related_tree, related_key, name) = decode_key(edge)?;
+    let (behaviour, data) = bincode::deserialize::<(DeletionBehaviour, Option<Vec<u8>>)>(value)?;
+    let mut rd = RelationDescriptor::new(&related_key, behaviour, name.as_deref());
+    rd.data = data;
+    Ok((related_tree, rd))
+}
+
+/// Encodes the value of an edge, holding the deletion behaviour and the data of the link, before it is sealed.
+pub(crate) fn encode_value(rd: &RelationDescriptor) -> Result<IVec> {
+    Ok(bincode::serialize(&(rd.deletion_behaviour, &rd.data))?.into())
+}
+
+/// Returns the names of the trees still holding relation descriptors written by previous versions.
+pub(crate) fn legacy_tree_names(db: &Db) -> Vec<String> {
+    db.tree_names()
+        .into_iter()
+        .filter(|name| name.starts_with(LEGACY_TREE_PREFIX.as_bytes()))
+        .map(|name| String::from_utf8_lossy(&name).into_owned())
+        .collect()
+}
+
+/// Name of the tree where the relation descriptors of the entities of `tree_name` were stored by previous versions.
+fn legacy_tree_name(tree_name: &str) -> String {
+    format!("{}{}", LEGACY_TREE_PREFIX, tree_name)
+}
+
+/// Encodes again the keys of the entities of `E` written by previous versions in the relation descriptors written
+/// by previous versions : the keys of the descriptors of its entities, and the keys of its entities in the links
+/// of every other descriptor. Returns the entries to rewrite in every tree holding relation descriptors.
+pub(crate) fn upgrade_legacy_keys<E: Entity>(db: &Db, cipher: &Cipher) -> Result<Vec<(Tree, Rewrites)>> {
+    let mut upgraded = Vec::new();
+    for legacy_name in legacy_tree_names(db) {
+        let legacy = db.open_tree(&legacy_name)?;
+        let own = legacy_name == legacy_tree_name(E::store_name());
+        let mut entries = Rewrites::new();
+        for elem in legacy.iter() {
+            let (key, value) = elem?;
+            let value = cipher.open(&legacy_name, &key, value)?;
+            let mut descriptor = bincode::deserialize::<LegacyRelations>(&value)?;
+            let links = descriptor.get_mut(E::store_name());
+            if !own && links.is_none() {
+                continue;
+            }
+            for (related_key, _, _) in links.into_iter().flatten() {
+                *related_key = E::Key::from_legacy_bytes(related_key)?.as_bytes();
+            }
+            let new_key = match own {
+                true => E::Key::from_legacy_bytes(&key)?.as_bytes(),
+                false => key.to_vec(),
+            };
+            let value = cipher.seal(&legacy_name, &new_key, bincode::serialize(&descriptor)?.into())?;
+            entries.push((key, new_key, value));
+        }
+        upgraded.push((legacy, entries));
+    }
+    Ok(upgraded)
+}
+
+/// Converts the relation descriptors written by previous versions, where every link of an entity was stored
+/// in a single value, to edges. Each store is converted in a single transaction, then its former tree is dropped.
+///
+/// Links from or to a store still in the legacy layout are kept until the entity of that store is registered,
+/// as the keys of its entities have not been encoded again yet. Once every descriptor has been converted, the links
+/// damaged by the case-insensitive removal of previous versions are looked for, to be returned by
+/// [`check_case_folded_links`](../../fn.check_case_folded_links.html).
+pub(crate) fn migrate_legacy_trees(db: &Db) -> Result<()> {
+    let legacy_names = legacy_tree_names(db);
+    if legacy_names.is_empty() {
+        return Ok(());
+    }
+    let legacy_stores = layout::legacy_stores(db)?;
+    let state = state::of(db)?;
+    let _write = state.write();
+    let cipher = Cipher::current(&state);
+    for legacy_name in legacy_names {
+        let store = &legacy_name[LEGACY_TREE_PREFIX.len()..];
+        if legacy_stores.contains(store) {
+            continue;
+        }
+        let legacy = db.open_tree(&legacy_name)?;
+        let edges_name = Relation::tree_name(store);
+        let edges = db.open_tree(&edges_name)?;
+        let mut converted = Vec::new();
+        for elem in legacy.iter() {
+            let (key, value) = elem?;
+            let value = cipher.open(&legacy_name, &key, value)?;
+            let mut descriptor = bincode::deserialize::<LegacyRelations>(&value)?;
+            let mut entity_edges = Vec::new();
+            for (related_tree, links) in &descriptor {
+                if legacy_stores.contains(related_tree) {
+                    continue;
+                }
+                for (related_key, behaviour, name) in links {
+                    let rd = RelationDescriptor::new(related_key, *behaviour, name.as_deref());
+                    let edge = self::key(&key, related_tree, related_key, name.as_deref());
+                    let value = cipher.seal(&edges_name, &edge, encode_value(&rd)?)?;
+                    entity_edges.push((edge, value));
+                }
+            }
+            let related_trees = descriptor.len();
+            descriptor.retain(|related_tree, _| legacy_stores.contains(related_tree));
+            if !descriptor.is_empty() && descriptor.len() == related_trees {
+                continue;
+            }
+            let remaining = match descriptor.is_empty() {
+                true => None,
+                false => Some(cipher.seal(&legacy_name, &key, bincode::serialize(&descriptor)?.into())?),
+            };
+            converted.push((key, entity_edges, remaining));
+        }
+        let res: std::result::Result<(), TransactionError<Error>> =
+            (&legacy, &edges).transaction(|(legacy, edges)| {
+                for (key, entity_edges, remaining) in &converted {
+                    for (edge, value) in entity_edges {
+                        edges.insert(edge.as_slice(), value)?;
+                    }
+                    match remaining {
+                        Some(remaining) => legacy.insert(key, remaining)?,
+                        None => legacy.remove(key)?,
+                    };
+                }
+                Ok::<(), ConflictableTransactionError<Error>>(())
+            });
+        match res {
+            Ok(()) => {}
+            Err(TransactionError::Abort(e)) => return Err(e),
+            Err(TransactionError::Storage(e)) => return Err(e.into()),
+        }
+        if legacy.is_empty() {
+            db.drop_tree(&legacy_name)?;
+        }
+    }
+    if legacy_tree_names(db).is_empty() {
+        fsck::record_case_folded_links(db)?;
+    }
+    Ok(())
+}
@@ -1,15 +1,115 @@
 mod descriptor;
-use crate::entity::{AsBytes, Entity};
+pub(crate) mod edge;
+use crate::entity::Entity;
 use crate::error::Result;
+use crate::index;
+use crate::key::{AsBytes, FromBytes};
+use crate::storage::Storage;
 use crate::{Error, ErrorKind};
+use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
 use sled::Db;
 
 pub use self::descriptor::FamilyDescriptor;
-pub use self::descriptor::{EntityRelations, RelationMap};
+pub use self::descriptor::EntityRelations;
+pub(crate) use self::descriptor::RelationDescriptor;
 
 pub struct Relation;
 
+/// Implemented by the keys of child entities : tuples whose first element is the key of the parent entity.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not the key of a child of an entity with key `{K}`",
+    note = "the key of a child entity must be a tuple `(ParentKey, T)`"
+)]
+pub trait ChildKey<K> {}
+
+impl<K, T> ChildKey<K> for (K, T) {}
+
+/// Fails to compile if `C` cannot be a child of `P`. Used by the `derive` macro.
+///
+/// ```
+/// use reindeer::{Deserialize, Entity, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "user")]
+/// #[children(Doc = Cascade)]
+/// struct User {
+///     id: u32,
+/// }
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "doc")]
+/// struct Doc {
+///     id: (u32, u32),
+/// }
+/// ```
+///
+/// A child whose key does not start with the key of its parent is rejected at build time :
+///
+/// ```compile_fail,E0277
+/// use reindeer::{Deserialize, Entity, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "user")]
+/// #[children(Doc = Cascade)]
+/// struct User {
+///     id: u32,
+/// }
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "doc")]
+/// struct Doc {
+///     id: (String, u32),
+/// }
+/// ```
+#[doc(hidden)]
+pub fn assert_child<P: Entity, C: Entity>()
+where
+    C::Key: ChildKey<P::Key>,
+{
+}
+
+/// Fails to compile if `S` cannot be a sibling of `E`. Used by the `derive` macro.
+///
+/// ```
+/// use reindeer::{Deserialize, Entity, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "user")]
+/// #[siblings(UserData = Cascade)]
+/// struct User {
+///     id: u32,
+/// }
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "user_data")]
+/// struct UserData {
+///     id: u32,
+/// }
+/// ```
+///
+/// A sibling with another key type is rejected at build time :
+///
+/// ```compile_fail,E0271
+/// use reindeer::{Deserialize, Entity, Serialize};
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "user")]
+/// #[siblings(UserData = Cascade)]
+/// struct User {
+///     id: u32,
+/// }
+///
+/// #[derive(Serialize, Deserialize, Entity)]
+/// #[entity(name = "user_data")]
+/// struct UserData {
+///     id: String,
+/// }
+/// ```
+#[doc(hidden)]
+pub fn assert_sibling<E: Entity, S: Entity<Key = E::Key>>() {}
+
 impl Relation {
     pub fn create<E1: Entity, E2: Entity>(
         e1: &E1,
@@ -17,56 +117,179 @@ impl Relation {
         e1_to_e2: DeletionBehaviour,
         e2_to_e1: DeletionBehaviour,
         name: Option<&str>,
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
+        Relation::check_declared::<E1, E2>(name, e1_to_e2, e2_to_e1)?;
         Relation::create_link(e1, e2, e1_to_e2, name, db)?;
         Relation::create_link(e2, e1, e2_to_e1, name, db)?;
         Ok(())
     }
 
-    pub fn remove<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &Db) -> Result<()> {
+    /// Checks that a relation named `name` between entities of `E1` and `E2` is declared by one of them
+    /// with the same deletion behaviours, unless none of them declares free relations.
+    pub(crate) fn check_declared<E1: Entity, E2: Entity>(
+        name: Option<&str>,
+        e1_to_e2: DeletionBehaviour,
+        e2_to_e1: DeletionBehaviour,
+    ) -> Result<()> {
+        let (relations_1, relations_2) = (E1::get_free_relations(), E2::get_free_relations());
+        if relations_1.is_empty() && relations_2.is_empty() {
+            return Ok(());
+        }
+        let declared = |relations: &[(&str, &str, DeletionBehaviour, DeletionBehaviour)], store: &str| {
+            relations
+                .iter()
+                .filter(|(related_store, n, _, _)| *related_store == store && Some(*n) == name)
+                .map(|(_, _, to_related, from_related)| (*to_related, *from_related))
+                .collect::<Vec<(DeletionBehaviour, DeletionBehaviour)>>()
+        };
+        let mut behaviours = declared(&relations_1, E2::store_name());
+        // The declarations of a store related to itself are read once, from its own point of view
+        if E1::store_name() != E2::store_name() {
+            behaviours.extend(
+                declared(&relations_2, E1::store_name())
+                    .into_iter()
+                    .map(|(to_related, from_related)| (from_related, to_related)),
+            );
+        }
+        if behaviours.is_empty() {
+            return Err(Relation::undeclared_error::<E1, E2>(name));
+        }
+        if behaviours.iter().any(|b| *b != (e1_to_e2, e2_to_e1)) {
+            return Err(Error::new(
+                ErrorKind::UndeclaredRelation,
+                format!(
+                    "Relation{} between stores {} and {} is declared with other deletion behaviours than {:?} and {:?}",
+                    name.map(|n| format!(" {}", n)).unwrap_or_default(),
+                    E1::store_name(),
+                    E2::store_name(),
+                    e1_to_e2,
+                    e2_to_e1
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn undeclared_error<E1: Entity, E2: Entity>(name: Option<&str>) -> Error {
+        Error::new(
+            ErrorKind::UndeclaredRelation,
+            format!(
+                "No relation{} is declared between stores {} and {}",
+                name.map(|n| format!(" named {}", n)).unwrap_or_default(),
+                E1::store_name(),
+                E2::store_name()
+            ),
+        )
+    }
+
+    /// Creates a relation carrying `data`, stored on the links in both ways.
+    pub fn create_with_data<E1: Entity, E2: Entity>(
+        e1: &E1,
+        e2: &E2,
+        e1_to_e2: DeletionBehaviour,
+        e2_to_e1: DeletionBehaviour,
+        name: Option<&str>,
+        data: &[u8],
+        db: &impl Storage,
+    ) -> Result<()> {
+        Relation::check_declared::<E1, E2>(name, e1_to_e2, e2_to_e1)?;
+        let (k1, k2) = (e1.get_key().as_bytes(), e2.get_key().as_bytes());
+        let mut link = RelationDescriptor::new(&k2, e1_to_e2, name);
+        link.data = Some(data.to_vec());
+        Relation::insert_link(E1::store_name(), &k1, E2::store_name(), &link, db)?;
+        let mut link = RelationDescriptor::new(&k1, e2_to_e1, name);
+        link.data = Some(data.to_vec());
+        Relation::insert_link(E2::store_name(), &k2, E1::store_name(), &link, db)
+    }
+
+    /// Replaces the data of the relation named `name` between two entities, in both ways.
+    pub fn update_data<E1: Entity, E2: Entity>(
+        e1: &E1,
+        e2: &E2,
+        name: Option<&str>,
+        data: &[u8],
+        db: &impl Storage,
+    ) -> Result<()> {
+        let (k1, k2) = (e1.get_key().as_bytes(), e2.get_key().as_bytes());
+        for (tree1, key1, tree2, key2) in [
+            (E1::store_name(), &k1, E2::store_name(), &k2),
+            (E2::store_name(), &k2, E1::store_name(), &k1),
+        ] {
+            let edge_key = edge::key(key1, tree2, key2, name);
+            let value = db.get(&Relation::tree_name(tree1), &edge_key)?.ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("No relation between {} and {} with this name", tree1, tree2),
+                )
+            })?;
+            let value = db.cipher()?.open(&Relation::tree_name(tree1), &edge_key, value)?;
+            let (_, mut link) = edge::decode(&edge_key, &value)?;
+            link.data = Some(data.to_vec());
+            Relation::insert_link(tree1, key1, tree2, &link, db)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &impl Storage) -> Result<()> {
         Relation::remove_link(e1, e2, db)?;
         Relation::remove_link(e2, e1, db)?;
         Ok(())
     }
 
-    pub fn change_entity_id(tree_name: &str, old_id: &[u8], new_id: &[u8], db: &Db) -> Result<()> {
-        let mut descriptor =
-            Relation::get_descriptor_with_key_and_tree_name(tree_name, old_id, db)?;
-        for (other_tree, entities) in &mut descriptor.related_entities {
-            for rd in entities {
-                let mut other_desc =
-                    Relation::get_descriptor_with_key_and_tree_name(other_tree, &rd.key, db)?;
-                other_desc.replace_id(tree_name, old_id, new_id);
-                Relation::save_descriptor_with_key_and_tree_name(
+    pub fn change_entity_id(
+        tree_name: &str,
+        old_id: &[u8],
+        new_id: &[u8],
+        db: &impl Storage,
+    ) -> Result<()> {
+        let links = Relation::links(tree_name, &edge::entity_prefix(old_id), db)?;
+        for (other_tree, rd) in &links {
+            // The links pointing to the entity are moved to its new key, with their name and behaviour
+            for (_, back) in Relation::links(other_tree, &edge::link_prefix(&rd.key, tree_name, old_id), db)? {
+                Storage::remove(
+                    db,
+                    &Relation::tree_name(other_tree),
+                    &edge::key(&rd.key, tree_name, old_id, back.name.as_deref()),
+                )?;
+                Relation::insert_link(
                     other_tree,
                     &rd.key,
-                    &other_desc,
+                    tree_name,
+                    &RelationDescriptor {
+                        key: new_id.to_vec(),
+                        ..back
+                    },
                     db,
                 )?;
             }
         }
-        Relation::save_descriptor_with_key_and_tree_name(tree_name, new_id, &descriptor, db)?;
-        let family_descriptor = FamilyDescriptor::get(&String::from(tree_name), db)?;
-        if family_descriptor.is_none() {
-            return Ok(());
+        Relation::remove_links(tree_name, &edge::entity_prefix(old_id), db)?;
+        for (other_tree, rd) in &links {
+            Relation::insert_link(tree_name, new_id, other_tree, rd, db)?;
         }
-        let family_descriptor = family_descriptor.unwrap();
+        let family_descriptor = match FamilyDescriptor::get_from_storage(tree_name, db)? {
+            Some(family_descriptor) => family_descriptor,
+            None => return Ok(()),
+        };
+        let cipher = db.cipher()?;
         for (other_tree, _) in family_descriptor.child_trees {
-            let tree = db.open_tree(&other_tree)?;
-            for (key, value) in tree.scan_prefix(old_id).flatten() {
+            for (key, value) in db.scan_prefix(&other_tree, old_id)? {
                 let new_key = [new_id, &key[old_id.len()..]].concat();
-                tree.insert(&new_key, value)?;
+                let value = cipher.open(&other_tree, &key, value)?;
+                db.insert_sealed(&other_tree, &new_key, value)?;
+                index::move_entries(&other_tree, &key, &new_key, db)?;
                 Relation::change_entity_id(&other_tree, &key, &new_key, db)?;
-                tree.remove(&key)?;
+                Storage::remove(db, &other_tree, &key)?;
             }
         }
         for (other_tree, _) in family_descriptor.sibling_trees {
-            let tree = db.open_tree(&other_tree)?;
-            if let Some(value) = tree.get(old_id)? {
-                tree.insert(&new_id, value)?;
+            if let Some(value) = db.get(&other_tree, old_id)? {
+                let value = cipher.open(&other_tree, old_id, value)?;
+                db.insert_sealed(&other_tree, new_id, value)?;
+                index::move_entries(&other_tree, old_id, new_id, db)?;
                 Relation::change_entity_id(&other_tree, old_id, new_id, db)?;
-                tree.remove(&old_id)?;
+                Storage::remove(db, &other_tree, old_id)?;
             }
         }
         Ok(())
@@ -76,47 +299,54 @@ impl Relation {
         e1: &E1,
         e2: &E2,
         name: &str,
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
         Relation::remove_link_with_name(e1, e2, name, db)?;
         Relation::remove_link_with_name(e2, e1, name, db)?;
         Ok(())
     }
 
-    pub fn remove_entity_entry<E1: Entity>(key: &[u8], db: &Db) -> Result<()> {
-        let descriptor = Self::get_descriptor_with_key::<E1>(key, db)?;
-        for (tree_name, referers) in descriptor.related_entities {
-            for referer in referers {
-                Self::remove_link_with_keys_and_tree_names(
-                    &tree_name,
-                    &referer.key,
-                    E1::store_name(),
-                    key,
-                    db,
-                )?;
+    pub fn remove_entity_entry(
+        tree_name: &str,
+        key: &[u8],
+        db: &impl Storage,
+    ) -> Result<()> {
+        for (other_tree, referer) in Self::links(tree_name, &edge::entity_prefix(key), db)? {
+            Self::remove_link_with_keys_and_tree_names(&other_tree, &referer.key, tree_name, key, db)?;
+        }
+        Self::remove_links(tree_name, &edge::entity_prefix(key), db)
+    }
+
+    /// Checks that an entity can be removed, then removes every entity that must be removed along with it
+    /// as well as their relation descriptors, index entries and the links pointing to them.
+    /// The entity itself is left in its store.
+    pub(crate) fn remove_related(tree_name: &str, key: &[u8], db: &impl Storage) -> Result<()> {
+        let mut to_be_removed = EntityRelations::default();
+        Relation::can_be_deleted(tree_name, key, &Vec::new(), &mut to_be_removed, db)?;
+        for (tree, entities) in &to_be_removed.related_entities {
+            for rd in entities {
+                db.remove(tree, &rd.key)?;
+                Relation::remove_entity_entry(tree, &rd.key, db)?;
+                index::remove_entries(tree, &rd.key, db)?;
             }
         }
-        let tree = db.open_tree(Relation::tree_name(E1::store_name()))?;
-        tree.remove(key)?;
+        Relation::remove_entity_entry(tree_name, key, db)?;
+        index::remove_entries(tree_name, key, db)?;
         Ok(())
     }
 
-    pub fn remove_by_keys<E1: Entity, E2: Entity>(e1: &[u8], e2: &[u8], db: &Db) -> Result<()> {
+    pub fn remove_by_keys<E1: Entity, E2: Entity>(e1: &[u8], e2: &[u8], db: &impl Storage) -> Result<()> {
         Relation::remove_link_with_keys::<E1, E2>(e1, e2, db)?;
         Relation::remove_link_with_keys::<E2, E1>(e2, e1, db)?;
         Ok(())
     }
 
-    pub fn relations<E1: Entity>(e1: &E1, db: &Db) -> Result<EntityRelations> {
-        Relation::get_descriptor(e1, db)
-    }
-
     pub fn can_be_deleted(
         tree_name: &str,
         e1: &[u8],
         already_checked: &[(String, Vec<u8>)],
         removable_entities: &mut EntityRelations,
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
         if already_checked
             .iter()
@@ -125,14 +355,14 @@ impl Relation {
             return Ok(());
         }
         let descriptor = Self::get_descriptor_with_key_and_tree_name(tree_name, e1, db)?;
-        let family_descriptor = FamilyDescriptor::get(&String::from(tree_name), db)?;
+        let family_descriptor = FamilyDescriptor::get_from_storage(tree_name, db)?;
 
         for (other_tree_name, entities) in &descriptor.related_entities {
             for rd in entities {
                 match rd.deletion_behaviour {
                     DeletionBehaviour::Error => {
                         if already_checked.iter().any(|(tn, k)| {
-                            tn == other_tree_name && k.as_bytes() == rd.key.as_bytes()
+                            tn == other_tree_name && *k == rd.key
                         }) {
                             continue;
                         }
@@ -178,8 +408,7 @@ impl Relation {
                     {
                         continue;
                     }
-                    let tree = db.open_tree(&other_tree_name)?;
-                    if tree.contains_key(e1)? {
+                    if db.contains_key(other_tree_name, e1)? {
                         return Err(Error::new(
                             ErrorKind::IntegrityError,
                             format!("Constrained sibling entity exists in {}", &other_tree_name),
@@ -208,28 +437,19 @@ impl Relation {
         }
         for (other_tree_name, behaviour) in &family_descriptor.child_trees {
             match behaviour {
-                DeletionBehaviour::Error => {
-                    let tree = db.open_tree(&other_tree_name)?;
-                    if tree.scan_prefix(e1).count() > 0 {
-                        return Err(Error::new(
-                            ErrorKind::IntegrityError,
-                            format!("Constrained child entity exists in {}", &other_tree_name),
-                        ));
-                    }
+                DeletionBehaviour::Error if db.has_prefix(other_tree_name, e1)? => {
+                    return Err(Error::new(
+                        ErrorKind::IntegrityError,
+                        format!("Constrained child entity exists in {}", &other_tree_name),
+                    ));
                 }
                 DeletionBehaviour::Cascade => {
                     let mut new_already_checked = already_checked.to_owned();
                     new_already_checked.push((String::from(tree_name), e1.to_vec()));
-                    let tree = db.open_tree(&other_tree_name)?;
-                    let keys = tree
-                        .scan_prefix(e1)
-                        .filter_map(|e| {
-                            if let Ok((key, _)) = e {
-                                Some(key.to_vec())
-                            } else {
-                                None
-                            }
-                        })
+                    let keys = db
+                        .scan_prefix(other_tree_name, e1)?
+                        .into_iter()
+                        .map(|(key, _)| key.to_vec())
                         .collect::<Vec<Vec<u8>>>();
                     for key in keys {
                         Self::can_be_deleted(
@@ -254,51 +474,60 @@ impl Relation {
     }
 
     pub fn get<E1: Entity, E2: Entity>(e1: &E1, db: &Db) -> Result<Vec<E2>> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            Ok(E2::get_each_u8(
-                (related_keys
-                    .iter()
-                    .map(|e| e.key.clone())
-                    .collect::<Vec<Vec<u8>>>())
-                .as_slice(),
-                db,
-            ))
-        } else {
-            Ok(Vec::new())
-        }
+        let related_keys = Relation::links_to_store::<E1, E2>(e1, db)?
+            .into_iter()
+            .map(|rd| rd.key)
+            .collect::<Vec<Vec<u8>>>();
+        E2::get_each_u8(&related_keys, db)
     }
 
     pub fn get_with_name<E1: Entity, E2: Entity>(e1: &E1, name: &str, db: &Db) -> Result<Vec<E2>> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            Ok(E2::get_each_u8(
-                (related_keys
-                    .iter()
-                    .filter(|rd| match &rd.name {
-                        Some(n) => name == n,
-                        None => false,
-                    })
-                    .map(|e| e.key.clone())
-                    .collect::<Vec<Vec<u8>>>())
-                .as_slice(),
-                db,
-            ))
-        } else {
-            Ok(Vec::new())
+        let related_keys = Relation::links_to_store::<E1, E2>(e1, db)?
+            .into_iter()
+            .filter(|rd| rd.name.as_deref() == Some(name))
+            .map(|rd| rd.key)
+            .collect::<Vec<Vec<u8>>>();
+        E2::get_each_u8(&related_keys, db)
+    }
+
+    /// Gets the entities of `E2` related to `e1` along with the data of their link.
+    /// Links that do not carry data are left out.
+    pub fn get_with_data<E1: Entity, E2: Entity, D: DeserializeOwned>(
+        e1: &E1,
+        db: &Db,
+    ) -> Result<Vec<(E2, D)>> {
+        let mut related = Vec::new();
+        for rd in Relation::links_to_store::<E1, E2>(e1, db)? {
+            if let (Some(data), Some(entity)) = (&rd.data, E2::get_from_u8_array(&rd.key, db)?) {
+                related.push((entity, bincode::deserialize(data)?));
+            }
         }
+        Ok(related)
+    }
+
+    pub fn get_keys<E1: Entity, E2: Entity>(e1: &E1, db: &Db) -> Result<Vec<E2::Key>> {
+        Relation::links_to_store::<E1, E2>(e1, db)?
+            .iter()
+            .map(|rd| E2::Key::from_bytes(&rd.key))
+            .collect()
+    }
+
+    pub fn get_keys_with_name<E1: Entity, E2: Entity>(
+        e1: &E1,
+        name: &str,
+        db: &Db,
+    ) -> Result<Vec<E2::Key>> {
+        Relation::links_to_store::<E1, E2>(e1, db)?
+            .iter()
+            .filter(|rd| rd.name.as_deref() == Some(name))
+            .map(|rd| E2::Key::from_bytes(&rd.key))
+            .collect()
     }
 
     pub fn get_one<E1: Entity, E2: Entity>(e1: &E1, db: &Db) -> Result<Option<E2>> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            if !related_keys.is_empty() {
-                E2::get_from_u8_array(&related_keys[0].key, db)
-            } else {
-                Ok(None)
-            }
-        } else {
-            Ok(None)
+        match Relation::links_to_store::<E1, E2>(e1, db)?.first() {
+            Some(rd) => E2::get_from_u8_array(&rd.key, db),
+            None => Ok(None),
         }
     }
 
@@ -307,33 +536,19 @@ impl Relation {
         name: &str,
         db: &Db,
     ) -> Result<Option<E2>> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            let item = related_keys.iter().find(|rd| {
-                if let Some(n) = &rd.name {
-                    name == n
-                } else {
-                    false
-                }
-            });
-            match item {
-                Some(rd) => E2::get_from_u8_array(&rd.key, db),
-                None => Ok(None),
-            }
-        } else {
-            Ok(None)
+        let links = Relation::links_to_store::<E1, E2>(e1, db)?;
+        match links.iter().find(|rd| rd.name.as_deref() == Some(name)) {
+            Some(rd) => E2::get_from_u8_array(&rd.key, db),
+            None => Ok(None),
         }
     }
 
     pub fn are_related<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &Db) -> Result<bool> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            Ok(related_keys
-                .iter()
-                .any(|rd| rd.key == e2.get_key().as_bytes()))
-        } else {
-            Ok(false)
-        }
+        Storage::has_prefix(
+            db,
+            &Relation::tree_name(E1::store_name()),
+            &edge::link_prefix(&e1.get_key().as_bytes(), E2::store_name(), &e2.get_key().as_bytes()),
+        )
     }
 
     pub fn are_related_with_name<E1: Entity, E2: Entity>(
@@ -342,18 +557,7 @@ impl Relation {
         name: &str,
         db: &Db,
     ) -> Result<bool> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            Ok(related_keys.iter().any(|rd| {
-                rd.key == e2.get_key().as_bytes()
-                    && (match &rd.name {
-                        Some(rname) => rname == name,
-                        None => false,
-                    })
-            }))
-        } else {
-            Ok(false)
-        }
+        Relation::are_related_with_any_name(e1, e2, &[name], db)
     }
 
     pub fn are_related_with_any_name<E1: Entity, E2: Entity>(
@@ -362,119 +566,158 @@ impl Relation {
         names: &[&str],
         db: &Db,
     ) -> Result<bool> {
-        let referers = Relation::relations(e1, db)?;
-        if let Some(related_keys) = referers.related_entities.get(E2::store_name()) {
-            Ok(related_keys.iter().any(|rd| {
-                rd.key == e2.get_key().as_bytes()
-                    && (match &rd.name {
-                        Some(rname) => names.iter().any(|name| name == rname),
-                        None => false,
-                    })
-            }))
-        } else {
-            Ok(false)
+        let tree = Relation::tree_name(E1::store_name());
+        let (e1, e2) = (e1.get_key().as_bytes(), e2.get_key().as_bytes());
+        for name in names {
+            if Storage::contains_key(db, &tree, &edge::key(&e1, E2::store_name(), &e2, Some(name)))? {
+                return Ok(true);
+            }
         }
+        Ok(false)
     }
 
-    fn tree_name(entity_tree: &str) -> String {
-        format!("__$rel_{}", entity_tree)
+    /// Name of the tree holding the links of the entities of `entity_tree`, one edge per link.
+    pub(crate) fn tree_name(entity_tree: &str) -> String {
+        format!("__$edges_{}", entity_tree)
     }
 
-    pub fn get_descriptor_with_key_and_tree_name(
+    /// Reads the links of the entities of `tree_name` whose edge keys start with `prefix`,
+    /// along with the store of the related entity.
+    pub(crate) fn links(
+        tree_name: &str,
+        prefix: &[u8],
+        db: &impl Storage,
+    ) -> Result<Vec<(String, RelationDescriptor)>> {
+        let cipher = db.cipher()?;
+        let tree = Relation::tree_name(tree_name);
+        db.scan_prefix(&tree, prefix)?
+            .into_iter()
+            .map(|(key, value)| edge::decode(&key, &cipher.open(&tree, &key, value)?))
+            .collect()
+    }
+
+    /// Reads the links of the entity of `tree_name` with key `e` to the entities of `related_tree`.
+    pub(crate) fn links_to_store_with_tree_names(
         tree_name: &str,
         e: &[u8],
-        db: &Db,
-    ) -> Result<EntityRelations> {
-        let tree = db.open_tree(Relation::tree_name(tree_name))?;
-        match tree.get(e)? {
-            Some(relation_descriptor) => Ok(bincode::deserialize::<EntityRelations>(
-                &relation_descriptor,
-            )?),
-            None => Ok(EntityRelations::default()),
-        }
+        related_tree: &str,
+        db: &impl Storage,
+    ) -> Result<Vec<RelationDescriptor>> {
+        Ok(Relation::links(tree_name, &edge::store_prefix(e, related_tree), db)?
+            .into_iter()
+            .map(|(_, rd)| rd)
+            .collect())
     }
 
-    fn get_descriptor_with_key<E: Entity>(e: &[u8], db: &Db) -> Result<EntityRelations> {
-        Self::get_descriptor_with_key_and_tree_name(E::store_name(), e, db)
+    fn links_to_store<E1: Entity, E2: Entity>(e1: &E1, db: &impl Storage) -> Result<Vec<RelationDescriptor>> {
+        Relation::links_to_store_with_tree_names(
+            E1::store_name(),
+            &e1.get_key().as_bytes(),
+            E2::store_name(),
+            db,
+        )
     }
 
-    fn get_descriptor<E: Entity>(e: &E, db: &Db) -> Result<EntityRelations> {
-        Self::get_descriptor_with_key::<E>(&e.get_key().as_bytes(), db)
+    fn insert_link(
+        tree_name: &str,
+        e: &[u8],
+        related_tree: &str,
+        rd: &RelationDescriptor,
+        db: &impl Storage,
+    ) -> Result<()> {
+        let tree = Relation::tree_name(tree_name);
+        let key = edge::key(e, related_tree, &rd.key, rd.name.as_deref());
+        db.insert_sealed(&tree, &key, edge::encode_value(rd)?)
     }
 
-    fn save_descriptor_with_key<E: Entity>(e: &[u8], r_d: &EntityRelations, db: &Db) -> Result<()> {
-        let tree = db.open_tree(Relation::tree_name(E::store_name()))?;
-        tree.insert(e, bincode::serialize(r_d)?)?;
+    fn remove_links(tree_name: &str, prefix: &[u8], db: &impl Storage) -> Result<()> {
+        let tree = Relation::tree_name(tree_name);
+        for (key, _) in db.scan_prefix(&tree, prefix)? {
+            db.remove(&tree, &key)?;
+        }
         Ok(())
     }
 
-    fn save_descriptor_with_key_and_tree_name(
+    /// Gathers every link of the entity of `tree_name` with key `e`.
+    pub fn get_descriptor_with_key_and_tree_name(
+        tree_name: &str,
+        e: &[u8],
+        db: &impl Storage,
+    ) -> Result<EntityRelations> {
+        let mut descriptor = EntityRelations::default();
+        for (related_tree, rd) in Relation::links(tree_name, &edge::entity_prefix(e), db)? {
+            descriptor.related_entities.entry(related_tree).or_default().push(rd);
+        }
+        Ok(descriptor)
+    }
+
+    fn save_descriptor_with_key<E: Entity>(e: &[u8], r_d: &EntityRelations, db: &impl Storage) -> Result<()> {
+        Self::save_descriptor_with_key_and_tree_name(E::store_name(), e, r_d, db)
+    }
+
+    /// Replaces every link of the entity of `tree_name` with key `e` by the links of `r_d`.
+    pub(crate) fn save_descriptor_with_key_and_tree_name(
         tree_name: &str,
         e: &[u8],
         r_d: &EntityRelations,
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
-        let tree = db.open_tree(Relation::tree_name(tree_name))?;
-        tree.insert(e, bincode::serialize(r_d)?)?;
+        Relation::remove_links(tree_name, &edge::entity_prefix(e), db)?;
+        for (related_tree, links) in &r_d.related_entities {
+            for rd in links {
+                Relation::insert_link(tree_name, e, related_tree, rd, db)?;
+            }
+        }
         Ok(())
     }
 
-    pub fn save_descriptor<E: Entity>(e: &E, r_d: &EntityRelations, db: &Db) -> Result<()> {
+    pub fn save_descriptor<E: Entity>(e: &E, r_d: &EntityRelations, db: &impl Storage) -> Result<()> {
         Self::save_descriptor_with_key::<E>(&e.get_key().as_bytes(), r_d, db)
     }
 
-    pub fn remove_descriptor(tree_name: &str, e: &[u8], db: &Db) -> Result<()> {
-        let tree = db.open_tree(Relation::tree_name(tree_name))?;
-        tree.remove(e)?;
-        Ok(())
-    }
-
     fn create_link<E1: Entity, E2: Entity>(
         e1: &E1,
         e2: &E2,
         e1_to_e2: DeletionBehaviour,
         name: Option<&str>,
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
-        let mut e1_descriptor = Self::get_descriptor(e1, db)?;
-        e1_descriptor.add_related(e2, e1_to_e2, name);
-        Self::save_descriptor(e1, &e1_descriptor, db)?;
-        Ok(())
+        Self::insert_link(
+            E1::store_name(),
+            &e1.get_key().as_bytes(),
+            E2::store_name(),
+            &RelationDescriptor::new(&e2.get_key().as_bytes(), e1_to_e2, name),
+            db,
+        )
     }
 
-    fn remove_link_with_keys<E1: Entity, E2: Entity>(e1: &[u8], e2: &[u8], db: &Db) -> Result<()> {
-        let mut e1_descriptor = Self::get_descriptor_with_key::<E1>(e1, db)?;
-        e1_descriptor.remove_related_by_key::<E2>(e2);
-        Self::save_descriptor_with_key::<E1>(e1, &e1_descriptor, db)?;
-        Ok(())
+    fn remove_link_with_keys<E1: Entity, E2: Entity>(e1: &[u8], e2: &[u8], db: &impl Storage) -> Result<()> {
+        Self::remove_link_with_keys_and_tree_names(E1::store_name(), e1, E2::store_name(), e2, db)
     }
 
     fn remove_link_with_keys_and_relation_name<E1: Entity, E2: Entity>(
         e1: &[u8],
         e2: &[u8],
         name: &str,
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
-        let mut e1_descriptor = Self::get_descriptor_with_key::<E1>(e1, db)?;
-        e1_descriptor.remove_related_by_key_with_name::<E2>(e2, name);
-        Self::save_descriptor_with_key::<E1>(e1, &e1_descriptor, db)?;
-        Ok(())
+        db.remove(
+            &Self::tree_name(E1::store_name()),
+            &edge::key(e1, E2::store_name(), e2, Some(name)),
+        )
     }
 
-    fn remove_link_with_keys_and_tree_names(
+    pub(crate) fn remove_link_with_keys_and_tree_names(
         tree1: &str,
         e1: &[u8],
         tree2: &str,
         e2: &[u8],
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
-        let mut e1_descriptor = Self::get_descriptor_with_key_and_tree_name(tree1, e1, db)?;
-        e1_descriptor.remove_related_by_key_and_tree_name(tree2, e2);
-        Self::save_descriptor_with_key_and_tree_name(tree1, e1, &e1_descriptor, db)?;
-        Ok(())
+        Self::remove_links(tree1, &edge::link_prefix(e1, tree2, e2), db)
     }
 
-    fn remove_link<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &Db) -> Result<()> {
+    fn remove_link<E1: Entity, E2: Entity>(e1: &E1, e2: &E2, db: &impl Storage) -> Result<()> {
         Relation::remove_link_with_keys::<E1, E2>(
             &e1.get_key().as_bytes(),
             &e2.get_key().as_bytes(),
@@ -486,7 +729,7 @@ impl Relation {
         e1: &E1,
         e2: &E2,
         name: &str,
-        db: &Db,
+        db: &impl Storage,
     ) -> Result<()> {
         Relation::remove_link_with_keys_and_relation_name::<E1, E2>(
             &e1.get_key().as_bytes(),
@@ -0,0 +1,210 @@
+//! # Schema Module
+//! This module checks that the families registered in a database, declared with `get_child_stores` and
+//! `get_sibling_stores`, form a coherent graph.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use sled::Db;
+
+use crate::error::Result;
+use crate::relation::FamilyDescriptor;
+use crate::{DeletionBehaviour, Entity};
+
+/// An inconsistency found by [`validate_schema`](fn.validate_schema.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaIssue {
+    /// A store declared as a child or a sibling of another store, which has not been registered.
+    MissingStore { store: String, related_store: String },
+    /// A sibling store that does not declare this store as its own sibling.
+    NonReciprocalSibling { store: String, sibling_store: String },
+    /// Deletion behaviours that cannot be applied together : a store declared several times by the same store
+    /// with different behaviours, or two siblings that both prevent removing the other one (`DeletionBehaviour::Error`),
+    /// so that neither can ever be removed.
+    ConflictingBehaviours {
+        store: String,
+        related_store: String,
+        behaviours: (DeletionBehaviour, DeletionBehaviour),
+    },
+    /// Stores that remove each other in a loop (`DeletionBehaviour::Cascade`), starting from the first one.
+    /// Two siblings removing each other are not reported, as this is the usual way of tying them together.
+    CascadeCycle { stores: Vec<String> },
+}
+
+impl fmt::Display for SchemaIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SchemaIssue::MissingStore {
+                store,
+                related_store,
+            } => write!(
+                f,
+                "Store {} declares store {}, which is not registered",
+                store, related_store
+            ),
+            SchemaIssue::NonReciprocalSibling {
+                store,
+                sibling_store,
+            } => write!(
+                f,
+                "Store {} declares store {} as a sibling, which does not declare it back",
+                store, sibling_store
+            ),
+            SchemaIssue::ConflictingBehaviours {
+                store,
+                related_store,
+                behaviours,
+            } => write!(
+                f,
+                "Stores {} and {} have conflicting deletion behaviours ({:?} and {:?})",
+                store, related_store, behaviours.0, behaviours.1
+            ),
+            SchemaIssue::CascadeCycle { stores } => write!(
+                f,
+                "Removing an entity cascades back to its own store : {} -> {}",
+                stores.join(" -> "),
+                stores[0]
+            ),
+        }
+    }
+}
+
+/// The inconsistencies found by [`validate_schema`](fn.validate_schema.html).
+#[derive(Debug, Default)]
+pub struct SchemaReport {
+    /// Every inconsistency found
+    pub issues: Vec<SchemaIssue>,
+}
+
+impl SchemaReport {
+    /// Returns `true` if no inconsistency was found.
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks the families registered in `db`, and returns the inconsistencies found : child and sibling stores
+/// that are not registered, siblings that are not declared both ways, conflicting deletion behaviours, and
+/// stores that remove each other in a loop.
+///
+/// Nothing is written. To check the schema before registering entities, turn
+/// [schema validation mode](fn.set_schema_validation.html) on.
+///
+/// ### Example
+/// ```rust
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// let report = reindeer::validate_schema(&db)?;
+/// for issue in &report.issues {
+///     println!("{}", issue);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn validate_schema(db: &Db) -> Result<SchemaReport> {
+    Ok(validate_families(&FamilyDescriptor::get_all(db)?))
+}
+
+/// Checks a set of families, with at most one family per store.
+pub(crate) fn validate_families(families: &[FamilyDescriptor]) -> SchemaReport {
+    let families = families
+        .iter()
+        .map(|family| (family.tree_name.as_str(), family))
+        .collect::<BTreeMap<&str, &FamilyDescriptor>>();
+    let mut report = SchemaReport::default();
+    // Stores removed along with each store, telling if they are siblings
+    let mut cascades: BTreeMap<&str, Vec<(&str, bool)>> = BTreeMap::new();
+    for (&store, family) in &families {
+        let mut declared: HashMap<&str, DeletionBehaviour> = HashMap::new();
+        let related = family
+            .child_trees
+            .iter()
+            .map(|(related_store, behaviour)| (related_store, behaviour, false))
+            .chain(
+                family
+                    .sibling_trees
+                    .iter()
+                    .map(|(related_store, behaviour)| (related_store, behaviour, true)),
+            );
+        for (related_store, behaviour, sibling) in related {
+            match declared.get(related_store.as_str()) {
+                Some(other) => {
+                    if other != behaviour {
+                        report.issues.push(SchemaIssue::ConflictingBehaviours {
+                            store: String::from(store),
+                            related_store: related_store.clone(),
+                            behaviours: (*other, *behaviour),
+                        });
+                    }
+                    continue;
+                }
+                None => {
+                    declared.insert(related_store, *behaviour);
+                }
+            }
+            if *behaviour == DeletionBehaviour::Cascade {
+                cascades
+                    .entry(store)
+                    .or_default()
+                    .push((related_store, sibling));
+            }
+            if !families.contains_key(related_store.as_str()) {
+                report.issues.push(SchemaIssue::MissingStore {
+                    store: String::from(store),
+                    related_store: related_store.clone(),
+                });
+            }
+        }
+        for (sibling_store, behaviour) in &family.sibling_trees {
+            let Some(sibling) = families.get(sibling_store.as_str()) else {
+                continue;
+            };
+            match sibling.sibling_trees.iter().find(|(other, _)| other == store) {
+                None => report.issues.push(SchemaIssue::NonReciprocalSibling {
+                    store: String::from(store),
+                    sibling_store: sibling_store.clone(),
+                }),
+                Some((_, DeletionBehaviour::Error))
+                    if *behaviour == DeletionBehaviour::Error && store < sibling_store.as_str() =>
+                {
+                    report.issues.push(SchemaIssue::ConflictingBehaviours {
+                        store: String::from(store),
+                        related_store: sibling_store.clone(),
+                        behaviours: (DeletionBehaviour::Error, DeletionBehaviour::Error),
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    for &start in cascades.keys() {
+        find_cycles(&cascades, &mut vec![(start, false)], &mut report);
+    }
+    report
+}
+
+/// Follows the cascades from the last store of `path`, and reports the paths leading back to its first store.
+/// Only stores after the first one in name order are visited, so that each cycle is found once,
+/// from its first store.
+fn find_cycles<'a>(
+    cascades: &BTreeMap<&'a str, Vec<(&'a str, bool)>>,
+    path: &mut Vec<(&'a str, bool)>,
+    report: &mut SchemaReport,
+) {
+    let start = path[0].0;
+    let current = path[path.len() - 1].0;
+    for &(next, sibling) in cascades.get(current).into_iter().flatten() {
+        if next == start {
+            let reciprocal_siblings = path.len() == 2 && path[1].1 && sibling;
+            if !reciprocal_siblings {
+                report.issues.push(SchemaIssue::CascadeCycle {
+                    stores: path.iter().map(|(store, _)| String::from(*store)).collect(),
+                });
+            }
+        } else if next > start && !path.iter().any(|(store, _)| *store == next) {
+            path.push((next, sibling));
+            find_cycles(cascades, path, report);
+            path.pop();
+        }
+    }
+}
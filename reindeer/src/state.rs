@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak};
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::ChaCha20Poly1305;
+use sled::{Db, Tree};
+
+use crate::error::Result;
+
+/// Name of the tree that keeps the in-memory state of a database alive for as long as the database is open.
+/// Nothing is ever written to it.
+const STATE_TREE: &str = "__$state";
+
+/// In-memory state of an open database, dropped along with the last handle to it.
+#[derive(Default)]
+pub(crate) struct DbState {
+    /// Taken for reading by writes made outside of a transaction, and for writing by a transaction while it
+    /// checks its scans and commits, so that no entry can be added under a scanned range in between.
+    writes: RwLock<()>,
+    /// The stores still in the legacy layout, once they have been read.
+    pub(crate) legacy_stores: RwLock<Option<HashSet<String>>>,
+    /// The key set for the database, if any.
+    #[cfg(feature = "encryption")]
+    pub(crate) key: RwLock<Option<Arc<ChaCha20Poly1305>>>,
+    /// Whether the database is in strict mode.
+    pub(crate) strict: AtomicBool,
+    /// Whether the database is in schema validation mode.
+    pub(crate) schema_validation: AtomicBool,
+}
+
+/// States of the open databases, by address of the inner state of their default tree.
+///
+/// Only weak references are kept here : the state is owned by the database itself, so that the database
+/// can be dropped, and a dead entry is replaced when another database is opened at the same address.
+static STATES: OnceLock<RwLock<HashMap<usize, Weak<DbState>>>> = OnceLock::new();
+
+fn states() -> &'static RwLock<HashMap<usize, Weak<DbState>>> {
+    STATES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Handles to the same database share the inner state of their default tree.
+fn address(db: &Db) -> usize {
+    let tree: &Tree = db;
+    &**tree as *const _ as usize
+}
+
+/// Returns the in-memory state of `db`, creating it the first time it is needed.
+pub(crate) fn of(db: &Db) -> Result<Arc<DbState>> {
+    let address = address(db);
+    if let Some(state) = find(address) {
+        return Ok(state);
+    }
+    let mut states = states().write().unwrap_or_else(|e| e.into_inner());
+    if let Some(state) = states.get(&address).and_then(Weak::upgrade) {
+        return Ok(state);
+    }
+    let state = Arc::new(DbState::default());
+    let owned = state.clone();
+    // The merge operator is never used : it only ties the lifetime of the state to the one of the database.
+    db.open_tree(STATE_TREE)?
+        .set_merge_operator(move |_: &[u8], old: Option<&[u8]>, _: &[u8]| {
+            let _ = &owned;
+            old.map(|old| old.to_vec())
+        });
+    states.retain(|_, state| state.strong_count() > 0);
+    states.insert(address, Arc::downgrade(&state));
+    Ok(state)
+}
+
+/// Returns the in-memory state of `db`, if it has already been created.
+pub(crate) fn get(db: &Db) -> Option<Arc<DbState>> {
+    find(address(db))
+}
+
+fn find(address: usize) -> Option<Arc<DbState>> {
+    states()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&address)
+        .and_then(Weak::upgrade)
+}
+
+impl DbState {
+    /// Held while writing to the database outside of a transaction.
+    pub(crate) fn write(&self) -> RwLockReadGuard<'_, ()> {
+        self.writes.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Held while a transaction checks its scans and commits, and while the whole database is read or rewritten
+    /// at once.
+    pub(crate) fn commit(&self) -> RwLockWriteGuard<'_, ()> {
+        self.writes.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
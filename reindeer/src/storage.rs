@@ -0,0 +1,86 @@
+use sled::{Db, IVec};
+
+use crate::cipher::Cipher;
+use crate::error::Result;
+use crate::state;
+
+/// Low-level access to the trees of a database, by tree name.
+///
+/// This is implemented for `sled::Db` as well as for [`Transaction`](struct.Transaction.html), so that
+/// the logic handling relations and deletion behaviours can run inside or outside of a transaction.
+pub(crate) trait Storage {
+    /// Gets the value stored for `key` in `tree`
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<IVec>>;
+
+    /// Stores `value` for `key` in `tree`
+    fn insert(&self, tree: &str, key: &[u8], value: IVec) -> Result<()>;
+
+    /// Seals `value` with the cipher of the database and stores it for `key` in `tree`
+    fn insert_sealed(&self, tree: &str, key: &[u8], value: IVec) -> Result<()>;
+
+    /// Removes `key` from `tree`
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()>;
+
+    /// Returns every entry of `tree` whose key starts with `prefix`, in key order
+    fn scan_prefix(&self, tree: &str, prefix: &[u8]) -> Result<Vec<(IVec, IVec)>>;
+
+    /// Returns the cipher used to seal and open the values of encrypted trees
+    fn cipher(&self) -> Result<Cipher>;
+
+    /// Checks if `key` exists in `tree`
+    fn contains_key(&self, tree: &str, key: &[u8]) -> Result<bool> {
+        Ok(self.get(tree, key)?.is_some())
+    }
+
+    /// Checks if at least one key of `tree` starts with `prefix`
+    fn has_prefix(&self, tree: &str, prefix: &[u8]) -> Result<bool> {
+        Ok(!self.scan_prefix(tree, prefix)?.is_empty())
+    }
+}
+
+impl Storage for Db {
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<IVec>> {
+        Ok(self.open_tree(tree)?.get(key)?)
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: IVec) -> Result<()> {
+        let state = state::of(self)?;
+        let _write = state.write();
+        self.open_tree(tree)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn insert_sealed(&self, tree: &str, key: &[u8], value: IVec) -> Result<()> {
+        let state = state::of(self)?;
+        let _write = state.write();
+        let value = Cipher::current(&state).seal(tree, key, value)?;
+        self.open_tree(tree)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()> {
+        let state = state::of(self)?;
+        let _write = state.write();
+        self.open_tree(tree)?.remove(key)?;
+        Ok(())
+    }
+
+    fn scan_prefix(&self, tree: &str, prefix: &[u8]) -> Result<Vec<(IVec, IVec)>> {
+        Ok(self
+            .open_tree(tree)?
+            .scan_prefix(prefix)
+            .collect::<std::result::Result<Vec<(IVec, IVec)>, sled::Error>>()?)
+    }
+
+    fn cipher(&self) -> Result<Cipher> {
+        Cipher::of(self)
+    }
+
+    fn contains_key(&self, tree: &str, key: &[u8]) -> Result<bool> {
+        Ok(self.open_tree(tree)?.contains_key(key)?)
+    }
+
+    fn has_prefix(&self, tree: &str, prefix: &[u8]) -> Result<bool> {
+        Ok(self.open_tree(tree)?.scan_prefix(prefix).next().is_some())
+    }
+}
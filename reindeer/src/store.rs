@@ -0,0 +1,762 @@
+//! # Store Module
+//! This module provides [`Store`](struct.Store.html), a handle on the store of an entity type that keeps its trees open,
+//! for code that reads or writes many entities of the same type.
+//!
+//! ### Example
+//! ```rust
+//! use reindeer::OpenStore;
+//! # use reindeer::{Entity, Serialize, Deserialize};
+//! # #[derive(Serialize, Deserialize, Entity)]
+//! # #[entity(name = "user")]
+//! # struct User { id : u32 }
+//! # fn main() -> reindeer::Result<()> {
+//! # let db = sled::Config::new().temporary(true).open()?;
+//! # User::register(&db)?;
+//! # let keys = vec![1, 2, 3];
+//!
+//! let users = db.store::<User>()?;
+//! for key in keys {
+//!     if let Some(user) = users.get(&key)? {
+//!         /* ... */
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::iter::Rev;
+use std::marker::PhantomData;
+use std::sync::{Arc, RwLock};
+
+use serde::{de::DeserializeOwned, Serialize};
+use sled::transaction::ConflictableTransactionError;
+use sled::{Db, IVec, Transactional, Tree};
+
+use crate::cipher::Cipher;
+use crate::error::Result;
+use crate::iter::EntityIter;
+use crate::key::{AsBytes, FromBytes};
+use crate::page::{self, Cursor, Page};
+use crate::registry;
+use crate::state::{self, DbState};
+use crate::storage::Storage;
+use crate::transaction::{self, Transaction};
+use crate::relation::{edge, FamilyDescriptor, Relation, RelationDescriptor};
+use crate::subscription::Subscription;
+use crate::{DeletionBehaviour, Entity, Error};
+
+/// Changes to the edges of a relation tree : the edge key, and its new value before it is sealed or `None` to remove it.
+type EdgeChanges = Vec<(Vec<u8>, Option<IVec>)>;
+
+/// A cached family descriptor, along with the generation of the families it was read at.
+type CachedFamily = (u64, Option<Arc<FamilyDescriptor>>);
+
+/// A handle on the store of `E`, holding its entity tree, its relation tree and its family descriptor,
+/// so that they are not looked up again on every call.
+///
+/// It provides the same methods as [`Entity`](trait.Entity.html), without the `db` argument. Reads, unindexed
+/// saves and updates, removals and the free relations of `E` use the cached trees, as do the reads of its children
+/// and related entities, whose trees are kept once opened. Indexed writes and the writes to the siblings and children
+/// of `E` go through the same code as `Entity`, so that related entities are handled the same way.
+///
+/// A `Store` is cheap to clone, and can be sent to other threads. It is obtained with [`Store::open`](#method.open)
+/// or [`OpenStore::store`](trait.OpenStore.html#tymethod.store). The family descriptor is read again
+/// whenever entities have been registered since it was cached.
+pub struct Store<E> {
+    db: Db,
+    tree: Tree,
+    edges: Tree,
+    state: Arc<DbState>,
+    family: Arc<RwLock<CachedFamily>>,
+    /// The trees of the stores read through this one, opened the first time they are read
+    related: Arc<RwLock<HashMap<&'static str, Tree>>>,
+    entity: PhantomData<fn() -> E>,
+}
+
+impl<E> Clone for Store<E> {
+    fn clone(&self) -> Self {
+        Store {
+            db: self.db.clone(),
+            tree: self.tree.clone(),
+            edges: self.edges.clone(),
+            state: self.state.clone(),
+            family: self.family.clone(),
+            related: self.related.clone(),
+            entity: PhantomData,
+        }
+    }
+}
+
+/// Opens [`Store`](struct.Store.html) handles from a database.
+pub trait OpenStore {
+    /// Opens a handle on the store of `E`.
+    fn store<E: Entity>(&self) -> Result<Store<E>>;
+}
+
+impl OpenStore for Db {
+    fn store<E: Entity>(&self) -> Result<Store<E>> {
+        Store::open(self)
+    }
+}
+
+impl<E: Entity> Store<E> {
+    /// Opens a handle on the store of `E`.
+    pub fn open(db: &Db) -> Result<Store<E>> {
+        let generation = registry::families_generation();
+        Ok(Store {
+            db: db.clone(),
+            tree: E::get_tree(db)?,
+            edges: db.open_tree(Relation::tree_name(E::store_name()))?,
+            state: state::of(db)?,
+            family: Arc::new(RwLock::new((
+                generation,
+                FamilyDescriptor::get(&String::from(E::store_name()), db)?.map(Arc::new),
+            ))),
+            related: Arc::new(RwLock::new(HashMap::new())),
+            entity: PhantomData,
+        })
+    }
+
+    /// The database this store belongs to.
+    pub fn db(&self) -> &Db {
+        &self.db
+    }
+
+    /// Checks whether `E` has been registered.
+    pub fn is_registered(&self) -> Result<bool> {
+        Ok(self.family()?.is_some())
+    }
+
+    /// The child stores of `E` and their deletion behaviour, as registered.
+    pub fn child_stores(&self) -> Result<Vec<(String, DeletionBehaviour)>> {
+        Ok(self
+            .family()?
+            .map(|family| family.child_trees.clone())
+            .unwrap_or_default())
+    }
+
+    /// The sibling stores of `E` and their deletion behaviour, as registered.
+    pub fn sibling_stores(&self) -> Result<Vec<(String, DeletionBehaviour)>> {
+        Ok(self
+            .family()?
+            .map(|family| family.sibling_trees.clone())
+            .unwrap_or_default())
+    }
+
+    /// The family descriptor of `E`, read again if families have been written since it was cached.
+    fn family(&self) -> Result<Option<Arc<FamilyDescriptor>>> {
+        let generation = registry::families_generation();
+        {
+            let cached = self.family.read().unwrap_or_else(|e| e.into_inner());
+            if cached.0 == generation {
+                return Ok(cached.1.clone());
+            }
+        }
+        let family = FamilyDescriptor::get(&String::from(E::store_name()), &self.db)?.map(Arc::new);
+        *self.family.write().unwrap_or_else(|e| e.into_inner()) = (generation, family.clone());
+        Ok(family)
+    }
+
+    /// Retrieves an entity given its key. See [`Entity::get`](trait.Entity.html#method.get).
+    pub fn get(&self, key: &E::Key) -> Result<Option<E>> {
+        self.get_from_u8_array(&key.as_bytes())
+    }
+
+    fn get_from_u8_array(&self, key: &[u8]) -> Result<Option<E>> {
+        self.tree
+            .get(key)?
+            .map(|value| E::try_from_ivec(Cipher::current(&self.state).open(E::store_name(), key, value)?))
+            .transpose()
+    }
+
+    /// Retrieves all entities of this store. See [`Entity::get_all`](trait.Entity.html#method.get_all).
+    pub fn get_all(&self) -> Result<Vec<E>> {
+        self.iter().collect()
+    }
+
+    /// Returns a lazy iterator over all entities of this store, in key order. See [`Entity::iter`](trait.Entity.html#method.iter).
+    pub fn iter(&self) -> EntityIter<E> {
+        EntityIter::new(self.tree.iter(), Cipher::current(&self.state))
+    }
+
+    /// Returns a lazy iterator over all entities of this store, in reverse key order.
+    pub fn iter_rev(&self) -> Rev<EntityIter<E>> {
+        self.iter().rev()
+    }
+
+    /// Returns a lazy iterator over the entities whose key starts with `prefix`, in key order.
+    /// See [`Entity::iter_prefix`](trait.Entity.html#method.iter_prefix).
+    pub fn iter_prefix(&self, prefix: &impl AsBytes) -> EntityIter<E> {
+        EntityIter::new(self.tree.scan_prefix(prefix.as_bytes()), Cipher::current(&self.state))
+    }
+
+    /// Returns a lazy iterator over the entities with keys from `start` (included) to `end` (excluded), in key order.
+    pub fn iter_range(&self, start: impl AsBytes, end: impl AsBytes) -> EntityIter<E> {
+        EntityIter::new(
+            self.tree.range(start.as_bytes()..end.as_bytes()),
+            Cipher::current(&self.state),
+        )
+    }
+
+    /// Returns the number of entities of this store.
+    pub fn get_count(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns the keys of all entities of this store, in order, without deserializing the entities.
+    pub fn get_keys(&self) -> Result<Vec<E::Key>> {
+        self.tree
+            .iter()
+            .keys()
+            .map(|key| -> Result<E::Key> { E::Key::from_bytes(&key?) })
+            .collect()
+    }
+
+    /// Returns the keys of the entities whose key starts with `prefix`, in order, without deserializing the entities.
+    pub fn get_keys_with_prefix(&self, prefix: &impl AsBytes) -> Result<Vec<E::Key>> {
+        self.tree
+            .scan_prefix(prefix.as_bytes())
+            .keys()
+            .map(|key| -> Result<E::Key> { E::Key::from_bytes(&key?) })
+            .collect()
+    }
+
+    /// Gets the entities whose key starts with `prefix`, such as the children of an entity.
+    pub fn get_with_prefix(&self, prefix: &impl AsBytes) -> Result<Vec<E>> {
+        self.iter_prefix(prefix).collect()
+    }
+
+    /// Gets the entities with keys from `start` (included) to `end` (excluded).
+    pub fn get_in_range(&self, start: impl AsBytes, end: impl AsBytes) -> Result<Vec<E>> {
+        self.iter_range(start, end).collect()
+    }
+
+    /// Gets `count` entities starting at index `start`, optionally among the children of `parent`.
+    /// See [`Entity::get_from_start`](trait.Entity.html#method.get_from_start).
+    pub fn get_from_start(&self, start: usize, count: usize, parent: Option<impl AsBytes>) -> Result<Vec<E>> {
+        let iter = match parent {
+            Some(prefix) => self.iter_prefix(&prefix),
+            None => self.iter(),
+        };
+        iter.skip(start).take(count).collect()
+    }
+
+    /// Gets `count` entities starting at index `start` from the end, optionally among the children of `parent`.
+    /// See [`Entity::get_from_end`](trait.Entity.html#method.get_from_end).
+    pub fn get_from_end(&self, start: usize, count: usize, parent: Option<impl AsBytes>) -> Result<Vec<E>> {
+        let iter = match parent {
+            Some(prefix) => self.iter_prefix(&prefix),
+            None => self.iter(),
+        };
+        let mut result = iter.rev().skip(start).take(count).collect::<Result<Vec<E>>>()?;
+        result.reverse();
+        Ok(result)
+    }
+
+    /// Gets the entities matching a collection of keys, leaving out the missing ones.
+    pub fn get_each(&self, keys: &[E::Key]) -> Result<Vec<E>> {
+        keys.iter()
+            .filter_map(|key| self.get(key).transpose())
+            .collect()
+    }
+
+    fn get_each_u8(&self, keys: &[Vec<u8>]) -> Result<Vec<E>> {
+        keys.iter()
+            .filter_map(|key| self.get_from_u8_array(key).transpose())
+            .collect()
+    }
+
+    /// Gets all entities of this store matching a condition. See [`Entity::get_with_filter`](trait.Entity.html#method.get_with_filter).
+    pub fn get_with_filter<F: Fn(&E) -> bool>(&self, f: F) -> Result<Vec<E>> {
+        self.iter()
+            .filter(|e| e.as_ref().map_or(true, &f))
+            .collect()
+    }
+
+    /// Gets the first entity whose indexed `field` has the given value.
+    pub fn get_by_index(&self, field: &str, value: &impl AsBytes) -> Result<Option<E>> {
+        E::get_by_index(field, value, &self.db)
+    }
+
+    /// Gets every entity whose indexed `field` has the given value.
+    pub fn find_by_index(&self, field: &str, value: &impl AsBytes) -> Result<Vec<E>> {
+        E::find_by_index(field, value, &self.db)
+    }
+
+    /// Gets a page of entities after `cursor`. See [`Entity::page_after`](trait.Entity.html#method.page_after).
+    pub fn page_after(
+        &self,
+        cursor: Option<&Cursor>,
+        count: usize,
+        parent: Option<impl AsBytes>,
+    ) -> Result<Page<E>> {
+        let prefix = parent.map(|p| p.as_bytes()).unwrap_or_default();
+        page::page_after(&self.tree, &Cipher::current(&self.state), &prefix, cursor, count)
+    }
+
+    /// Gets a page of entities before `cursor`. See [`Entity::page_before`](trait.Entity.html#method.page_before).
+    pub fn page_before(
+        &self,
+        cursor: Option<&Cursor>,
+        count: usize,
+        parent: Option<impl AsBytes>,
+    ) -> Result<Page<E>> {
+        let prefix = parent.map(|p| p.as_bytes()).unwrap_or_default();
+        page::page_before(&self.tree, &Cipher::current(&self.state), &prefix, cursor, count)
+    }
+
+    /// Subscribes to the changes of this store. See [`Entity::subscribe`](trait.Entity.html#method.subscribe).
+    pub fn subscribe(&self) -> Subscription<E> {
+        Subscription::new(self.tree.watch_prefix(vec![]), Cipher::current(&self.state))
+    }
+
+    /// Subscribes to the changes of the entities whose key starts with `prefix`, such as the children of an entity.
+    /// See [`Entity::subscribe_prefix`](trait.Entity.html#method.subscribe_prefix).
+    pub fn subscribe_prefix(&self, prefix: &impl AsBytes) -> Subscription<E> {
+        Subscription::new(self.tree.watch_prefix(prefix.as_bytes()), Cipher::current(&self.state))
+    }
+
+    /// Checks if an entity exists, without fetching it.
+    pub fn exists(&self, key: &E::Key) -> Result<bool> {
+        Ok(self.tree.contains_key(key.as_bytes())?)
+    }
+
+    /// Saves an entity. See [`Entity::save`](trait.Entity.html#method.save).
+    pub fn save(&self, entity: &E) -> Result<()> {
+        if !E::get_indexes().is_empty() {
+            return entity.save(&self.db);
+        }
+        self.check_registered()?;
+        let key = entity.get_key().as_bytes();
+        let value = entity.try_into_ivec()?;
+        let _write = self.state.write();
+        self.tree.insert(&key, Cipher::current(&self.state).seal(E::store_name(), &key, value)?)?;
+        Ok(())
+    }
+
+    /// Updates an entity using the provided function. See [`Entity::update`](trait.Entity.html#method.update).
+    pub fn update<F: Fn(&mut E)>(&self, key: &E::Key, f: F) -> Result<()> {
+        if !E::get_indexes().is_empty() {
+            return E::update(key, f, &self.db);
+        }
+        let key = key.as_bytes();
+        // An entity that cannot be read or written again is left unchanged, and the error returned
+        let mut error = None;
+        let _write = self.state.write();
+        let cipher = Cipher::current(&self.state);
+        self.tree.fetch_and_update(&key, |stored| {
+            error = None;
+            let stored = stored?;
+            let updated = cipher
+                .open(E::store_name(), &key, IVec::from(stored))
+                .and_then(E::try_from_ivec)
+                .and_then(|mut value| {
+                    f(&mut value);
+                    cipher.seal(E::store_name(), &key, value.try_into_ivec()?)
+                });
+            match updated {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    error = Some(e);
+                    Some(IVec::from(stored))
+                }
+            }
+        })?;
+        error.map_or(Ok(()), Err)
+    }
+
+    /// Updates every entity matching `filter` with `modifier`. See [`Entity::filter_update`](trait.Entity.html#method.filter_update).
+    pub fn filter_update<F: Fn(&E) -> bool, M: Fn(&mut E)>(&self, filter: F, modifier: M) -> Result<()> {
+        for mut entity in self.get_with_filter(filter)? {
+            modifier(&mut entity);
+            self.save(&entity)?;
+        }
+        Ok(())
+    }
+
+    /// Removes an entity given its key, along with the entities that must be removed with it.
+    /// See [`Entity::remove`](trait.Entity.html#method.remove).
+    pub fn remove(&self, key: &E::Key) -> Result<()> {
+        let key = key.as_bytes();
+        if E::use_pre_remove_hook() {
+            E::can_be_removed(&key, &self.db)?;
+            E::pre_remove_hook(
+                &self.get_from_u8_array(&key)?.ok_or(Error::new(
+                    crate::ErrorKind::IntegrityError,
+                    "Entry was not found".to_string(),
+                ))?,
+                &self.db,
+            )?;
+        }
+        self.transaction(|tx| tx.remove_from_u8_array(E::store_name(), &key))
+    }
+
+    /// Removes the entities whose key starts with `prefix`. See [`Entity::remove_prefixed`](trait.Entity.html#method.remove_prefixed).
+    pub fn remove_prefixed(&self, prefix: impl AsBytes) -> Result<()> {
+        let prefix = prefix.as_bytes();
+        self.transaction(|tx| {
+            for (key, _) in Storage::scan_prefix(tx, E::store_name(), &prefix)? {
+                tx.remove_from_u8_array(E::store_name(), &key)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Removes the entities matching a condition, and returns them. See [`Entity::filter_remove`](trait.Entity.html#method.filter_remove).
+    pub fn filter_remove<F: Fn(&E) -> bool>(&self, f: F) -> Result<Vec<E>> {
+        let mut res = self.get_with_filter(f)?;
+        res.retain(|entity| self.remove(entity.get_key()).is_ok());
+        Ok(res)
+    }
+
+    /// Saves `sibling` in its own store with the key of `entity`. See [`Entity::save_sibling`](trait.Entity.html#method.save_sibling).
+    pub fn save_sibling<E2: Entity<Key = E::Key>>(&self, entity: &E, sibling: &mut E2) -> Result<()> {
+        entity.save_sibling(sibling, &self.db)
+    }
+
+    /// Gets the sibling of `entity` in the store of `E2`. See [`Entity::get_sibling`](trait.Entity.html#method.get_sibling).
+    pub fn get_sibling<E2: Entity<Key = E::Key>>(&self, entity: &E) -> Result<Option<E2>> {
+        entity.get_sibling(&self.db)
+    }
+
+    /// Saves `child` in its own store as a child of `parent`. See [`Entity::save_child`](trait.Entity.html#method.save_child).
+    pub fn save_child<E2: Entity<Key = (E::Key, T)>, T: Clone>(&self, parent: &E, child: &mut E2) -> Result<E2::Key> {
+        parent.save_child(child, &self.db)
+    }
+
+    /// Saves `child` in its own store as the next child of `parent`.
+    /// See [`Entity::save_next_child`](trait.Entity.html#method.save_next_child).
+    pub fn save_next_child<E2: Entity<Key = (E::Key, u32)>>(&self, parent: &E, child: &mut E2) -> Result<E2::Key> {
+        parent.save_next_child(child, &self.db)
+    }
+
+    /// Reparents `child` to `parent`. See [`Entity::adopt_child`](trait.Entity.html#method.adopt_child).
+    pub fn adopt_child<E2: Entity<Key = (E::Key, T)>, T: Clone + AsBytes>(&self, parent: &E, child: &mut E2) -> Result<()>
+    where
+        E::Key: PartialEq,
+    {
+        parent.adopt_child(child, &self.db)
+    }
+
+    /// Reparents `child` to `parent` as its next child. See [`Entity::adopt_as_next_child`](trait.Entity.html#method.adopt_as_next_child).
+    pub fn adopt_as_next_child<E2: Entity<Key = (E::Key, u32)>>(&self, parent: &E, child: &mut E2) -> Result<()> {
+        parent.adopt_as_next_child(child, &self.db)
+    }
+
+    /// Gets the children of `parent` in the store of `E2`. See [`Entity::get_children`](trait.Entity.html#method.get_children).
+    pub fn get_children<E2: Entity<Key = (E::Key, T)>, T: AsBytes>(&self, parent: &E) -> Result<Vec<E2>> {
+        let tree = self.related_tree::<E2>()?;
+        EntityIter::new(tree.scan_prefix(parent.get_key().as_bytes()), Cipher::current(&self.state)).collect()
+    }
+
+    /// Gets the entities of `E2` related to `entity`. See [`Entity::get_related`](trait.Entity.html#method.get_related).
+    pub fn get_related<E2: Entity>(&self, entity: &E) -> Result<Vec<E2>> {
+        let keys = self
+            .links::<E2>(entity)?
+            .into_iter()
+            .map(|rd| rd.key)
+            .collect::<Vec<Vec<u8>>>();
+        self.get_each_related::<E2>(&keys)
+    }
+
+    /// Gets the entities of `E2` related to `entity` by relations named `name`.
+    pub fn get_related_with_name<E2: Entity>(&self, entity: &E, name: &str) -> Result<Vec<E2>> {
+        let keys = self
+            .links::<E2>(entity)?
+            .into_iter()
+            .filter(|rd| rd.name.as_deref() == Some(name))
+            .map(|rd| rd.key)
+            .collect::<Vec<Vec<u8>>>();
+        self.get_each_related::<E2>(&keys)
+    }
+
+    /// Gets the entities of `E2` related to `entity` along with the data carried by their relation.
+    /// See [`Entity::get_related_with_data`](trait.Entity.html#method.get_related_with_data).
+    pub fn get_related_with_data<E2: Entity, D: DeserializeOwned>(&self, entity: &E) -> Result<Vec<(E2, D)>> {
+        let mut related = Vec::new();
+        for rd in self.links::<E2>(entity)? {
+            if let (Some(data), Some(other)) = (&rd.data, self.get_related_from_u8_array::<E2>(&rd.key)?) {
+                related.push((other, bincode::deserialize(data)?));
+            }
+        }
+        Ok(related)
+    }
+
+    /// Gets the first entity of `E2` related to `entity`. See [`Entity::get_single_related`](trait.Entity.html#method.get_single_related).
+    pub fn get_single_related<E2: Entity>(&self, entity: &E) -> Result<Option<E2>> {
+        match self.links::<E2>(entity)?.first() {
+            Some(rd) => self.get_related_from_u8_array::<E2>(&rd.key),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets the first entity of `E2` related to `entity` by a relation named `name`.
+    pub fn get_single_related_with_name<E2: Entity>(&self, entity: &E, name: &str) -> Result<Option<E2>> {
+        match self
+            .links::<E2>(entity)?
+            .iter()
+            .find(|rd| rd.name.as_deref() == Some(name))
+        {
+            Some(rd) => self.get_related_from_u8_array::<E2>(&rd.key),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks if a free relation exists between `entity` and `other`.
+    pub fn is_related_to<E2: Entity>(&self, entity: &E, other: &E2) -> Result<bool> {
+        let prefix = edge::link_prefix(&entity.get_key().as_bytes(), E2::store_name(), &other.get_key().as_bytes());
+        Ok(self.edges.scan_prefix(prefix).next().transpose()?.is_some())
+    }
+
+    /// Checks if a free relation named `name` exists between `entity` and `other`.
+    pub fn is_related_to_with_name<E2: Entity>(&self, entity: &E, other: &E2, name: &str) -> Result<bool> {
+        let key = edge::key(&entity.get_key().as_bytes(), E2::store_name(), &other.get_key().as_bytes(), Some(name));
+        Ok(self.edges.contains_key(key)?)
+    }
+
+    /// Gets the keys of the entities of `E2` related to `entity`, without reading them.
+    pub fn get_related_keys<E2: Entity>(&self, entity: &E) -> Result<Vec<E2::Key>> {
+        self.links::<E2>(entity)?
+            .iter()
+            .map(|rd| E2::Key::from_bytes(&rd.key))
+            .collect()
+    }
+
+    /// Gets the keys of the entities of `E2` related to `entity` by relations named `name`, without reading them.
+    pub fn get_related_keys_with_name<E2: Entity>(&self, entity: &E, name: &str) -> Result<Vec<E2::Key>> {
+        self.links::<E2>(entity)?
+            .iter()
+            .filter(|rd| rd.name.as_deref() == Some(name))
+            .map(|rd| E2::Key::from_bytes(&rd.key))
+            .collect()
+    }
+
+    /// Gets the entities of this store related to `entity`, read through this handle.
+    /// This is the counterpart of [`get_related`](#method.get_related), for relations followed towards this store.
+    pub fn get_related_to<E2: Entity>(&self, entity: &E2) -> Result<Vec<E>> {
+        let keys = Relation::links_to_store_with_tree_names(
+            E2::store_name(),
+            &entity.get_key().as_bytes(),
+            E::store_name(),
+            &self.db,
+        )?
+        .into_iter()
+        .map(|rd| rd.key)
+        .collect::<Vec<Vec<u8>>>();
+        self.get_each_u8(&keys)
+    }
+
+    /// Creates a free relation between `entity` and `other`. See [`Entity::create_relation`](trait.Entity.html#method.create_relation).
+    pub fn create_relation<E2: Entity>(
+        &self,
+        entity: &E,
+        other: &E2,
+        self_to_other: DeletionBehaviour,
+        other_to_self: DeletionBehaviour,
+        name: Option<&str>,
+    ) -> Result<()> {
+        Relation::check_declared::<E, E2>(name, self_to_other, other_to_self)?;
+        let (key, other_key) = (entity.get_key().as_bytes(), other.get_key().as_bytes());
+        self.insert_links::<E2>(
+            &key,
+            &RelationDescriptor::new(&other_key, self_to_other, name),
+            &RelationDescriptor::new(&key, other_to_self, name),
+        )
+    }
+
+    /// Creates a free relation between `entity` and `other`, carrying `data`.
+    /// See [`Entity::create_relation_with_data`](trait.Entity.html#method.create_relation_with_data).
+    pub fn create_relation_with_data<E2: Entity, D: Serialize>(
+        &self,
+        entity: &E,
+        other: &E2,
+        self_to_other: DeletionBehaviour,
+        other_to_self: DeletionBehaviour,
+        name: Option<&str>,
+        data: &D,
+    ) -> Result<()> {
+        Relation::check_declared::<E, E2>(name, self_to_other, other_to_self)?;
+        let data = bincode::serialize(data)?;
+        let (key, other_key) = (entity.get_key().as_bytes(), other.get_key().as_bytes());
+        let mut link = RelationDescriptor::new(&other_key, self_to_other, name);
+        link.data = Some(data.clone());
+        let mut back = RelationDescriptor::new(&key, other_to_self, name);
+        back.data = Some(data);
+        self.insert_links::<E2>(&key, &link, &back)
+    }
+
+    /// Replaces the data carried by the relation named `name` between `entity` and `other`.
+    /// See [`Entity::update_relation_data`](trait.Entity.html#method.update_relation_data).
+    pub fn update_relation_data<E2: Entity, D: Serialize>(
+        &self,
+        entity: &E,
+        other: &E2,
+        name: Option<&str>,
+        data: &D,
+    ) -> Result<()> {
+        entity.update_relation_data(other, name, data, &self.db)
+    }
+
+    /// Breaks every link between `entity` and `other`. See [`Entity::remove_relation`](trait.Entity.html#method.remove_relation).
+    pub fn remove_relation<E2: Entity>(&self, entity: &E, other: &E2) -> Result<()> {
+        let (key, other_key) = (entity.get_key().as_bytes(), other.get_key().as_bytes());
+        let removed = |tree: &Tree, prefix: Vec<u8>| {
+            tree.scan_prefix(prefix)
+                .keys()
+                .map(|edge| Ok((edge?.to_vec(), None)))
+                .collect::<Result<EdgeChanges>>()
+        };
+        let changes = removed(&self.edges, edge::link_prefix(&key, E2::store_name(), &other_key))?;
+        let other_changes = removed(
+            &self.edges_of(E2::store_name())?,
+            edge::link_prefix(&other_key, E::store_name(), &key),
+        )?;
+        self.write_edges(E2::store_name(), changes, other_changes)
+    }
+
+    /// Breaks the link named `name` between `entity` and `other`.
+    /// See [`Entity::remove_relation_with_name`](trait.Entity.html#method.remove_relation_with_name).
+    pub fn remove_relation_with_name<E2: Entity>(&self, entity: &E, other: &E2, name: &str) -> Result<()> {
+        let (key, other_key) = (entity.get_key().as_bytes(), other.get_key().as_bytes());
+        self.write_edges(
+            E2::store_name(),
+            vec![(edge::key(&key, E2::store_name(), &other_key, Some(name)), None)],
+            vec![(edge::key(&other_key, E::store_name(), &key, Some(name)), None)],
+        )
+    }
+
+    /// Writes `link` from the entity `key` of this store and `back` to it, from the related entity of `E2`.
+    fn insert_links<E2: Entity>(&self, key: &[u8], link: &RelationDescriptor, back: &RelationDescriptor) -> Result<()> {
+        let edge = |key: &[u8], related_store: &str, rd: &RelationDescriptor| {
+            let edge_key = edge::key(key, related_store, &rd.key, rd.name.as_deref());
+            Ok::<(Vec<u8>, Option<IVec>), Error>((edge_key, Some(edge::encode_value(rd)?)))
+        };
+        self.write_edges(
+            E2::store_name(),
+            vec![edge(key, E2::store_name(), link)?],
+            vec![edge(&link.key, E::store_name(), back)?],
+        )
+    }
+
+    /// The relation tree of `store`, which is the cached one for the store of `E`.
+    fn edges_of(&self, store: &str) -> Result<Tree> {
+        if store == E::store_name() {
+            return Ok(self.edges.clone());
+        }
+        Ok(self.db.open_tree(Relation::tree_name(store))?)
+    }
+
+    /// Applies `changes` to the cached relation tree and `other_changes` to the relation tree of `other_store`,
+    /// in a single transaction. New values are sealed right before being written.
+    fn write_edges(&self, other_store: &str, changes: EdgeChanges, other_changes: EdgeChanges) -> Result<()> {
+        let _write = self.state.write();
+        let cipher = Cipher::current(&self.state);
+        let sealed = |store: &str, changes: EdgeChanges| {
+            let tree = Relation::tree_name(store);
+            changes
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = value.map(|value| cipher.seal(&tree, &key, value)).transpose()?;
+                    Ok((key, value))
+                })
+                .collect::<Result<EdgeChanges>>()
+        };
+        let mut trees = vec![self.edges.clone()];
+        let mut all_changes = vec![sealed(E::store_name(), changes)?];
+        if other_store == E::store_name() {
+            all_changes[0].extend(sealed(other_store, other_changes)?);
+        } else {
+            trees.push(self.edges_of(other_store)?);
+            all_changes.push(sealed(other_store, other_changes)?);
+        }
+        trees.as_slice().transaction(|views| {
+            for (view, changes) in views.iter().zip(&all_changes) {
+                for (key, value) in changes {
+                    match value {
+                        Some(value) => view.insert(key.as_slice(), value.clone())?,
+                        None => view.remove(key.as_slice())?,
+                    };
+                }
+            }
+            Ok::<(), ConflictableTransactionError<Error>>(())
+        })?;
+        Ok(())
+    }
+
+    /// Reads the links of `entity` to the entities of `E2` from the cached relation tree.
+    fn links<E2: Entity>(&self, entity: &E) -> Result<Vec<RelationDescriptor>> {
+        let cipher = Cipher::current(&self.state);
+        let tree = Relation::tree_name(E::store_name());
+        self.edges
+            .scan_prefix(edge::store_prefix(&entity.get_key().as_bytes(), E2::store_name()))
+            .map(|elem| {
+                let (key, value) = elem?;
+                Ok(edge::decode(&key, &cipher.open(&tree, &key, value)?)?.1)
+            })
+            .collect()
+    }
+
+    /// Runs `f` in a transaction that uses the cached trees of `E`.
+    fn transaction<R>(&self, f: impl Fn(&Transaction) -> Result<R>) -> Result<R> {
+        let trees = [
+            (String::from(E::store_name()), self.tree.clone()),
+            (Relation::tree_name(E::store_name()), self.edges.clone()),
+        ];
+        transaction::transaction_with_trees(&self.db, &trees, f)
+    }
+
+    /// Returns the tree of the store of `E2`, opening it the first time it is read through this store.
+    fn related_tree<E2: Entity>(&self) -> Result<Tree> {
+        if let Some(tree) = self.related.read().unwrap_or_else(|e| e.into_inner()).get(E2::store_name()) {
+            return Ok(tree.clone());
+        }
+        let tree = E2::get_tree(&self.db)?;
+        self.related
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(E2::store_name(), tree.clone());
+        Ok(tree)
+    }
+
+    fn get_related_from_u8_array<E2: Entity>(&self, key: &[u8]) -> Result<Option<E2>> {
+        self.related_tree::<E2>()?
+            .get(key)?
+            .map(|value| E2::try_from_ivec(Cipher::current(&self.state).open(E2::store_name(), key, value)?))
+            .transpose()
+    }
+
+    fn get_each_related<E2: Entity>(&self, keys: &[Vec<u8>]) -> Result<Vec<E2>> {
+        keys.iter()
+            .filter_map(|key| self.get_related_from_u8_array(key).transpose())
+            .collect()
+    }
+
+    /// In strict mode, fails if `E` has not been registered.
+    fn check_registered(&self) -> Result<()> {
+        if !registry::is_strict(&self.db) || self.family()?.is_some() {
+            return Ok(());
+        }
+        registry::check_registered::<E>(&self.db, &self.db)
+    }
+}
+
+impl<E: Entity<Key = u32>> Store<E> {
+    /// Returns a new key that is currently not used in this store.
+    /// See [`AutoIncrementEntity::get_next_key`](trait.AutoIncrementEntity.html#tymethod.get_next_key).
+    pub fn get_next_key(&self) -> Result<u32> {
+        match self.tree.last()? {
+            Some((key, _)) => Ok(E::bytes_to_u32(key.as_ref())? + 1),
+            None => Ok(Default::default()),
+        }
+    }
+
+    /// Saves `entity` after having changed its key to an auto-incremented one.
+    /// See [`AutoIncrementEntity::save_next`](trait.AutoIncrementEntity.html#tymethod.save_next).
+    pub fn save_next(&self, entity: &mut E) -> Result<u32> {
+        let next_key = self.get_next_key()?;
+        entity.set_key(&next_key);
+        self.save(entity)?;
+        Ok(next_key)
+    }
+}
@@ -0,0 +1,113 @@
+//! # Subscription Module
+//! This module provides typed subscriptions to the changes made to an entity store.
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use sled::Subscriber;
+
+use crate::cipher::Cipher;
+use crate::entity::Entity;
+use crate::error::Result;
+use crate::key::FromBytes;
+
+/// A change made to an entity store.
+pub enum Event<T: Entity> {
+    /// An entity has been saved, either created or updated.
+    Inserted(T),
+    /// The entity with the given key has been removed.
+    Removed(T::Key),
+}
+
+/// A stream of the changes made to an entity store, obtained through
+/// [`Entity::subscribe`](entity/trait.Entity.html#method.subscribe) or
+/// [`Entity::subscribe_prefix`](entity/trait.Entity.html#method.subscribe_prefix).
+///
+/// Every change is reported, whether it comes from a save, a transaction, a cascade deletion or a child
+/// being adopted by another parent, as long as the change happens after the subscription is created.
+///
+/// `Subscription` is a blocking `Iterator` that ends when the database is closed, and a `Future`
+/// resolving to the next event for async code. With the `async` feature, it is also a `Stream`.
+///
+/// ### Example
+/// ```rust,no_run
+/// # use reindeer::{Entity, Serialize, Deserialize, Event};
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "my_struct", id = "key")]
+/// # struct MyStruct { key : u32 }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # MyStruct::register(&db)?;
+/// for event in MyStruct::subscribe(&db)? {
+///     match event? {
+///         Event::Inserted(entity) => { /* ... */ }
+///         Event::Removed(key) => { /* ... */ }
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Subscription<T> {
+    subscriber: Subscriber,
+    cipher: Cipher,
+    entity: PhantomData<fn() -> T>,
+}
+
+impl<T: Entity> Subscription<T> {
+    pub(crate) fn new(subscriber: Subscriber, cipher: Cipher) -> Subscription<T> {
+        Subscription {
+            subscriber,
+            cipher,
+            entity: PhantomData,
+        }
+    }
+
+    /// Waits for the next event for at most `timeout`.
+    /// Returns `None` if no event happened in time, or if the database has been closed.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<Result<Event<T>>> {
+        let event = self.subscriber.next_timeout(timeout).ok()?;
+        Some(self.decode(event))
+    }
+
+    fn decode(&self, event: sled::Event) -> Result<Event<T>> {
+        match event {
+            sled::Event::Insert { key, value } => {
+                let value = self.cipher.open(T::store_name(), &key, value)?;
+                Ok(Event::Inserted(T::try_from_ivec(value)?))
+            }
+            sled::Event::Remove { key } => Ok(Event::Removed(T::Key::from_bytes(&key)?)),
+        }
+    }
+}
+
+impl<T: Entity> Iterator for Subscription<T> {
+    type Item = Result<Event<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.subscriber.next()?;
+        Some(self.decode(event))
+    }
+}
+
+impl<T: Entity> Future for Subscription<T> {
+    type Output = Option<Result<Event<T>>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        Pin::new(&mut this.subscriber)
+            .poll(cx)
+            .map(|event| event.map(|event| this.decode(event)))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: Entity> futures_core::Stream for Subscription<T> {
+    type Item = Result<Event<T>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll(cx)
+    }
+}
@@ -1,18 +1,69 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
 mod test_entities;
 
 use crate::{
-    error::Result, relation::FamilyDescriptor,
-    test::test_entities::GrandChildEntity, AutoIncrementEntity, DeletionBehaviour, Entity,
-    QueryBuilder,
+    error::Result, ErrorKind, relation::{FamilyDescriptor, Relation},
+    test::test_entities::GrandChildEntity, AsBytes, AutoIncrementEntity, DeletionBehaviour, Entity,
+    Codec, Cursor, Event, FromBytes, FsckIssue, Page, OpenStore, QueryBuilder, RelationGroup, RepairOptions, SchemaIssue,
+    Traversal,
 };
 use test_entities::{
-    set_up, set_up_content, tear_down, ChildEntity1, ChildEntity2, Entity1, Entity2, Entity3,
+    set_up, set_up_content, tear_down, ChildEntity1, ChildEntity2, DefaultNamedEntity, Entity1, Entity2, Entity3,
+    Author, Book, Club, IndexedEntity, JsonEntity, OptionalIndexedEntity, SignedEntity, Student, VersionedEntity, VersionedEntityV0, VersionedEntityV1,
 };
 use uuid::Uuid;
 
 fn get_random_name() -> String {
     format!("reindeer-test-{}", Uuid::new_v4())
 }
+
+/// Opens a database that has just been dropped, waiting for its last handle to release the lock on its files.
+fn open_unlocked(config: &sled::Config) -> Result<sled::Db> {
+    let mut attempts = 0;
+    loop {
+        match config.open() {
+            Err(sled::Error::Io(_)) if attempts < 50 => {
+                attempts += 1;
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            db => return Ok(db?),
+        }
+    }
+}
+
+/// Relates the `Entity2` with the key `parent` to the `Entity1` entities with the given ids.
+fn relate_to_entity_2(parent: &str, ids: &[u32], name: Option<&str>, db: &sled::Db) -> Result<()> {
+    let e2 = Entity2::get(&String::from(parent), db)?.unwrap();
+    for id in ids {
+        let e1 = Entity1::get(id, db)?.unwrap();
+        e2.create_relation(&e1, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, name, db)?;
+    }
+    Ok(())
+}
+
+/// Returns the keys of the `Entity1` entities matching `query`.
+fn entity_1_keys(query: &QueryBuilder, db: &sled::Db) -> Result<Vec<u32>> {
+    query.get_keys::<Entity1>(db)
+}
+
+/// Builds a family descriptor from store names, so that it can describe stores that do not exist.
+fn family(
+    store: &str,
+    siblings: &[(&str, DeletionBehaviour)],
+    children: &[(&str, DeletionBehaviour)],
+) -> FamilyDescriptor {
+    let to_owned = |stores: &[(&str, DeletionBehaviour)]| {
+        stores.iter().map(|(store, behaviour)| (String::from(*store), *behaviour)).collect()
+    };
+    FamilyDescriptor {
+        tree_name: String::from(store),
+        sibling_trees: to_owned(siblings),
+        child_trees: to_owned(children),
+    }
+}
+
 #[test]
 fn create_and_register() -> Result<()> {
     let name = get_random_name();
@@ -468,7 +519,7 @@ fn test_query_builder() -> Result<()> {
     assert_eq!(result.len(), 3);
     let result_2 = QueryBuilder::new()
         .with_parent(&String::from("id3"))
-        .with_relation_to::<ChildEntity2>(&(2, 1))
+        .with_relation_to::<ChildEntity2>(&(2u32, 1u32))
         .get::<ChildEntity1>(&db)?;
     assert_eq!(result_2.len(), 1);
     let child_entity_1 = ChildEntity1::get(&(String::from("id3"), 1), &db)?.unwrap();
@@ -482,7 +533,7 @@ fn test_query_builder() -> Result<()> {
     )?;
     let result_3 = QueryBuilder::new()
         .with_parent(&String::from("id3"))
-        .with_relation_to::<ChildEntity2>(&(2, 1))
+        .with_relation_to::<ChildEntity2>(&(2u32, 1u32))
         .get::<ChildEntity1>(&db)?;
     assert_eq!(result_3.len(), 2);
     tear_down(&name)?;
@@ -523,4 +574,2000 @@ fn test_filtered_query_builder() -> Result<()> {
     assert_eq!(result[0].id,2);
     tear_down(&name)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_migrations() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    VersionedEntityV0::register(&db)?;
+    VersionedEntityV0 {
+        id: 0,
+        name: String::from("Ada Lovelace"),
+    }
+    .save(&db)?;
+    VersionedEntityV0 {
+        id: 1,
+        name: String::from("Grace Hopper"),
+    }
+    .save(&db)?;
+    VersionedEntity::register(&db)?;
+    let entities = VersionedEntity::get_all(&db)?;
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[0].first_name, "Ada");
+    assert_eq!(entities[0].last_name, "Lovelace");
+    assert_eq!(entities[1].first_name, "Grace");
+    assert!(entities[1].age.is_none());
+    VersionedEntity::register(&db)?;
+    assert_eq!(VersionedEntity::get_all(&db)?.len(), 2);
+    assert!(matches!(
+        VersionedEntityV1::register(&db).map_err(|e| e.kind()),
+        Err(ErrorKind::MigrationError)
+    ));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_migrations_unversioned_store() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    // Records saved before any version is recorded for the store
+    VersionedEntityV0 {
+        id: 0,
+        name: String::from("Ada Lovelace"),
+    }
+    .save(&db)?;
+    assert!(crate::migration::get_store_version(VersionedEntity::store_name(), &db)?.is_none());
+    VersionedEntity::register(&db)?;
+    let entities = VersionedEntity::get_all(&db)?;
+    assert_eq!(entities.len(), 1);
+    assert_eq!(entities[0].first_name, "Ada");
+    assert_eq!(entities[0].last_name, "Lovelace");
+    assert_eq!(crate::migration::get_store_version(VersionedEntity::store_name(), &db)?, Some(2));
+    // An empty store without a version is at the current version
+    VersionedEntity::get_tree(&db)?.clear()?;
+    db.open_tree(crate::migration::VERSION_TREE)?.clear()?;
+    VersionedEntityV1::register(&db)?;
+    assert_eq!(crate::migration::get_store_version(VersionedEntityV1::store_name(), &db)?, Some(1));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_default_store_name() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    assert_eq!(DefaultNamedEntity::store_name(), "DefaultNamedEntity");
+    DefaultNamedEntity::register(&db)?;
+    DefaultNamedEntity { id: 1 }.save(&db)?;
+    assert!(DefaultNamedEntity::exists(&1, &db)?);
+    assert_eq!(db.open_tree("DefaultNamedEntity")?.len(), 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_transaction() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e1 = Entity1 {
+        id: 10,
+        prop1: String::from("Transactional"),
+    };
+    crate::transaction(&db, |tx| {
+        tx.save(&e1)?;
+        tx.save_sibling(&e1, &mut Entity3 { id: 0, some_bool: true })?;
+        tx.create_relation(
+            &e1,
+            &e2_1,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            Some("rel1"),
+        )?;
+        assert!(tx.exists::<Entity1>(&10)?);
+        Ok(())
+    })?;
+    assert!(Entity1::exists(&10, &db)?);
+    assert!(Entity3::get(&10, &db)?.unwrap().some_bool);
+    assert!(e1.is_related_to_with_name(&e2_1, "rel1", &db)?);
+    assert!(e2_1.is_related_to(&e1, &db)?);
+
+    let e1_2 = Entity1 {
+        id: 11,
+        prop1: String::from("Rolled back"),
+    };
+    let result: Result<()> = crate::transaction(&db, |tx| {
+        tx.save(&e1_2)?;
+        tx.create_relation(
+            &e1_2,
+            &e2_1,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+        )?;
+        tx.remove::<Entity1>(&10)?;
+        Err(crate::Error::new(ErrorKind::IntegrityError, String::from("Abort")))
+    });
+    assert!(result.is_err());
+    assert!(!Entity1::exists(&11, &db)?);
+    assert!(!e2_1.is_related_to(&e1_2, &db)?);
+    assert!(Entity1::exists(&10, &db)?);
+    assert!(Entity3::exists(&10, &db)?);
+
+    crate::transaction(&db, |tx| tx.remove::<Entity1>(&10))?;
+    assert!(!Entity1::exists(&10, &db)?);
+    assert!(!Entity3::exists(&10, &db)?);
+    assert!(!e2_1.is_related_to(&e1, &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_transaction_conflicts() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let attempts = std::cell::Cell::new(0);
+    crate::transaction(&db, |tx| {
+        attempts.set(attempts.get() + 1);
+        tx.remove::<Entity2>(&String::from("id1"))?;
+        if attempts.get() == 1 {
+            ChildEntity1 {
+                id: (String::from("id1"), 7),
+            }
+            .save(&db)?;
+        }
+        Ok(())
+    })?;
+    assert_eq!(attempts.get(), 2);
+    assert!(!Entity2::exists(&String::from("id1"), &db)?);
+    assert!(!ChildEntity1::exists(&(String::from("id1"), 7), &db)?);
+
+    attempts.set(0);
+    let result = crate::transaction(&db, |tx| {
+        attempts.set(attempts.get() + 1);
+        tx.remove::<Entity2>(&String::from("id2"))?;
+        ChildEntity1 {
+            id: (String::from("id2"), 100 + attempts.get()),
+        }
+        .save(&db)
+    });
+    assert!(matches!(
+        result.map_err(|e| e.kind()),
+        Err(ErrorKind::TransactionConflict)
+    ));
+    assert_eq!(attempts.get(), 16);
+    assert!(Entity2::exists(&String::from("id2"), &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_transaction_isolation() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // Entity methods called with the database inside the closure are not part of the transaction
+    let result = crate::transaction(&db, |tx| {
+        tx.remove::<Entity2>(&String::from("id1"))?;
+        Entity2 {
+            id: String::from("id4"),
+            prop2: 4,
+        }
+        .save(&db)?;
+        Err::<(), _>(crate::Error::new(ErrorKind::IntegrityError, String::from("Rolled back")))
+    });
+    assert!(result.is_err());
+    assert!(Entity2::exists(&String::from("id1"), &db)?);
+    assert!(Entity2::exists(&String::from("id4"), &db)?);
+
+    // Writes made outside of a transaction wait for the transaction being committed to the same database
+    let state = crate::state::of(&db)?;
+    let commit = state.commit();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let writer = {
+        let db = db.clone();
+        std::thread::spawn(move || {
+            let result = ChildEntity1 {
+                id: (String::from("id1"), 7),
+            }
+            .save(&db);
+            sender.send(()).unwrap();
+            result
+        })
+    };
+    let other_name = get_random_name();
+    let other_db = set_up(&other_name)?;
+    crate::transaction(&other_db, |tx| tx.save(&Entity2 { id: String::from("id1"), prop2: 1 }))?;
+    assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+    drop(commit);
+    receiver.recv().unwrap();
+    writer.join().unwrap()?;
+    assert!(ChildEntity1::exists(&(String::from("id1"), 7), &db)?);
+
+    // The state of a database is dropped along with it
+    let weak = std::sync::Arc::downgrade(&state);
+    drop(state);
+    drop(db);
+    tear_down(&name)?;
+    assert!(weak.upgrade().is_none());
+    drop(other_db);
+    tear_down(&other_name)?;
+    Ok(())
+}
+
+#[test]
+fn test_indexes() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let mut e = IndexedEntity {
+        id: 0,
+        email: String::from("ada@example.com"),
+        city: String::from("London"),
+    };
+    e.save_next(&db)?;
+    e.email = String::from("grace@example.com");
+    e.city = String::from("New York");
+    e.save_next(&db)?;
+    e.email = String::from("alan@example.com");
+    e.city = String::from("London");
+    e.save_next(&db)?;
+    assert_eq!(
+        IndexedEntity::get_by_email(&String::from("grace@example.com"), &db)?
+            .unwrap()
+            .id,
+        1
+    );
+    assert_eq!(IndexedEntity::find_by_city(&String::from("London"), &db)?.len(), 2);
+    assert!(IndexedEntity::find_by_city(&String::from("Lond"), &db)?.is_empty());
+
+    e.email = String::from("ada@example.com");
+    assert!(matches!(
+        e.save(&db).map_err(|e| e.kind()),
+        Err(ErrorKind::UniqueConstraint)
+    ));
+    assert_eq!(
+        IndexedEntity::get(&2, &db)?.unwrap().email,
+        "alan@example.com"
+    );
+
+    IndexedEntity::update(&2, |e| e.city = String::from("Manchester"), &db)?;
+    assert_eq!(IndexedEntity::find_by_city(&String::from("London"), &db)?.len(), 1);
+    assert_eq!(
+        IndexedEntity::find_by_city(&String::from("Manchester"), &db)?[0].id,
+        2
+    );
+
+    IndexedEntity::remove(&0, &db)?;
+    assert!(IndexedEntity::get_by_email(&String::from("ada@example.com"), &db)?.is_none());
+    assert!(IndexedEntity::find_by_city(&String::from("London"), &db)?.is_empty());
+    e.id = 3;
+    e.save(&db)?;
+    assert_eq!(
+        IndexedEntity::get_by_email(&String::from("ada@example.com"), &db)?
+            .unwrap()
+            .id,
+        3
+    );
+
+    IndexedEntity::filter_remove(|e| e.city == "New York", &db)?;
+    assert!(IndexedEntity::get_by_email(&String::from("grace@example.com"), &db)?.is_none());
+    IndexedEntity::rebuild_indexes(&db)?;
+    assert_eq!(IndexedEntity::find_by_city(&String::from("Manchester"), &db)?.len(), 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_optional_indexes() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    OptionalIndexedEntity::register(&db)?;
+    OptionalIndexedEntity { id: 1, nickname: None }.save(&db)?;
+    OptionalIndexedEntity { id: 2, nickname: None }.save(&db)?;
+    let mut e = OptionalIndexedEntity {
+        id: 3,
+        nickname: Some(String::from("Ada")),
+    };
+    e.save(&db)?;
+    assert!(OptionalIndexedEntity::get_by_nickname(&None, &db)?.is_none());
+    assert_eq!(
+        OptionalIndexedEntity::get_by_nickname(&Some(String::from("Ada")), &db)?
+            .unwrap()
+            .id,
+        3
+    );
+    assert!(matches!(
+        OptionalIndexedEntity {
+            id: 4,
+            nickname: Some(String::from("Ada")),
+        }
+        .save(&db)
+        .map_err(|e| e.kind()),
+        Err(ErrorKind::UniqueConstraint)
+    ));
+
+    e.nickname = None;
+    e.save(&db)?;
+    assert!(OptionalIndexedEntity::get_by_nickname(&Some(String::from("Ada")), &db)?.is_none());
+    OptionalIndexedEntity::rebuild_indexes(&db)?;
+    OptionalIndexedEntity {
+        id: 4,
+        nickname: Some(String::from("Ada")),
+    }
+    .save(&db)?;
+    assert_eq!(OptionalIndexedEntity::get_all(&db)?.len(), 4);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_key_order() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    for id in [3, -20, 0, -1, i64::MIN, 7, i64::MAX] {
+        SignedEntity { id }.save(&db)?;
+    }
+    let ids = SignedEntity::get_all(&db)?
+        .iter()
+        .map(|e| e.id)
+        .collect::<Vec<i64>>();
+    assert_eq!(ids, vec![i64::MIN, -20, -1, 0, 3, 7, i64::MAX]);
+    let ids = SignedEntity::get_in_range(-20i64, 3i64, &db)?
+        .iter()
+        .map(|e| e.id)
+        .collect::<Vec<i64>>();
+    assert_eq!(ids, vec![-20, -1, 0]);
+    assert!(AsBytes::as_bytes(&String::from("ab")) < AsBytes::as_bytes(&String::from("abc")));
+    assert!(AsBytes::as_bytes(&String::from("a")) < AsBytes::as_bytes(&String::from("a\0")));
+    assert!((String::from("a"), 9u32).as_bytes() < (String::from("ab"), 0u32).as_bytes());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_key_prefix() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let ab = Entity2 {
+        id: String::from("ab"),
+        prop2: 0,
+    };
+    let abc = Entity2 {
+        id: String::from("abc"),
+        prop2: 0,
+    };
+    ab.save(&db)?;
+    abc.save(&db)?;
+    let mut child = ChildEntity1 {
+        id: (String::new(), 0),
+    };
+    ab.save_next_child(&mut child, &db)?;
+    abc.save_next_child(&mut child, &db)?;
+    abc.save_next_child(&mut child, &db)?;
+    let children: Vec<ChildEntity1> = ab.get_children(&db)?;
+    assert_eq!(children.len(), 1);
+    let result = QueryBuilder::new()
+        .with_parent(&String::from("ab"))
+        .get::<ChildEntity1>(&db)?;
+    assert_eq!(result.len(), 1);
+    Entity2::remove(&String::from("ab"), &db)?;
+    assert_eq!(ChildEntity1::get_count(&db)?, 2);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_key_decoding() -> Result<()> {
+    let key = ((String::from("a\0b"), -3i32), 7u64, true, 'é', [1u8; 16], vec![0u8, 1, 255]);
+    assert_eq!(
+        <((String, i32), u64, bool, char, [u8; 16], Vec<u8>)>::from_bytes(&key.as_bytes())?,
+        key
+    );
+    assert_eq!(i128::from_bytes(&(-5i128).as_bytes())?, -5);
+    assert_eq!(u16::from_bytes(&300u16.as_bytes())?, 300);
+    assert!(u32::from_bytes(&[0, 1]).is_err());
+    assert!(String::from_bytes(b"ab").is_err());
+    assert!(u8::from_bytes(&[1, 2]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_typed_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    assert_eq!(
+        Entity2::get_keys(&db)?,
+        vec![
+            String::from("id1"),
+            String::from("id2"),
+            String::from("id3")
+        ]
+    );
+    assert_eq!(
+        Entity1::get_keys(&db)?,
+        Entity1::get_all(&db)?
+            .iter()
+            .map(|e| e.id)
+            .collect::<Vec<u32>>()
+    );
+    let children = ChildEntity1::get_keys_with_prefix(&String::from("id3"), &db)?;
+    assert_eq!(
+        children,
+        vec![
+            (String::from("id3"), 0),
+            (String::from("id3"), 1),
+            (String::from("id3"), 2)
+        ]
+    );
+    assert_eq!(
+        QueryBuilder::new()
+            .with_parent(&String::from("id3"))
+            .get_keys::<ChildEntity1>(&db)?,
+        children
+    );
+    let child = ChildEntity1::get(&(String::from("id3"), 2), &db)?.unwrap();
+    assert_eq!(
+        child.get_related_keys::<ChildEntity2>(&db)?,
+        vec![(2, 1)]
+    );
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e3 = Entity3::get(&1, &db)?.unwrap();
+    e2.create_relation(
+        &e3,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        Some("rel1"),
+        &db,
+    )?;
+    assert_eq!(e2.get_related_keys::<Entity3>(&db)?, vec![1]);
+    assert_eq!(e2.get_related_keys_with_name::<Entity3>("rel1", &db)?, vec![1]);
+    assert!(e2
+        .get_related_keys_with_name::<Entity3>("rel2", &db)?
+        .is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_raw_keys() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let id3 = AsBytes::as_bytes(&String::from("id3"));
+    assert_eq!(id3.as_bytes(), id3);
+    assert_eq!(id3.as_slice().as_bytes(), id3);
+    assert_eq!(Vec::<u8>::from_bytes(&id3)?, id3);
+    let result = QueryBuilder::new()
+        .with_parent(&id3)
+        .with_relation_to::<ChildEntity2>(&(2u32, 1u32).as_bytes())
+        .get::<ChildEntity1>(&db)?;
+    assert_eq!(result.len(), 1);
+    assert_eq!(
+        QueryBuilder::new().with_id(&id3.as_slice()).get_keys::<Entity2>(&db)?,
+        vec![String::from("id3")]
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_iterators() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let ids = Entity2::iter(&db)?
+        .map(|e| e.map(|e| e.id))
+        .collect::<Result<Vec<String>>>()?;
+    assert_eq!(ids, vec!["id1", "id2", "id3"]);
+    let ids = Entity2::iter_rev(&db)?
+        .map(|e| e.map(|e| e.id))
+        .collect::<Result<Vec<String>>>()?;
+    assert_eq!(ids, vec!["id3", "id2", "id1"]);
+    let mut children = ChildEntity1::iter_prefix(&String::from("id3"), &db)?;
+    assert_eq!(children.next().unwrap()?.id, (String::from("id3"), 0));
+    assert_eq!(children.next_back().unwrap()?.id, (String::from("id3"), 2));
+    assert_eq!(children.next().unwrap()?.id, (String::from("id3"), 1));
+    assert!(children.next().is_none());
+    assert_eq!(Entity1::iter_range(1u32, 3u32, &db)?.count(), 2);
+    let result = QueryBuilder::new()
+        .with_parent(&String::from("id3"))
+        .iter::<ChildEntity1>(&db)?
+        .rev()
+        .map(|e| e.map(|e| e.id.1))
+        .collect::<Result<Vec<u32>>>()?;
+    assert_eq!(result, vec![2, 1, 0]);
+    Entity2::get_tree(&db)?.insert(AsBytes::as_bytes(&String::from("id25")), vec![0xFF])?;
+    let entities = Entity2::iter(&db)?.collect::<Vec<Result<Entity2>>>();
+    assert_eq!(entities.len(), 4);
+    assert!(entities[0].is_ok());
+    assert!(entities[1].is_ok());
+    assert!(entities[2].is_err());
+    assert!(entities[3].is_ok());
+    assert!(Entity2::get_all(&db).is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_read_errors() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // Entities that cannot be read are reported instead of being left out
+    let key = (String::from("id3"), 2u32);
+    db.open_tree("child_entity_1")?.insert(key.as_bytes(), vec![0xFF])?;
+    assert!(ChildEntity1::get_with_filter(|_| true, &db).is_err());
+    assert!(ChildEntity1::get_each(std::slice::from_ref(&key), &db).is_err());
+    assert_eq!(ChildEntity1::get_each(&[(String::from("id4"), 0)], &db)?.len(), 0);
+    let child_entity_2 = ChildEntity2::get(&(2, 1), &db)?.unwrap();
+    assert!(child_entity_2.get_related::<ChildEntity1>(&db).is_err());
+    assert!(Traversal::from(&child_entity_2)
+        .out::<ChildEntity1>(None)
+        .collect::<ChildEntity1>(&db)
+        .is_err());
+    let mut query = QueryBuilder::new();
+    query.with_parent(&String::from("id3"));
+    assert!(query.get::<ChildEntity1>(&db).is_err());
+    assert!(query.get_with_filter(|_: &ChildEntity1| true, &db).is_err());
+    let children = db.store::<ChildEntity1>()?;
+    assert!(children.get_each(&[key]).is_err());
+    assert!(children.get_with_filter(|_| true).is_err());
+
+    IndexedEntity {
+        id: 0,
+        email: String::from("ada@example.com"),
+        city: String::from("London"),
+    }
+    .save(&db)?;
+    IndexedEntity::get_tree(&db)?.insert(0u32.as_bytes(), vec![0xFF])?;
+    assert!(IndexedEntity::find_by_index("city", &String::from("London"), &db).is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_filter_remove() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // Entities 0, 1 and 2 cannot be removed while their Entity1 sibling exists
+    Entity3 { id: 3, some_bool: false }.save(&db)?;
+    Entity3 { id: 4, some_bool: false }.save(&db)?;
+    let removed = Entity3::filter_remove(|_| true, &db)?;
+    assert_eq!(removed.iter().map(|e| e.id).collect::<Vec<u32>>(), vec![3, 4]);
+    assert_eq!(Entity3::get_keys(&db)?, vec![0, 1, 2]);
+
+    // A child that cannot be removed prevents the removal of the whole prefix
+    let child_entity_1 = ChildEntity1::get(&(String::from("id3"), 0), &db)?.unwrap();
+    let child_entity_2 = ChildEntity2::get(&(2, 0), &db)?.unwrap();
+    child_entity_2.create_relation(
+        &child_entity_1,
+        DeletionBehaviour::Error,
+        DeletionBehaviour::BreakLink,
+        None,
+        &db,
+    )?;
+    assert!(matches!(
+        ChildEntity2::remove_prefixed(2u32, &db).map_err(|e| e.kind()),
+        Err(ErrorKind::IntegrityError)
+    ));
+    assert_eq!(ChildEntity2::get_keys_with_prefix(&2u32, &db)?.len(), 3);
+    child_entity_2.remove_relation(&child_entity_1, &db)?;
+    ChildEntity2::remove_prefixed(2u32, &db)?;
+    assert_eq!(ChildEntity2::get_keys_with_prefix(&2u32, &db)?.len(), 0);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_pagination() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    for id in 10..20 {
+        Entity1 {
+            id,
+            prop1: String::new(),
+        }
+        .save(&db)?;
+    }
+    let ids = |page: &Page<Entity1>| page.items.iter().map(|e| e.id).collect::<Vec<u32>>();
+    let first = Entity1::page_after(None, 4, None::<u32>, &db)?;
+    assert_eq!(ids(&first), vec![0, 1, 2, 10]);
+    assert!(first.previous.is_none());
+    let cursor: Cursor = first.next.as_ref().unwrap().to_string().parse()?;
+    Entity1 {
+        id: 5,
+        prop1: String::new(),
+    }
+    .save(&db)?;
+    let second = Entity1::page_after(Some(&cursor), 4, None::<u32>, &db)?;
+    assert_eq!(ids(&second), vec![11, 12, 13, 14]);
+    let last = Entity1::page_after(second.next.as_ref(), 10, None::<u32>, &db)?;
+    assert_eq!(ids(&last), vec![15, 16, 17, 18, 19]);
+    assert!(last.next.is_none());
+    let back = Entity1::page_before(second.previous.as_ref(), 3, None::<u32>, &db)?;
+    assert_eq!(ids(&back), vec![2, 5, 10]);
+    assert!(back.next.is_some());
+    let start = Entity1::page_before(back.previous.as_ref(), 3, None::<u32>, &db)?;
+    assert_eq!(ids(&start), vec![0, 1]);
+    assert!(start.previous.is_none());
+    let end = Entity1::page_before(None, 2, None::<u32>, &db)?;
+    assert_eq!(ids(&end), vec![18, 19]);
+    assert!(end.next.is_none());
+
+    // An empty page leads back to the entity at its cursor
+    let beyond = Entity1::page_after(Some(&Cursor::from_key(&19u32)), 2, None::<u32>, &db)?;
+    assert!(beyond.items.is_empty() && beyond.next.is_none());
+    let back = Entity1::page_before(beyond.previous.as_ref(), 2, None::<u32>, &db)?;
+    assert_eq!(ids(&back), vec![18, 19]);
+    let before = Entity1::page_before(Some(&Cursor::from_key(&0u32)), 2, None::<u32>, &db)?;
+    assert!(before.items.is_empty() && before.previous.is_none());
+    let forth = Entity1::page_after(before.next.as_ref(), 2, None::<u32>, &db)?;
+    assert_eq!(ids(&forth), vec![0, 1]);
+
+    let children = ChildEntity1::page_after(None, 2, Some(String::from("id3")), &db)?;
+    assert_eq!(children.items.len(), 2);
+    let children = ChildEntity1::page_after(
+        children.next.as_ref(),
+        2,
+        Some(String::from("id3")),
+        &db,
+    )?;
+    assert_eq!(children.items[0].id, (String::from("id3"), 2));
+    assert!(children.next.is_none());
+    assert!(children.previous.is_some());
+    let children = ChildEntity1::page_before(None, 5, Some(String::from("id2")), &db)?;
+    assert_eq!(children.items.len(), 1);
+
+    let query = QueryBuilder::new().with_parent(&String::from("id3")).page_before::<ChildEntity1>(
+        Some(&Cursor::from_key(&(String::from("id3"), 2u32))),
+        1,
+        &db,
+    )?;
+    assert_eq!(query.items[0].id, (String::from("id3"), 1));
+    assert!(query.previous.is_some());
+    assert!(query.next.is_some());
+    let last = Cursor::from_key(&(String::from("id3"), 2u32));
+    let query = QueryBuilder::new().with_parent(&String::from("id3")).page_after::<ChildEntity1>(
+        Some(&last),
+        1,
+        &db,
+    )?;
+    assert!(query.items.is_empty());
+    let query = QueryBuilder::new().with_parent(&String::from("id3")).page_before::<ChildEntity1>(
+        query.previous.as_ref(),
+        1,
+        &db,
+    )?;
+    assert_eq!(query.items[0].id, (String::from("id3"), 2));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_subscriptions() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let timeout = Duration::from_secs(5);
+    let mut all = ChildEntity1::subscribe(&db)?;
+    let mut children = ChildEntity1::subscribe_prefix(&String::from("id2"), &db)?;
+    let id3 = Entity2::get(&String::from("id3"), &db)?.unwrap();
+    let id2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    let mut child = ChildEntity1::get(&(String::from("id3"), 1), &db)?.unwrap();
+    id2.adopt_child(&mut child, &db)?;
+    let (mut inserted, mut removed) = (Vec::new(), Vec::new());
+    for _ in 0..2 {
+        match all.next_timeout(timeout).unwrap()? {
+            Event::Inserted(e) => inserted.push(e.id),
+            Event::Removed(key) => removed.push(key),
+        }
+    }
+    assert_eq!(inserted, vec![(String::from("id2"), 1)]);
+    assert_eq!(removed, vec![(String::from("id3"), 1)]);
+    match children.next_timeout(timeout).unwrap()? {
+        Event::Inserted(e) => assert_eq!(e.id, (String::from("id2"), 1)),
+        Event::Removed(_) => panic!("Expected the adopted child to be inserted"),
+    }
+    Entity2::remove(id3.get_key(), &db)?;
+    let mut removed = Vec::new();
+    while let Some(event) = all.next_timeout(Duration::from_millis(200)) {
+        match event? {
+            Event::Removed(key) => removed.push(key),
+            Event::Inserted(_) => panic!("Expected removals only"),
+        }
+    }
+    removed.sort();
+    assert_eq!(
+        removed,
+        vec![(String::from("id3"), 0), (String::from("id3"), 2)]
+    );
+    assert!(children
+        .next_timeout(Duration::from_millis(200))
+        .is_none());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn test_async() -> Result<()> {
+    use crate::AsyncEntity;
+
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+    runtime.block_on(async {
+        let mut subscription = Entity2::subscribe(&db)?;
+        let e2 = Entity2 {
+            id: String::from("id4"),
+            prop2: 4,
+        };
+        e2.save_async(&db).await?;
+        match (&mut subscription).await.unwrap()? {
+            Event::Inserted(e) => assert_eq!(e.id, "id4"),
+            Event::Removed(_) => panic!("Expected an insertion"),
+        }
+        Entity2::update_async(&String::from("id4"), |e| e.prop2 = 5, &db).await?;
+        assert_eq!(
+            Entity2::get_async(&String::from("id4"), &db)
+                .await?
+                .unwrap()
+                .prop2,
+            5
+        );
+        assert_eq!(Entity2::get_all_async(&db).await?.len(), 4);
+        let children = QueryBuilder::new()
+            .with_parent(&String::from("id3"))
+            .get_async::<ChildEntity1>(&db)
+            .await?;
+        assert_eq!(children.len(), 3);
+        Entity2::remove_async(&String::from("id3"), &db).await?;
+        assert!(!Entity2::exists_async(&String::from("id3"), &db).await?);
+        assert_eq!(
+            crate::run_blocking(&db, ChildEntity1::get_count).await?,
+            1
+        );
+        crate::flush_async(&db).await?;
+        Ok::<(), crate::Error>(())
+    })?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_backup() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    IndexedEntity {
+        id: 1,
+        email: String::from("ada@example.com"),
+        city: String::from("London"),
+    }
+    .save(&db)?;
+    let mut archive = Vec::new();
+    let manifest = crate::backup::export_all(&db, &mut archive)?;
+    let store = manifest
+        .stores
+        .iter()
+        .find(|s| s.name == "child_entity_1")
+        .unwrap();
+    assert_eq!(store.entities, 4);
+    assert_eq!(store.version, Some(1));
+
+    Entity2::remove(&String::from("id3"), &db)?;
+    IndexedEntity::remove(&1, &db)?;
+    Entity2 {
+        id: String::from("id9"),
+        prop2: 9,
+    }
+    .save(&db)?;
+    SignedEntity::register(&db)?;
+    assert_eq!(crate::backup::import_all(&db, archive.as_slice())?, manifest);
+    assert!(!Entity2::exists(&String::from("id9"), &db)?);
+    let e2 = Entity2::get(&String::from("id3"), &db)?.unwrap();
+    assert_eq!(e2.get_children::<ChildEntity1>(&db)?.len(), 3);
+    let child = ChildEntity1::get(&(String::from("id3"), 2), &db)?.unwrap();
+    assert_eq!(child.get_related::<ChildEntity2>(&db)?.len(), 1);
+    assert!(IndexedEntity::get_by_index("email", &String::from("ada@example.com"), &db)?.is_some());
+    assert!(FamilyDescriptor::exists(&String::from("signed_entity"), &db)?);
+    assert!(crate::backup::import_all(&db, &b"{\"manifest\":"[..]).is_err());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_backup_staging() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut archive = Vec::new();
+    crate::backup::export_all(&db, &mut archive)?;
+    Entity2::remove(&String::from("id3"), &db)?;
+    let staging_trees = |db: &sled::Db| {
+        db.tree_names()
+            .iter()
+            .filter(|name| name.starts_with(b"__$import/"))
+            .count()
+    };
+
+    // A truncated archive is read into the staging trees only
+    let truncated = &archive[..archive.len() - 10];
+    assert!(matches!(
+        crate::backup::import_all(&db, truncated).map_err(|e| e.kind()),
+        Err(ErrorKind::SerializationError)
+    ));
+    assert!(!Entity2::exists(&String::from("id3"), &db)?);
+    assert_eq!(staging_trees(&db), 0);
+
+    // So is an archive with an invalid entry
+    let text = String::from_utf8(archive.clone()).unwrap();
+    let invalid = text.replacen("\"entity_2\":[[\"", "\"entity_2\":[[\"zz", 1);
+    assert_ne!(invalid, text);
+    assert!(crate::backup::import_all(&db, invalid.as_bytes()).is_err());
+    assert!(!Entity2::exists(&String::from("id3"), &db)?);
+    assert_eq!(staging_trees(&db), 0);
+
+    // Newer formats are refused before anything is read
+    let newer = text.replacen("\"format_version\":2", "\"format_version\":99", 1);
+    assert!(crate::backup::import_all(&db, newer.as_bytes()).is_err());
+    assert_eq!(staging_trees(&db), 0);
+
+    crate::backup::import_all(&db, archive.as_slice())?;
+    assert!(Entity2::exists(&String::from("id3"), &db)?);
+    assert_eq!(staging_trees(&db), 0);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_codecs() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let entity = JsonEntity {
+        id: 1,
+        nickname: None,
+    };
+    entity.save(&db)?;
+    let raw = db.open_tree("json_entity")?.get(1u32.as_bytes())?.unwrap();
+    assert_eq!(raw[0], Codec::Json.tag());
+    assert_eq!(&raw[1..], br#"{"id":1}"#);
+    assert_eq!(JsonEntity::get(&1, &db)?.unwrap(), entity);
+
+    // Values saved with another codec are still readable
+    db.open_tree("json_entity")?.insert(
+        2u32.as_bytes(),
+        Codec::Bincode.encode(&(2u32, Some(String::from("two"))))?,
+    )?;
+    assert_eq!(JsonEntity::get(&2, &db)?.unwrap().nickname.as_deref(), Some("two"));
+
+    let codecs = [
+        Codec::Bincode,
+        Codec::Json,
+        #[cfg(feature = "postcard")]
+        Codec::Postcard,
+        #[cfg(feature = "cbor")]
+        Codec::Cbor,
+        #[cfg(feature = "messagepack")]
+        Codec::MessagePack,
+    ];
+    for codec in codecs {
+        assert_eq!(Codec::from_tag(codec.tag()), Some(codec));
+        let value = (3u32, Some(String::from("three")));
+        assert_eq!(Codec::decode::<(u32, Option<String>)>(&codec.encode(&value)?)?, value);
+    }
+
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_save_before_register() -> Result<()> {
+    let name = get_random_name();
+    let mut dir = std::env::temp_dir();
+    dir.push(&name);
+    // Without a flusher thread, the database is closed as soon as it is dropped and its last write is done
+    let config = sled::Config::new().path(&dir).flush_every_ms(None);
+    let db = open_unlocked(&config)?;
+    SignedEntity { id: -1 }.save(&db)?;
+    JsonEntity {
+        id: 1,
+        nickname: Some(String::from("one")),
+    }
+    .save(&db)?;
+    drop(db);
+
+    // Stores written by this version before their entity is registered are not taken for legacy ones
+    let db = open_unlocked(&config)?;
+    SignedEntity::register(&db)?;
+    JsonEntity::register(&db)?;
+    assert_eq!(SignedEntity::get(&-1, &db)?.unwrap().id, -1);
+    assert_eq!(JsonEntity::get(&1, &db)?.unwrap().nickname.as_deref(), Some("one"));
+    SignedEntity::register(&db)?;
+    assert_eq!(SignedEntity::get_all(&db)?.len(), 1);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn test_compression() -> Result<()> {
+    use crate::Compression;
+    use test_entities::CompressedEntity;
+
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    CompressedEntity::register(&db)?;
+    let tree = db.open_tree("compressed_entity")?;
+    let small = CompressedEntity {
+        id: 1,
+        body: String::from("small"),
+    };
+    let large = CompressedEntity {
+        id: 2,
+        body: "<p>reindeer</p>".repeat(100),
+    };
+    small.save(&db)?;
+    large.save(&db)?;
+    assert_eq!(tree.get(1u32.as_bytes())?.unwrap()[0], Codec::Bincode.tag());
+    let raw = tree.get(2u32.as_bytes())?.unwrap();
+    assert_eq!(raw[0], Compression::Zstd.tag());
+    assert!(raw.len() < large.body.len());
+    assert_eq!(CompressedEntity::get(&1, &db)?.unwrap(), small);
+    assert_eq!(CompressedEntity::get(&2, &db)?.unwrap(), large);
+
+    // Uncompressed values are compressed by compact_store
+    tree.insert(3u32.as_bytes(), Codec::Bincode.encode(&(3u32, large.body.clone()))?)?;
+    assert_eq!(CompressedEntity::compact_store(&db)?, 1);
+    assert_eq!(tree.get(3u32.as_bytes())?.unwrap()[0], Compression::Zstd.tag());
+    assert_eq!(CompressedEntity::get(&3, &db)?.unwrap().body, large.body);
+    assert_eq!(CompressedEntity::compact_store(&db)?, 0);
+
+    #[cfg(feature = "lz4")]
+    {
+        let value = Compression::Lz4.compress(Codec::Json.encode(&large.body)?, 64)?;
+        assert_eq!(value[0], Compression::Lz4.tag());
+        assert_eq!(Codec::decode::<String>(&value)?, large.body);
+    }
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_encryption() -> Result<()> {
+    use crate::encryption::{forget_key, rotate_key, set_key, EncryptionKey};
+
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    SignedEntity { id: -1 }.save(&db)?;
+    let key = EncryptionKey::generate();
+    set_key(&db, &key)?;
+    // Stores that have not been registered yet are encrypted as well
+    for tree in ["entity_1", "signed_entity", "__$family_rel", "__$edges_child_entity_1"] {
+        let tree = db.open_tree(tree)?;
+        assert!(!tree.is_empty());
+        for elem in tree.iter() {
+            assert_eq!(elem?.1[0], crate::cipher::ENCRYPTED_TAG);
+        }
+    }
+    assert_eq!(Entity1::get(&0, &db)?.unwrap().prop1, "Hello, World!");
+    SignedEntity::register(&db)?;
+    assert_eq!(SignedEntity::get(&-1, &db)?.unwrap().id, -1);
+    let e4 = ChildEntity1::get(&(String::from("id3"), 2), &db)?.unwrap();
+    assert_eq!(e4.get_related::<ChildEntity2>(&db)?.len(), 1);
+    Entity1 {
+        id: 10,
+        prop1: String::from("Secret"),
+    }
+    .save(&db)?;
+    let raw = db.open_tree("entity_1")?.get(10u32.as_bytes())?.unwrap();
+    assert!(!raw.windows(6).any(|w| w == b"Secret"));
+    // Values are bound to their key
+    db.open_tree("entity_1")?.insert(11u32.as_bytes(), raw)?;
+    assert!(matches!(
+        Entity1::get(&11, &db).map_err(|e| e.kind()),
+        Err(ErrorKind::AuthenticationError)
+    ));
+    db.open_tree("entity_1")?.remove(11u32.as_bytes())?;
+
+    forget_key(&db);
+    assert!(matches!(
+        Entity1::get(&10, &db).map_err(|e| e.kind()),
+        Err(ErrorKind::AuthenticationError)
+    ));
+    assert!(matches!(
+        set_key(&db, &EncryptionKey::generate()).map_err(|e| e.kind()),
+        Err(ErrorKind::AuthenticationError)
+    ));
+    set_key(&db, &key)?;
+
+    let new_key = EncryptionKey::generate();
+    rotate_key(&db, &new_key)?;
+    assert_eq!(Entity1::get(&10, &db)?.unwrap().prop1, "Secret");
+    assert_eq!(Entity1::get_all(&db)?.len(), 4);
+    forget_key(&db);
+    assert!(set_key(&db, &key).is_err());
+    set_key(&db, &new_key)?;
+
+    let mut tampered = db.open_tree("entity_1")?.get(10u32.as_bytes())?.unwrap().to_vec();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    db.open_tree("entity_1")?.insert(10u32.as_bytes(), tampered.clone())?;
+    assert!(matches!(
+        Entity1::get(&10, &db).map_err(|e| e.kind()),
+        Err(ErrorKind::AuthenticationError)
+    ));
+    // A value that cannot be authenticated is left untouched by updates
+    assert!(matches!(
+        Entity1::update(&10, |e| e.prop1.clear(), &db).map_err(|e| e.kind()),
+        Err(ErrorKind::AuthenticationError)
+    ));
+    assert!(db.store::<Entity1>()?.update(&10, |e| e.prop1.clear()).is_err());
+    assert_eq!(db.open_tree("entity_1")?.get(10u32.as_bytes())?.unwrap(), tampered);
+
+    // A child is adopted with its grand children and relations at once, or not at all
+    let grand_children = db.open_tree("grand_child_entity")?;
+    let last_key = ((String::from("id3"), 2u32), 2u32).as_bytes();
+    let mut tampered = grand_children.get(&last_key)?.unwrap().to_vec();
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    grand_children.insert(&last_key, tampered)?;
+    let id1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let mut e4 = ChildEntity1::get(&(String::from("id3"), 2), &db)?.unwrap();
+    assert!(matches!(
+        id1.adopt_as_next_child(&mut e4, &db).map_err(|e| e.kind()),
+        Err(ErrorKind::AuthenticationError)
+    ));
+    assert_eq!(e4.id, (String::from("id3"), 2));
+    assert!(id1.get_children::<ChildEntity1>(&db)?.is_empty());
+    assert_eq!(e4.get_related::<ChildEntity2>(&db)?.len(), 1);
+    assert_eq!(
+        grand_children.scan_prefix((String::from("id3"), 2u32).as_bytes()).count(),
+        3
+    );
+    forget_key(&db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn test_key_rotation() -> Result<()> {
+    use crate::encryption::{rotate_key, set_key, EncryptionKey};
+
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    set_key(&db, &EncryptionKey::generate())?;
+    // A transaction sealing values with a key rotated in the meantime is run again with the new key
+    let attempts = std::cell::Cell::new(0);
+    crate::transaction(&db, |tx| {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() == 1 {
+            rotate_key(&db, &EncryptionKey::generate())?;
+        }
+        tx.save(&Entity1 {
+            id: 10,
+            prop1: String::from("Secret"),
+        })
+    })?;
+    assert_eq!(attempts.get(), 2);
+    assert_eq!(Entity1::get(&10, &db)?.unwrap().prop1, "Secret");
+    assert_eq!(Entity1::get_all(&db)?.len(), 4);
+
+    // The key is dropped along with the database
+    drop(db);
+    let mut dir = std::env::temp_dir();
+    dir.push(&name);
+    let db = open_unlocked(&sled::Config::new().path(&dir))?;
+    assert!(matches!(
+        Entity1::get(&10, &db).map_err(|e| e.kind()),
+        Err(ErrorKind::AuthenticationError)
+    ));
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_fsck() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    assert!(crate::fsck(&db)?.is_clean());
+
+    let e1_1 = Entity1::get(&1, &db)?.unwrap();
+    let e1_2 = Entity1::get(&2, &db)?.unwrap();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    for (e1, e2) in [(&e1_1, &e2_1), (&e1_2, &e2_2)] {
+        e1.create_relation(
+            e2,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+    }
+    let id1 = AsBytes::as_bytes(&String::from("id1"));
+    let id2 = AsBytes::as_bytes(&String::from("id2"));
+    // Corrupt the database by writing to the trees directly
+    let edges = db.open_tree("__$edges_entity_2")?;
+    for edge in edges.scan_prefix(crate::key::escape(&id1)).keys() {
+        edges.remove(edge?)?;
+    }
+    db.open_tree("entity_2")?.remove(&id2)?;
+    db.open_tree("entity_1")?.remove(0u32.as_bytes())?;
+
+    let report = crate::fsck(&db)?;
+    let expected = [
+        FsckIssue::AsymmetricLink {
+            store: String::from("entity_1"),
+            key: 1u32.as_bytes(),
+            related_store: String::from("entity_2"),
+            related_key: id1.clone(),
+        },
+        FsckIssue::DanglingLink {
+            store: String::from("entity_1"),
+            key: 2u32.as_bytes(),
+            related_store: String::from("entity_2"),
+            related_key: id2.clone(),
+        },
+        FsckIssue::OrphanedDescriptor {
+            store: String::from("entity_2"),
+            key: id2.clone(),
+        },
+        FsckIssue::OrphanedChild {
+            store: String::from("child_entity_1"),
+            key: (String::from("id2"), 0u32).as_bytes(),
+            parent_store: String::from("entity_2"),
+        },
+    ];
+    assert_eq!(report.issues.len(), expected.len());
+    for issue in &expected {
+        assert!(report.issues.contains(issue), "{} not found", issue);
+    }
+
+    report.repair_with(&RepairOptions { remove_orphans: true }, &db)?;
+    assert!(crate::fsck(&db)?.is_clean());
+    assert!(e1_1.get_related::<Entity2>(&db)?.is_empty());
+    assert!(!ChildEntity1::exists(&(String::from("id2"), 0), &db)?);
+    // A sibling of a removed entity_1 is removed with it, but may also be saved on its own
+    assert!(Entity3::exists(&0, &db)?);
+    assert!(Entity3::exists(&1, &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_fsck_orphans() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    db.open_tree("entity_2")?.remove(AsBytes::as_bytes(&String::from("id2")))?;
+    // entity_3 cannot be removed while its entity_1 sibling exists
+    db.open_tree("entity_3")?.remove(1u32.as_bytes())?;
+
+    let report = crate::fsck(&db)?;
+    assert!(report.issues.contains(&FsckIssue::OrphanedSibling {
+        store: String::from("entity_1"),
+        key: 1u32.as_bytes(),
+        sibling_store: String::from("entity_3"),
+        behaviour: DeletionBehaviour::Error,
+    }));
+    assert!(report
+        .issues
+        .iter()
+        .any(|issue| matches!(issue, FsckIssue::OrphanedChild { store, .. } if store == "child_entity_1")));
+
+    // Orphans are only reported, unless their removal is asked for
+    report.repair(&db)?;
+    assert!(Entity1::exists(&1, &db)?);
+    assert!(ChildEntity1::exists(&(String::from("id2"), 0), &db)?);
+    let report = crate::fsck(&db)?;
+    assert!(report.issues.iter().all(|issue| matches!(
+        issue,
+        FsckIssue::OrphanedChild { .. } | FsckIssue::OrphanedSibling { .. }
+    )));
+
+    report.repair_with(&RepairOptions { remove_orphans: true }, &db)?;
+    assert!(!Entity1::exists(&1, &db)?);
+    assert!(!ChildEntity1::exists(&(String::from("id2"), 0), &db)?);
+    assert!(crate::fsck(&db)?.is_clean());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_relation_keys_case() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let upper = Entity2 {
+        id: String::from("ABC"),
+        prop2: 0,
+    };
+    let lower = Entity2 {
+        id: String::from("abc"),
+        prop2: 0,
+    };
+    upper.save(&db)?;
+    lower.save(&db)?;
+    let e1_1 = Entity1::get(&1, &db)?.unwrap();
+    let e1_2 = Entity1::get(&2, &db)?.unwrap();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    for (e2, relation_name) in [(&upper, "n"), (&lower, "n"), (&e2_1, "other")] {
+        e1_1.create_relation(
+            e2,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            Some(relation_name),
+            &db,
+        )?;
+    }
+    e1_1.remove_relation_with_name(&upper, "n", &db)?;
+    assert!(!e1_1.is_related_to(&upper, &db)?);
+    assert!(e1_1.is_related_to_with_name(&lower, "n", &db)?);
+    assert!(e1_1.is_related_to_with_name(&e2_1, "other", &db)?);
+    e1_1.remove_relation(&lower, &db)?;
+    assert!(!e1_1.is_related_to(&lower, &db)?);
+    assert!(e1_1.is_related_to(&e2_1, &db)?);
+
+    // Reproduce the descriptors left by the former case-insensitive removal
+    for e2 in [&upper, &lower] {
+        e1_2.create_relation(
+            e2,
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            None,
+            &db,
+        )?;
+    }
+    let e1_2_key = 2u32.as_bytes();
+    let upper_key = AsBytes::as_bytes(&upper.id);
+    let lower_key = AsBytes::as_bytes(&lower.id);
+    let mut descriptor = Relation::get_descriptor_with_key_and_tree_name("entity_1", &e1_2_key, &db)?;
+    descriptor
+        .related_entities
+        .get_mut("entity_2")
+        .unwrap()
+        .retain(|rd| rd.key != lower_key);
+    Relation::save_descriptor_with_key_and_tree_name("entity_1", &e1_2_key, &descriptor, &db)?;
+    // Links lost by keys without ASCII letters cannot come from case folding
+    let mut descriptor = Relation::get_descriptor_with_key_and_tree_name("entity_2", &upper_key, &db)?;
+    descriptor.related_entities.get_mut("entity_1").unwrap().clear();
+    Relation::save_descriptor_with_key_and_tree_name("entity_2", &upper_key, &descriptor, &db)?;
+
+    let report = crate::check_case_folded_links(&db)?.unwrap();
+    assert_eq!(
+        report.issues,
+        vec![FsckIssue::AsymmetricLink {
+            store: String::from("entity_2"),
+            key: lower_key,
+            related_store: String::from("entity_1"),
+            related_key: e1_2_key,
+        }]
+    );
+    assert!(crate::check_case_folded_links(&db)?.is_none());
+    assert_eq!(crate::fsck(&db)?.issues.len(), 2);
+    tear_down(&name)?;
+    Ok(())
+}
+
+/// Opens a database written by a previous version : keys are not encoded in order, values have no codec tag,
+/// and every link of an entity is stored in a single descriptor.
+fn set_up_legacy(name: &str) -> Result<sled::Db> {
+    let mut dir = std::env::temp_dir();
+    dir.push(name);
+    let db = crate::open(dir.to_str().unwrap())?;
+    let families = db.open_tree("__$family_rel")?;
+    for family in [
+        FamilyDescriptor::of::<Author>(),
+        FamilyDescriptor::of::<Book>(),
+        FamilyDescriptor::of::<SignedEntity>(),
+    ] {
+        families.insert(family.tree_name.as_bytes(), bincode::serialize(&family)?)?;
+    }
+    let authors = db.open_tree("author")?;
+    for id in ["b", "a", "ab"] {
+        authors.insert(id, bincode::serialize(&Author { id: String::from(id) })?)?;
+    }
+    let books = db.open_tree("book")?;
+    for (author, number) in [("a", 1u32), ("a", 2), ("ab", 1)] {
+        let book = Book {
+            id: (String::from(author), number),
+        };
+        books.insert([author.as_bytes(), &number.to_be_bytes()].concat(), bincode::serialize(&book)?)?;
+    }
+    let signed = db.open_tree("signed_entity")?;
+    for id in [1i64, -1, -5] {
+        signed.insert(id.to_be_bytes(), bincode::serialize(&SignedEntity { id })?)?;
+    }
+    let legacy = |store: &str, key: &[u8], behaviour| {
+        let link = (key.to_vec(), behaviour, Some(String::from("legacy")));
+        bincode::serialize(&HashMap::from([(String::from(store), vec![link])]))
+    };
+    db.open_tree("__$rel_author")?.insert(
+        "a",
+        legacy("signed_entity", &(-1i64).to_be_bytes(), DeletionBehaviour::Cascade)?,
+    )?;
+    db.open_tree("__$rel_signed_entity")?.insert(
+        (-1i64).to_be_bytes(),
+        legacy("author", b"a", DeletionBehaviour::BreakLink)?,
+    )?;
+    Ok(db)
+}
+
+#[test]
+fn test_legacy_layout() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up_legacy(&name)?;
+    assert!(matches!(
+        Author::get(&String::from("a"), &db).map_err(|e| e.kind()),
+        Err(ErrorKind::MigrationError)
+    ));
+    Author::register(&db)?;
+    Book::register(&db)?;
+    SignedEntity::register(&db)?;
+
+    let ids = Author::get_all(&db)?.into_iter().map(|author| author.id).collect::<Vec<String>>();
+    assert_eq!(ids, ["a", "ab", "b"]);
+    let author = Author::get(&String::from("a"), &db)?.unwrap();
+    assert_eq!(author.get_children::<Book>(&db)?.len(), 2);
+    assert_eq!(Book::get(&(String::from("ab"), 1), &db)?.unwrap().id.1, 1);
+    let ids = SignedEntity::get_all(&db)?.into_iter().map(|e| e.id).collect::<Vec<i64>>();
+    assert_eq!(ids, [-5, -1, 1]);
+    assert_eq!(crate::migration::get_store_version("author", &db)?, Some(0));
+
+    // Stores are only upgraded once
+    Author::register(&db)?;
+    assert_eq!(Author::get_all(&db)?.len(), 3);
+    assert!(crate::fsck(&db)?.is_clean());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_legacy_layout_foreign_trees() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up_legacy(&name)?;
+    let foreign = db.open_tree("foreign")?;
+    foreign.insert("key", "value")?;
+    assert!(crate::layout::is_legacy("author", &db)?);
+    assert!(!crate::layout::is_legacy("foreign", &db)?);
+    Author::register(&db)?;
+    assert!(!crate::layout::is_legacy("author", &db)?);
+    assert!(crate::layout::is_legacy("book", &db)?);
+    assert!(!db.open_tree(crate::layout::LAYOUT_TREE)?.contains_key("foreign")?);
+    assert_eq!(foreign.get("key")?.as_deref(), Some("value".as_bytes()));
+    drop(foreign);
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_legacy_relations() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up_legacy(&name)?;
+    Author::register(&db)?;
+    // Links to a store that has not been upgraded yet are converted once it is
+    assert!(db.tree_names().iter().any(|name| name.as_ref() == b"__$rel_author"));
+    SignedEntity::register(&db)?;
+    Book::register(&db)?;
+    assert!(!db.tree_names().iter().any(|name| name.starts_with(b"__$rel_")));
+
+    let author = Author::get(&String::from("a"), &db)?.unwrap();
+    let signed = SignedEntity::get(&-1, &db)?.unwrap();
+    assert!(author.is_related_to_with_name(&signed, "legacy", &db)?);
+    assert!(signed.is_related_to_with_name(&author, "legacy", &db)?);
+    assert_eq!(author.get_related::<SignedEntity>(&db)?[0].id, -1);
+    assert!(crate::fsck(&db)?.is_clean());
+    Author::remove(&String::from("a"), &db)?;
+    assert!(!SignedEntity::exists(&-1, &db)?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_legacy_case_folded_links() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up_legacy(&name)?;
+    // Author "ab" kept its link to -5, which lost the link back
+    let link = (
+        (-5i64).to_be_bytes().to_vec(),
+        DeletionBehaviour::BreakLink,
+        Option::<String>::None,
+    );
+    db.open_tree("__$rel_author")?.insert(
+        "ab",
+        bincode::serialize(&HashMap::from([(String::from("signed_entity"), vec![link])]))?,
+    )?;
+    Author::register(&db)?;
+    // Links to a store that has not been upgraded yet cannot be checked
+    assert!(matches!(
+        crate::check_case_folded_links(&db).map_err(|e| e.kind()),
+        Err(ErrorKind::MigrationError)
+    ));
+    SignedEntity::register(&db)?;
+    Book::register(&db)?;
+
+    // The check ran when the last descriptors were converted : links damaged since are not reported
+    let e1 = Entity1 {
+        id: 1,
+        prop1: String::from("e1"),
+    };
+    Entity1::register(&db)?;
+    Entity2::register(&db)?;
+    e1.save(&db)?;
+    let e2 = Entity2 {
+        id: String::from("xyz"),
+        prop2: 0,
+    };
+    e2.save(&db)?;
+    e1.create_relation(&e2, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &db)?;
+    let mut descriptor = Relation::get_descriptor_with_key_and_tree_name("entity_1", &1u32.as_bytes(), &db)?;
+    descriptor.related_entities.clear();
+    Relation::save_descriptor_with_key_and_tree_name("entity_1", &1u32.as_bytes(), &descriptor, &db)?;
+
+    let report = crate::check_case_folded_links(&db)?.unwrap();
+    assert_eq!(
+        report.issues,
+        vec![FsckIssue::AsymmetricLink {
+            store: String::from("author"),
+            key: AsBytes::as_bytes(&String::from("ab")),
+            related_store: String::from("signed_entity"),
+            related_key: (-5i64).as_bytes(),
+        }]
+    );
+    assert!(crate::check_case_folded_links(&db)?.is_none());
+    assert!(!db.tree_names().iter().any(|name| name.as_ref() == b"__$case_folded_links_report"));
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_relation_data() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let e1 = Entity1::get(&1, &db)?.unwrap();
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e2_2 = Entity2::get(&String::from("id2"), &db)?.unwrap();
+    e1.create_relation_with_data(
+        &e2_1,
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        Some("enrolment"),
+        &(15u8, String::from("student")),
+        &db,
+    )?;
+    e1.create_relation(&e2_2, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &db)?;
+
+    let related = e1.get_related_with_data::<Entity2, (u8, String)>(&db)?;
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].0.id, "id1");
+    assert_eq!(related[0].1, (15, String::from("student")));
+    assert_eq!(
+        e2_1.get_related_with_data::<Entity1, (u8, String)>(&db)?[0].1,
+        (15, String::from("student"))
+    );
+
+    e1.update_relation_data(&e2_1, Some("enrolment"), &(18u8, String::from("tutor")), &db)?;
+    assert_eq!(
+        e2_1.get_related_with_data::<Entity1, (u8, String)>(&db)?[0].1,
+        (18, String::from("tutor"))
+    );
+    assert!(matches!(
+        e1.update_relation_data(&e2_1, None, &(0u8, String::new()), &db).map_err(|e| e.kind()),
+        Err(ErrorKind::NotFound)
+    ));
+
+    let path = std::env::temp_dir().join(format!("{}.json", name));
+    Entity1::export_json(std::fs::File::create(&path)?, false, &db)?;
+    e1.remove_relation(&e2_1, &db)?;
+    Entity1::import_json(std::fs::File::open(&path)?, &db)?;
+    std::fs::remove_file(&path)?;
+    assert_eq!(
+        e1.get_related_with_data::<Entity2, (u8, String)>(&db)?[0].1,
+        (18, String::from("tutor"))
+    );
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_traversal() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Entity1 {
+        id: 3,
+        prop1: String::from("Fourth"),
+    }
+    .save(&db)?;
+    let people = (0..4)
+        .map(|id| Entity1::get(&id, &db).map(Option::unwrap))
+        .collect::<Result<Vec<Entity1>>>()?;
+    // 0 - 1 - 2 - 3, with a cycle between 0, 1 and 2
+    for (a, b) in [(0, 1), (1, 2), (2, 0), (2, 3)] {
+        people[a].create_relation(
+            &people[b],
+            DeletionBehaviour::BreakLink,
+            DeletionBehaviour::BreakLink,
+            Some("friend"),
+            &db,
+        )?;
+    }
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    people[3].create_relation(&e2, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &db)?;
+
+    let mut friends_of_friends = Traversal::from(&people[1])
+        .out::<Entity1>("friend")
+        .out::<Entity1>("friend")
+        .keys::<Entity1>(&db)?;
+    friends_of_friends.sort();
+    assert_eq!(friends_of_friends, vec![0, 1, 2, 3]);
+    let mut reached = Traversal::from(&people[0]).bfs::<Entity1>("friend", 1).keys::<Entity1>(&db)?;
+    reached.sort();
+    assert_eq!(reached, vec![1, 2]);
+    assert_eq!(
+        Traversal::from(&people[0]).bfs::<Entity1>("friend", 10).keys::<Entity1>(&db)?.len(),
+        3
+    );
+    assert!(Traversal::from(&people[0]).bfs::<Entity1>("other", 10).keys::<Entity1>(&db)?.is_empty());
+    let related = Traversal::from(&people[0])
+        .bfs::<Entity1>("friend", 2)
+        .out::<Entity2>(None)
+        .collect::<Entity2>(&db)?;
+    assert_eq!(related.len(), 1);
+    assert_eq!(related[0].id, "id1");
+    assert!(matches!(
+        Traversal::from(&people[0]).out::<Entity1>(None).keys::<Entity2>(&db).map_err(|e| e.kind()),
+        Err(ErrorKind::IntegrityError)
+    ));
+
+    assert_eq!(
+        Traversal::shortest_path(&people[0], &people[3], Some("friend"), &db)?,
+        Some(vec![0, 2, 3])
+    );
+    assert_eq!(Traversal::shortest_path(&people[1], &people[1], None, &db)?, Some(vec![1]));
+    people[2].remove_relation(&people[3], &db)?;
+    assert_eq!(Traversal::shortest_path(&people[0], &people[3], None, &db)?, None);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_query_conditions() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Entity1 {
+        id: 3,
+        prop1: String::from("Hi"),
+    }
+    .save(&db)?;
+    relate_to_entity_2("id1", &[0, 1, 2], None, &db)?;
+    relate_to_entity_2("id2", &[1, 2, 3], Some("n"), &db)?;
+    let (id1, id2) = (String::from("id1"), String::from("id2"));
+    assert_eq!(
+        entity_1_keys(
+            QueryBuilder::new().with_relation_to::<Entity2>(&id1).with_named_relation_to::<Entity2>(&id2, "n"),
+            &db
+        )?,
+        vec![1, 2]
+    );
+    assert_eq!(
+        entity_1_keys(
+            QueryBuilder::new().with_relation_to::<Entity2>(&id1).or_named_relation_to::<Entity2>(&id2, "n"),
+            &db
+        )?,
+        vec![0, 1, 2, 3]
+    );
+    assert_eq!(
+        entity_1_keys(
+            QueryBuilder::new().with_relation_to::<Entity2>(&id1).without_named_relation_to::<Entity2>(&id2, "n"),
+            &db
+        )?,
+        vec![0]
+    );
+    assert_eq!(entity_1_keys(QueryBuilder::new().without_relation_to::<Entity2>(&id1), &db)?, vec![3]);
+    assert_eq!(
+        entity_1_keys(QueryBuilder::new().with_id(&2u32).with_relation_to::<Entity2>(&id1), &db)?,
+        vec![2]
+    );
+    assert!(QueryBuilder::new()
+        .with_parent(&String::from("missing"))
+        .get_single::<ChildEntity1>(&db)?
+        .is_none());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_query_pipeline() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let mut query = QueryBuilder::new();
+    query.with_parent(&String::from("id3"));
+    let children = query.select::<ChildEntity1>();
+    assert_eq!(children.count(&db)?, 3);
+    assert!(children.exists(&db)?);
+    let children = children.filter(|c| c.id.1 > 0).order_by_key_desc();
+    assert_eq!(children.map(|c| c.id.1, &db)?, vec![2, 1]);
+    assert_eq!(children.first(&db)?.unwrap().id.1, 2);
+    let children = children.offset(1).limit(5);
+    assert_eq!(children.keys(&db)?, vec![(String::from("id3"), 1)]);
+    assert_eq!(children.count(&db)?, 1);
+    assert!(!query.select::<ChildEntity1>().filter(|c| c.id.1 > 5).exists(&db)?);
+    assert_eq!(query.select::<ChildEntity1>().offset(2).count(&db)?, 1);
+
+    let mut query = QueryBuilder::new();
+    query.with_ids(&mut vec![&0u32, &1u32, &2u32]);
+    let names = query
+        .select::<Entity1>()
+        .order_by(|e| e.prop1.len())
+        .order_by_desc(|e| e.prop1.clone())
+        .map(|e| e.prop1, &db)?;
+    assert_eq!(names, vec!["Hello, World!", "Hello, Nancy!", "Hello, Steeve!"]);
+    assert_eq!(query.select::<Entity1>().limit(0).get(&db)?.len(), 0);
+    assert!(query.select::<Entity1>().limit(0).first(&db)?.is_none());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_relation_groups() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    relate_to_entity_2("id1", &[0, 1], None, &db)?;
+    relate_to_entity_2("id1", &[1, 2], Some("n"), &db)?;
+    relate_to_entity_2("id2", &[0, 2], None, &db)?;
+    let (id1, id2) = (String::from("id1"), String::from("id2"));
+    assert_eq!(
+        entity_1_keys(
+            QueryBuilder::new().any_of(
+                RelationGroup::new()
+                    .relation_to::<Entity2>(&id1)
+                    .named_relation_to::<Entity2>(&id1, "n")
+            ),
+            &db
+        )?,
+        vec![0, 1, 2]
+    );
+    assert_eq!(
+        entity_1_keys(
+            QueryBuilder::new().all_of(
+                RelationGroup::new()
+                    .named_relation_to::<Entity2>(&id1, "n")
+                    .relation_to::<Entity2>(&id2)
+            ),
+            &db
+        )?,
+        vec![2]
+    );
+    assert_eq!(
+        entity_1_keys(
+            QueryBuilder::new()
+                .any_of(RelationGroup::new().relation_to::<Entity2>(&id1).relation_to::<Entity2>(&id2))
+                .none_of(RelationGroup::new().named_relation_to::<Entity2>(&id1, "n")),
+            &db
+        )?,
+        vec![0]
+    );
+    assert!(entity_1_keys(QueryBuilder::new().any_of(RelationGroup::new()), &db)?.is_empty());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_query_paging() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    // Entities that cannot be read are reported even when they would be filtered out or skipped
+    let key = (String::from("id3"), 0u32);
+    db.open_tree("child_entity_1")?.insert(key.as_bytes(), vec![0xFF])?;
+    let mut query = QueryBuilder::new();
+    query.with_parent(&String::from("id3"));
+    assert!(query.select::<ChildEntity1>().offset(1).get(&db).is_err());
+    assert!(query.select::<ChildEntity1>().filter(|_| false).get(&db).is_err());
+
+    // Pages of queries without relation conditions are read from their cursor, skipping excluded entities
+    let e2_1 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    e2_1.create_relation(
+        &Entity3::get(&1, &db)?.unwrap(),
+        DeletionBehaviour::BreakLink,
+        DeletionBehaviour::BreakLink,
+        Some("rel1"),
+        &db,
+    )?;
+    let mut query = QueryBuilder::new();
+    query.without_named_relation_to::<Entity2>(&e2_1.id, "rel1");
+    let first = query.page_after::<Entity3>(None, 1, &db)?;
+    assert_eq!(first.items[0].id, 0);
+    assert!(first.previous.is_none());
+    let second = query.page_after::<Entity3>(first.next.as_ref(), 1, &db)?;
+    assert_eq!(second.items[0].id, 2);
+    assert!(second.next.is_none());
+    let back = query.page_before::<Entity3>(second.previous.as_ref(), 1, &db)?;
+    assert_eq!(back.items[0].id, 0);
+    assert!(back.previous.is_none());
+    assert!(back.next.is_some());
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_declared_relations() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    Student::register(&db)?;
+    Club::register(&db)?;
+    let student = Student { id: 1 };
+    let (chess, go) = (Club { id: 1 }, Club { id: 2 });
+    for entity in [&chess, &go] {
+        entity.save(&db)?;
+    }
+    student.save(&db)?;
+    student.add_member_club(&chess, &db)?;
+    student.add_member_club(&go, &db)?;
+    student.add_president_club(&go, &db)?;
+    assert_eq!(student.member_clubs(&db)?.len(), 2);
+    assert_eq!(go.get_related_keys_with_name::<Student>("president", &db)?, vec![1]);
+    student.remove_member_club(&chess, &db)?;
+    assert_eq!(
+        student.member_clubs(&db)?.iter().map(|c| c.id).collect::<Vec<u32>>(),
+        vec![2]
+    );
+    let entity_1 = Entity1::get(&0, &db)?.unwrap();
+    assert!(matches!(
+        student.add_president_club(&entity_1, &db).map_err(|e| e.kind()),
+        Err(ErrorKind::UndeclaredRelation)
+    ));
+    // Relations must be created with their declared deletion behaviours, from either end
+    assert!(matches!(
+        student
+            .create_relation(&chess, DeletionBehaviour::Cascade, DeletionBehaviour::BreakLink, Some("member"), &db)
+            .map_err(|e| e.kind()),
+        Err(ErrorKind::UndeclaredRelation)
+    ));
+    chess.create_relation(&student, DeletionBehaviour::BreakLink, DeletionBehaviour::Cascade, Some("president"), &db)?;
+    assert!(matches!(
+        chess
+            .create_relation(&student, DeletionBehaviour::Cascade, DeletionBehaviour::BreakLink, Some("president"), &db)
+            .map_err(|e| e.kind()),
+        Err(ErrorKind::UndeclaredRelation)
+    ));
+    assert!(matches!(
+        chess
+            .create_relation(&student, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, Some("coach"), &db)
+            .map_err(|e| e.kind()),
+        Err(ErrorKind::UndeclaredRelation)
+    ));
+    chess.create_relation(&entity_1, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &db)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_typed_family_declarations() {
+    assert_eq!(Entity3::get_sibling_stores(), vec![("entity_1", DeletionBehaviour::Error)]);
+    assert_eq!(Entity3::get_child_stores(), vec![("child_entity_2", DeletionBehaviour::Error)]);
+    assert_eq!(Entity1::get_sibling_stores(), vec![("entity_3", DeletionBehaviour::Cascade)]);
+}
+
+#[test]
+fn test_register_all() -> Result<()> {
+    let name = get_random_name();
+    let mut dir = std::env::temp_dir();
+    dir.push(&name);
+    let db = crate::open(dir.to_str().unwrap())?;
+    crate::set_strict(&db, true)?;
+    let entity = Entity1 {
+        id: 0,
+        prop1: String::from("Hello"),
+    };
+    assert!(matches!(entity.save(&db).map_err(|e| e.kind()), Err(ErrorKind::UnregisteredEntity)));
+    assert!(Entity1::get(&0, &db)?.is_none());
+    crate::register_all(&db)?;
+    entity.save(&db)?;
+    IndexedEntity {
+        id: 0,
+        email: String::from("ada@example.com"),
+        city: String::from("Paris"),
+    }
+    .save(&db)?;
+    assert!(FamilyDescriptor::get(&String::from("versioned_entity"), &db)?.is_some());
+    Entity1::remove(&0, &db)?;
+    crate::set_strict(&db, false)?;
+    assert!(!crate::is_strict(&db));
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_modes_dropped_with_db() -> Result<()> {
+    let name = get_random_name();
+    let mut dir = std::env::temp_dir();
+    dir.push(&name);
+    let config = sled::Config::new().path(&dir);
+    let db = open_unlocked(&config)?;
+    crate::set_strict(&db, true)?;
+    crate::set_schema_validation(&db, true)?;
+    assert!(crate::is_strict(&db.clone()));
+    assert!(crate::validates_schema(&db));
+    // The modes do not keep the database open
+    let state = std::sync::Arc::downgrade(&crate::state::of(&db)?);
+    drop(db);
+    assert!(state.upgrade().is_none());
+    let db = open_unlocked(&config)?;
+    assert!(!crate::is_strict(&db));
+    assert!(!crate::validates_schema(&db));
+    drop(db);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_validate_schema() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    let report = crate::validate_schema(&db)?;
+    assert!(report.is_consistent(), "{:?}", report.issues);
+    use DeletionBehaviour::{Cascade, Error};
+    family("a", &[("b", Cascade)], &[("missing", Cascade)]).save(&db)?;
+    family("b", &[("a", Cascade)], &[("c", Cascade)]).save(&db)?;
+    family("c", &[("d", Cascade)], &[]).save(&db)?;
+    family("d", &[], &[("b", Cascade), ("b", Error)]).save(&db)?;
+    family("e", &[("f", Error)], &[]).save(&db)?;
+    family("f", &[("e", Error)], &[]).save(&db)?;
+    let issues = crate::validate_schema(&db)?.issues;
+    assert_eq!(
+        issues,
+        vec![
+            SchemaIssue::MissingStore {
+                store: String::from("a"),
+                related_store: String::from("missing")
+            },
+            SchemaIssue::NonReciprocalSibling {
+                store: String::from("c"),
+                sibling_store: String::from("d")
+            },
+            SchemaIssue::ConflictingBehaviours {
+                store: String::from("d"),
+                related_store: String::from("b"),
+                behaviours: (Cascade, Error)
+            },
+            SchemaIssue::ConflictingBehaviours {
+                store: String::from("e"),
+                related_store: String::from("f"),
+                behaviours: (Error, Error)
+            },
+            SchemaIssue::CascadeCycle {
+                stores: vec![String::from("b"), String::from("c"), String::from("d")]
+            },
+        ]
+    );
+    // Strict mode alone does not validate the schema
+    crate::set_strict(&db, true)?;
+    crate::register_all(&db)?;
+    crate::set_strict(&db, false)?;
+    crate::set_schema_validation(&db, true)?;
+    assert!(crate::validates_schema(&db));
+    assert!(matches!(crate::register_all(&db).map_err(|e| e.kind()), Err(ErrorKind::IntegrityError)));
+    // Registering a single entity only fails on the inconsistencies it brings
+    Entity2::register(&db)?;
+    db.open_tree(FamilyDescriptor::store_name())?
+        .remove(AsBytes::as_bytes(&String::from("entity_1")))?;
+    family("entity_3", &[], &[]).save(&db)?;
+    assert!(matches!(Entity1::register(&db).map_err(|e| e.kind()), Err(ErrorKind::IntegrityError)));
+    assert!(FamilyDescriptor::get(&String::from("entity_1"), &db)?.is_none());
+    Entity3::register(&db)?;
+    Entity1::register(&db)?;
+    crate::set_schema_validation(&db, false)?;
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_store_handle() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let entities = db.store::<Entity1>()?;
+    assert!(entities.is_registered()?);
+    assert_eq!(entities.sibling_stores()?, vec![(String::from("entity_3"), DeletionBehaviour::Cascade)]);
+    let count = entities.get_count();
+    entities.save(&Entity1 {
+        id: 10,
+        prop1: String::from("Hey"),
+    })?;
+    entities.update(&10, |e| e.prop1.push('!'))?;
+    assert_eq!(entities.get(&10)?.unwrap().prop1, "Hey!");
+    assert_eq!(entities.get_count(), count + 1);
+    assert_eq!(entities.get_keys()?, Entity1::get_keys(&db)?);
+    assert_eq!(entities.iter_range(0u32, 2u32).count(), 2);
+    let e2 = Entity2::get(&String::from("id1"), &db)?.unwrap();
+    let e1 = entities.get(&10)?.unwrap();
+    entities.create_relation(&e1, &e2, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, Some("n"))?;
+    assert_eq!(entities.get_related_keys::<Entity2>(&e1)?, vec![String::from("id1")]);
+    assert_eq!(entities.get_related_with_name::<Entity2>(&e1, "n")?.len(), 1);
+    assert_eq!(entities.get_related_to(&e2)?.first().map(|e| e.id), Some(10));
+    assert!(entities.is_related_to_with_name(&e1, &e2, "n")?);
+    assert_eq!(entities.get_single_related::<Entity2>(&e1)?.map(|e| e.id), Some(String::from("id1")));
+    entities.remove_relation_with_name(&e1, &e2, "n")?;
+    assert!(!entities.is_related_to(&e1, &e2)?);
+    assert!(e2.get_related::<Entity1>(&db)?.is_empty());
+    entities.create_relation_with_data(&e1, &e2, DeletionBehaviour::BreakLink, DeletionBehaviour::BreakLink, None, &7u32)?;
+    assert_eq!(e2.get_related_with_data::<Entity1, u32>(&db)?.first().map(|(e, d)| (e.id, *d)), Some((10, 7)));
+    let handle = entities.clone();
+    std::thread::spawn(move || handle.remove(&10)).join().unwrap()?;
+    assert!(!entities.exists(&10)?);
+    assert!(e2.get_related::<Entity1>(&db)?.is_empty());
+    let mut e1 = Entity1 {
+        id: 0,
+        prop1: String::from("Next"),
+    };
+    assert_eq!(entities.save_next(&mut e1)?, Entity1::get_next_key(&db)? - 1);
+    assert_eq!(entities.get_from_end(0, 1, None::<u32>)?[0].prop1, "Next");
+    let mut e3 = Entity3 { id: 0, some_bool: true };
+    entities.save_sibling(&e1, &mut e3)?;
+    assert!(entities.get_sibling::<Entity3>(&e1)?.unwrap().some_bool);
+    let parents = db.store::<Entity2>()?;
+    let children: Vec<ChildEntity1> = parents.get_children(&e2)?;
+    let mut child = ChildEntity1 { id: (String::new(), 0) };
+    parents.save_next_child(&e2, &mut child)?;
+    assert_eq!(parents.get_children::<ChildEntity1, u32>(&e2)?.len(), children.len() + 1);
+    // The family of a store registered after its handle was opened is read again
+    let students = db.store::<Student>()?;
+    assert!(!students.is_registered()?);
+    Student::register(&db)?;
+    assert!(students.is_registered()?);
+    tear_down(&name)?;
+    Ok(())
+}
+
+#[test]
+fn test_store_removals() -> Result<()> {
+    let name = get_random_name();
+    let db = set_up(&name)?;
+    set_up_content(&db)?;
+    let parents = db.store::<Entity2>()?;
+    let e2 = parents.get(&String::from("id3"))?.unwrap();
+    assert_eq!(parents.get_children::<ChildEntity1, u32>(&e2)?.len(), 3);
+    parents.remove(&String::from("id3"))?;
+    assert!(!parents.exists(&String::from("id3"))?);
+    assert!(parents.get_children::<ChildEntity1, u32>(&e2)?.is_empty());
+    assert!(ChildEntity1::get_with_prefix(&String::from("id3"), &db)?.is_empty());
+
+    let children = db.store::<ChildEntity1>()?;
+    assert!(!children.get_with_prefix(&String::from("id2"))?.is_empty());
+    children.remove_prefixed(String::from("id2"))?;
+    assert!(children.get_with_prefix(&String::from("id2"))?.is_empty());
+
+    let entities = db.store::<Entity1>()?;
+    assert!(Entity3::exists(&1, &db)?);
+    let removed = entities.filter_remove(|e| e.id == 1)?;
+    assert_eq!(removed.iter().map(|e| e.id).collect::<Vec<u32>>(), vec![1]);
+    assert!(!entities.exists(&1)?);
+    assert!(!Entity3::exists(&1, &db)?);
+    assert!(crate::fsck(&db)?.is_clean());
+    tear_down(&name)?;
+    Ok(())
+}
@@ -16,7 +16,7 @@ pub struct Entity1 {
 
 #[derive(Serialize, Deserialize, Clone,Entity)]
 #[entity(name = "entity_2",version = 1,crate = "crate")]
-#[children(("child_entity_1",Cascade))]
+#[children(ChildEntity1 = Cascade)]
 pub struct Entity2 {
     pub id: String,
     pub prop2: u32,
@@ -24,8 +24,8 @@ pub struct Entity2 {
 
 #[derive(Serialize, Deserialize,Entity)]
 #[entity(name = "entity_3",version = 1,crate = "crate")]
-#[siblings(("entity_1",Error))]
-#[children(("child_entity_2",Error))]
+#[siblings(Entity1 = Error)]
+#[children(ChildEntity2 = Error)]
 pub struct Entity3 {
     pub id: u32,
     pub some_bool : bool,
@@ -33,9 +33,9 @@ pub struct Entity3 {
 
 #[derive(Serialize, Deserialize, Clone,Entity)]
 #[entity(name = "child_entity_1",version = 1,crate = "crate")]
-#[children(("grand_child_entity",Cascade))]
+#[children(GrandChildEntity = Cascade)]
 pub struct ChildEntity1 {
-    id: (String, u32),
+    pub id: (String, u32),
 }
 
 #[derive(Serialize, Deserialize,Entity)]
@@ -50,6 +50,126 @@ pub struct GrandChildEntity {
     id: ((String, u32), u32),
 }
 
+#[derive(Serialize, Deserialize,Entity)]
+#[entity(name = "versioned_entity",crate = "crate")]
+pub struct VersionedEntityV0 {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize,Entity)]
+#[entity(name = "versioned_entity",version = 1,crate = "crate")]
+#[migrations(VersionedEntityV0)]
+pub struct VersionedEntityV1 {
+    pub id: u32,
+    pub first_name: String,
+    pub last_name: String,
+}
+
+impl From<VersionedEntityV0> for VersionedEntityV1 {
+    fn from(source: VersionedEntityV0) -> Self {
+        let mut parts = source.name.splitn(2, ' ');
+        VersionedEntityV1 {
+            id: source.id,
+            first_name: parts.next().unwrap_or_default().to_string(),
+            last_name: parts.next().unwrap_or_default().to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize,Entity)]
+#[entity(name = "versioned_entity",version = 2,crate = "crate")]
+#[migrations(VersionedEntityV0, VersionedEntityV1)]
+pub struct VersionedEntity {
+    pub id: u32,
+    pub first_name: String,
+    pub last_name: String,
+    pub age: Option<u32>,
+}
+
+impl From<VersionedEntityV1> for VersionedEntity {
+    fn from(source: VersionedEntityV1) -> Self {
+        VersionedEntity {
+            id: source.id,
+            first_name: source.first_name,
+            last_name: source.last_name,
+            age: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(crate = "crate")]
+pub struct DefaultNamedEntity {
+    pub id: u32,
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(name = "indexed_entity", crate = "crate")]
+pub struct IndexedEntity {
+    pub id: u32,
+    #[index(unique)]
+    pub email: String,
+    #[index]
+    pub city: String,
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(name = "optional_indexed_entity", crate = "crate")]
+pub struct OptionalIndexedEntity {
+    pub id: u32,
+    #[index(unique)]
+    pub nickname: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(name = "signed_entity", crate = "crate")]
+pub struct SignedEntity {
+    pub id: i64,
+}
+
+#[derive(Serialize, Deserialize, Entity, Debug, PartialEq)]
+#[entity(name = "json_entity", codec = "json", crate = "crate")]
+pub struct JsonEntity {
+    pub id: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(name = "student", crate = "crate")]
+#[relations((Club, "member", BreakLink, BreakLink), ("club", "president", Cascade, BreakLink))]
+pub struct Student {
+    pub id: u32,
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(name = "club", crate = "crate")]
+pub struct Club {
+    pub id: u32,
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(name = "author", crate = "crate")]
+#[children(Book = Cascade)]
+pub struct Author {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Entity)]
+#[entity(name = "book", crate = "crate")]
+pub struct Book {
+    pub id: (String, u32),
+}
+
+#[cfg(feature = "zstd")]
+#[derive(Serialize, Deserialize, Entity, Debug, PartialEq)]
+#[entity(name = "compressed_entity", compress = "zstd", compress_threshold = 64, crate = "crate")]
+pub struct CompressedEntity {
+    pub id: u32,
+    pub body: String,
+}
+
 pub fn set_up(name: &str) -> Result<Db> {
     let mut dir = std::env::temp_dir();
     dir.push(name);
@@ -61,6 +181,8 @@ pub fn set_up(name: &str) -> Result<Db> {
     ChildEntity1::register(&db)?;
     ChildEntity2::register(&db)?;
     GrandChildEntity::register(&db)?;
+    IndexedEntity::register(&db)?;
+    JsonEntity::register(&db)?;
     Ok(db)
 }
 
@@ -0,0 +1,392 @@
+//! # Transaction Module
+//! This module provides multi-store transactions, so that several entities, their relations,
+//! siblings and children can be saved or removed atomically.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use sled::transaction::ConflictableTransactionError;
+use sled::{Db, IVec, Transactional, Tree};
+
+use crate::cipher::Cipher;
+use crate::error::Result;
+use crate::index;
+use crate::key::AsBytes;
+use crate::layout;
+use crate::registry;
+use crate::state;
+use crate::relation::Relation;
+use crate::storage::Storage;
+use crate::{DeletionBehaviour, Entity, Error, ErrorKind};
+
+type TreeChanges = HashMap<String, BTreeMap<Vec<u8>, Option<IVec>>>;
+type TreeScans = HashMap<String, BTreeMap<Vec<u8>, Vec<(IVec, IVec)>>>;
+
+/// Number of times a transaction is run before giving up when it keeps conflicting with concurrent changes.
+const MAX_ATTEMPTS: usize = 16;
+
+/// A set of changes on several entity stores that are either all committed or all rolled back.
+///
+/// A `Transaction` is obtained through the [`transaction`](fn.transaction.html) function, and provides
+/// the same methods as [`Entity`](entity/trait.Entity.html) to read, save and remove entities and relations.
+///
+/// Changes are kept in memory until the end of the transaction, and reads see the changes made earlier
+/// in the same transaction. They are then written to every store at once using a `sled` transaction.
+pub struct Transaction<'a> {
+    db: &'a Db,
+    cipher: Cipher,
+    reads: RefCell<TreeChanges>,
+    scans: RefCell<TreeScans>,
+    writes: RefCell<TreeChanges>,
+    trees: RefCell<HashMap<String, Tree>>,
+}
+
+/// Runs `f` in a transaction, so that every entity, sibling, child and relation saved or removed
+/// through the provided [`Transaction`](struct.Transaction.html) is committed at once.
+///
+/// If `f` returns an error, nothing is written to the database and the error is returned.
+/// If an entity read during the transaction has been modified concurrently by the time it is committed,
+/// or if entries have been added or removed under a range scanned during the transaction (the children
+/// or relations of an entity being removed, for instance), the closure is run again, so it should not
+/// have side effects outside of the transaction. After 16 attempts, an
+/// [`ErrorKind::TransactionConflict`](enum.ErrorKind.html) error is returned.
+///
+/// Writes made outside of a transaction wait for the transactions being committed to the same database,
+/// and the other way around, so that an entry cannot be added under a scanned range while it is checked.
+///
+/// ⚠ Only the changes made through the provided `Transaction` are part of the transaction. The methods of
+/// [`Entity`](entity/trait.Entity.html) and [`Store`](struct.Store.html) called with the database inside the
+/// closure write to it right away : their changes are kept even if the closure returns an error or the
+/// transaction conflicts, and are made again each time the closure is run.
+///
+/// ### Example
+/// ```rust
+/// # use reindeer::{Entity, Serialize, Deserialize, DeletionBehaviour};
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "user")]
+/// # #[siblings(UserData = Cascade)]
+/// # #[children(Document = Cascade)]
+/// # struct User { id : u32 }
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "user_data")]
+/// # #[siblings(User = Error)]
+/// # #[derive(Clone)]
+/// # struct UserData { id : u32 }
+/// # #[derive(Serialize, Deserialize, Entity, Clone)]
+/// # #[entity(name = "document")]
+/// # struct Document { id : (u32, u32) }
+/// # #[derive(Serialize, Deserialize, Entity)]
+/// # #[entity(name = "club")]
+/// # struct Club { id : u32 }
+/// # fn main() -> reindeer::Result<()> {
+/// # let db = sled::Config::new().temporary(true).open()?;
+/// # User::register(&db)?;
+/// # UserData::register(&db)?;
+/// # Document::register(&db)?;
+/// # Club::register(&db)?;
+/// # let user = User { id : 1 };
+/// # let user_data = UserData { id : 0 };
+/// # let document = Document { id : (0, 1) };
+/// # let club = Club { id : 1 };
+/// # club.save(&db)?;
+/// reindeer::transaction(&db, |tx| {
+///     tx.save(&user)?;
+///     tx.save_sibling(&user, &mut user_data.clone())?;
+///     tx.save_child(&user, &mut document.clone())?;
+///     tx.create_relation(&user, &club, DeletionBehaviour::BreakLink, DeletionBehaviour::Cascade, Some("member"))?;
+///     Ok(())
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn transaction<R, F>(db: &Db, f: F) -> Result<R>
+where
+    F: Fn(&Transaction) -> Result<R>,
+{
+    transaction_with_trees(db, &[], f)
+}
+
+/// Runs `f` in a transaction like [`transaction`](fn.transaction.html), reading and writing the trees
+/// already opened in `trees` instead of opening them again.
+pub(crate) fn transaction_with_trees<R, F>(db: &Db, trees: &[(String, Tree)], f: F) -> Result<R>
+where
+    F: Fn(&Transaction) -> Result<R>,
+{
+    layout::ensure_checked(db)?;
+    for _ in 0..MAX_ATTEMPTS {
+        let transaction = Transaction {
+            db,
+            cipher: Cipher::of(db)?,
+            reads: RefCell::new(HashMap::new()),
+            scans: RefCell::new(HashMap::new()),
+            writes: RefCell::new(HashMap::new()),
+            trees: RefCell::new(trees.iter().cloned().collect()),
+        };
+        let result = f(&transaction)?;
+        if transaction.commit()? {
+            return Ok(result);
+        }
+    }
+    Err(Error::new(
+        ErrorKind::TransactionConflict,
+        format!(
+            "Transaction aborted after {} attempts conflicting with concurrent changes",
+            MAX_ATTEMPTS
+        ),
+    ))
+}
+
+impl<'a> Transaction<'a> {
+    /// Retrieves an entity instance given its key.
+    pub fn get<E: Entity>(&self, key: &E::Key) -> Result<Option<E>> {
+        layout::ensure_current(E::store_name(), self.db)?;
+        let key = key.as_bytes();
+        Storage::get(self, E::store_name(), &key)?
+            .map(|value| E::try_from_ivec(self.cipher.open(E::store_name(), &key, value)?))
+            .transpose()
+    }
+
+    /// Checks if an entity exists in a given store, without fetching it.
+    pub fn exists<E: Entity>(&self, key: &E::Key) -> Result<bool> {
+        self.contains_key(E::store_name(), &key.as_bytes())
+    }
+
+    /// Saves an entity, using its key provided by the`get_key` method.
+    pub fn save<E: Entity>(&self, entity: &E) -> Result<()> {
+        registry::check_registered::<E>(self.db, self)?;
+        layout::ensure_current(E::store_name(), self.db)?;
+        index::save_entries(entity, self)?;
+        let key = entity.get_key().as_bytes();
+        let value = self.cipher.seal(E::store_name(), &key, entity.try_into_ivec()?)?;
+        self.insert(E::store_name(), &key, value)
+    }
+
+    /// Updates an entity entry using the provided function.
+    pub fn update<E: Entity, F: Fn(&mut E)>(&self, key: &E::Key, f: F) -> Result<()> {
+        if let Some(mut entity) = self.get::<E>(key)? {
+            f(&mut entity);
+            self.save(&entity)?;
+        }
+        Ok(())
+    }
+
+    /// Saves `sibling` in its own store after having changed its key to match `entity`.
+    pub fn save_sibling<E1: Entity, E2: Entity<Key = E1::Key>>(
+        &self,
+        entity: &E1,
+        sibling: &mut E2,
+    ) -> Result<()> {
+        sibling.set_key(entity.get_key());
+        self.save(sibling)
+    }
+
+    /// Saves `child` in its own store after having changed its key to make it effectively a child of `parent`.
+    pub fn save_child<E1: Entity, E2: Entity<Key = (E1::Key, T)>, T: Clone>(
+        &self,
+        parent: &E1,
+        child: &mut E2,
+    ) -> Result<E2::Key> {
+        let key = (parent.get_key().clone(), child.get_key().1.clone());
+        child.set_key(&key);
+        self.save(child)?;
+        Ok(key)
+    }
+
+    /// Saves `entity` under its current key and moves the index entries, links, children and siblings of
+    /// the entity stored at `old_key` to it, before removing the latter.
+    pub(crate) fn move_entity<E: Entity>(&self, entity: &E, old_key: &E::Key) -> Result<()> {
+        let (old_key, new_key) = (old_key.as_bytes(), entity.get_key().as_bytes());
+        index::remove_entries(E::store_name(), &old_key, self)?;
+        self.save(entity)?;
+        Relation::change_entity_id(E::store_name(), &old_key, &new_key, self)?;
+        self.remove_from_u8_array(E::store_name(), &old_key)
+    }
+
+    /// Removes an entity given its key, along with the related entities that must be removed with it.
+    ///
+    /// ⚠ [`pre_remove_hook`](entity/trait.Entity.html#method.pre_remove_hook) is not called inside a transaction.
+    pub fn remove<E: Entity>(&self, key: &E::Key) -> Result<()> {
+        self.remove_from_u8_array(E::store_name(), &key.as_bytes())
+    }
+
+    pub(crate) fn remove_from_u8_array(&self, tree_name: &str, key: &[u8]) -> Result<()> {
+        Relation::remove_related(tree_name, key, self)?;
+        Storage::remove(self, tree_name, key)
+    }
+
+    /// Creates a free relation between two entities.
+    /// See [`Entity::create_relation`](entity/trait.Entity.html#method.create_relation).
+    pub fn create_relation<E1: Entity, E2: Entity>(
+        &self,
+        e1: &E1,
+        e2: &E2,
+        e1_to_e2: DeletionBehaviour,
+        e2_to_e1: DeletionBehaviour,
+        name: Option<&str>,
+    ) -> Result<()> {
+        Relation::create(e1, e2, e1_to_e2, e2_to_e1, name, self)
+    }
+
+    /// Breaks an existing link between two entities, in both ways.
+    pub fn remove_relation<E1: Entity, E2: Entity>(&self, e1: &E1, e2: &E2) -> Result<()> {
+        Relation::remove(e1, e2, self)
+    }
+
+    /// Breaks an existing link between two entities with a specific name, in both ways.
+    pub fn remove_relation_with_name<E1: Entity, E2: Entity>(
+        &self,
+        e1: &E1,
+        e2: &E2,
+        name: &str,
+    ) -> Result<()> {
+        Relation::remove_with_name(e1, e2, name, self)
+    }
+
+    /// Returns the tree `name`, opening it the first time it is used in this transaction.
+    fn tree(&self, name: &str) -> Result<Tree> {
+        if let Some(tree) = self.trees.borrow().get(name) {
+            return Ok(tree.clone());
+        }
+        let tree = self.db.open_tree(name)?;
+        self.trees.borrow_mut().insert(String::from(name), tree.clone());
+        Ok(tree)
+    }
+
+    /// Writes every change to the database in a single `sled` transaction, provided that
+    /// none of the values read and none of the ranges scanned during the transaction have changed in the meantime,
+    /// and that the encryption key has not been rotated.
+    /// Returns `false` if a conflicting change was found and nothing was written.
+    ///
+    /// `sled` transactions cannot scan ranges, so scans are checked right before the `sled` transaction,
+    /// while nothing else can write to the database.
+    fn commit(self) -> Result<bool> {
+        let state = state::of(self.db)?;
+        let _commit = state.commit();
+        // Values sealed with a key rotated in the meantime could no longer be read
+        if !self.cipher.is_current(&state) {
+            return Ok(false);
+        }
+        for (name, scans) in self.scans.take() {
+            let tree = self.tree(&name)?;
+            for (prefix, entries) in scans {
+                let current = tree
+                    .scan_prefix(prefix)
+                    .collect::<std::result::Result<Vec<(IVec, IVec)>, sled::Error>>()?;
+                if current != entries {
+                    return Ok(false);
+                }
+            }
+        }
+        let reads = self.reads.take();
+        let writes = self.writes.take();
+        let mut names = reads.keys().chain(writes.keys()).collect::<Vec<&String>>();
+        names.sort();
+        names.dedup();
+        if names.is_empty() {
+            return Ok(true);
+        }
+        let trees = names
+            .iter()
+            .map(|name| self.tree(name))
+            .collect::<Result<Vec<Tree>>>()?;
+        let result = trees.as_slice().transaction(|views| {
+            for (name, view) in names.iter().zip(views) {
+                for (key, value) in reads.get(*name).into_iter().flatten() {
+                    if view.get(key)? != *value {
+                        return Err(ConflictableTransactionError::Abort(()));
+                    }
+                }
+            }
+            for (name, view) in names.iter().zip(views) {
+                for (key, value) in writes.get(*name).into_iter().flatten() {
+                    match value {
+                        Some(value) => view.insert(key.as_slice(), value)?,
+                        None => view.remove(key.as_slice())?,
+                    };
+                }
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => Ok(true),
+            Err(sled::transaction::TransactionError::Abort(())) => Ok(false),
+            Err(sled::transaction::TransactionError::Storage(e)) => Err(e.into()),
+        }
+    }
+}
+
+impl<'a> Storage for Transaction<'a> {
+    fn get(&self, tree: &str, key: &[u8]) -> Result<Option<IVec>> {
+        if let Some(value) = self.writes.borrow().get(tree).and_then(|w| w.get(key)) {
+            return Ok(value.clone());
+        }
+        if let Some(value) = self.reads.borrow().get(tree).and_then(|r| r.get(key)) {
+            return Ok(value.clone());
+        }
+        let value = self.tree(tree)?.get(key)?;
+        self.reads
+            .borrow_mut()
+            .entry(String::from(tree))
+            .or_default()
+            .insert(key.to_vec(), value.clone());
+        Ok(value)
+    }
+
+    fn cipher(&self) -> Result<Cipher> {
+        Ok(self.cipher.clone())
+    }
+
+    fn insert(&self, tree: &str, key: &[u8], value: IVec) -> Result<()> {
+        self.writes
+            .borrow_mut()
+            .entry(String::from(tree))
+            .or_default()
+            .insert(key.to_vec(), Some(value));
+        Ok(())
+    }
+
+    fn insert_sealed(&self, tree: &str, key: &[u8], value: IVec) -> Result<()> {
+        self.insert(tree, key, self.cipher.seal(tree, key, value)?)
+    }
+
+    fn remove(&self, tree: &str, key: &[u8]) -> Result<()> {
+        self.writes
+            .borrow_mut()
+            .entry(String::from(tree))
+            .or_default()
+            .insert(key.to_vec(), None);
+        Ok(())
+    }
+
+    fn scan_prefix(&self, tree: &str, prefix: &[u8]) -> Result<Vec<(IVec, IVec)>> {
+        let scanned = self
+            .tree(tree)?
+            .scan_prefix(prefix)
+            .collect::<std::result::Result<Vec<(IVec, IVec)>, sled::Error>>()?;
+        let mut entries = scanned
+            .iter()
+            .map(|(key, value)| (key.to_vec(), value.clone()))
+            .collect::<BTreeMap<Vec<u8>, IVec>>();
+        self.scans
+            .borrow_mut()
+            .entry(String::from(tree))
+            .or_default()
+            .entry(prefix.to_vec())
+            .or_insert(scanned);
+        if let Some(writes) = self.writes.borrow().get(tree) {
+            for (key, value) in writes.range(prefix.to_vec()..) {
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                match value {
+                    Some(value) => entries.insert(key.clone(), value.clone()),
+                    None => entries.remove(key),
+                };
+            }
+        }
+        Ok(entries
+            .into_iter()
+            .map(|(key, value)| (IVec::from(key), value))
+            .collect())
+    }
+}
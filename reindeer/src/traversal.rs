@@ -0,0 +1,244 @@
+//! # Traversal Module
+//! This module provides graph traversals over free relations : paths of several hops through different stores,
+//! depth-limited breadth-first searches over a relation, and shortest paths between two entities.
+//!
+//! ### Example
+//! ```rust
+//! # use reindeer::{Entity, Serialize, Deserialize, Traversal};
+//! # #[derive(Serialize, Deserialize, Entity)]
+//! # #[entity(name = "school")]
+//! # struct School { id : u32 }
+//! # #[derive(Serialize, Deserialize, Entity)]
+//! # #[entity(name = "student")]
+//! # struct Student { id : u32 }
+//! # #[derive(Serialize, Deserialize, Entity)]
+//! # #[entity(name = "club")]
+//! # struct Club { id : u32 }
+//! # #[derive(Serialize, Deserialize, Entity)]
+//! # #[entity(name = "event")]
+//! # struct Event { id : u32 }
+//! # #[derive(Serialize, Deserialize, Entity)]
+//! # #[entity(name = "person")]
+//! # struct Person { id : u32 }
+//! # fn main() -> reindeer::Result<()> {
+//! # let db = sled::Config::new().temporary(true).open()?;
+//! # School::register(&db)?;
+//! # Student::register(&db)?;
+//! # Club::register(&db)?;
+//! # Event::register(&db)?;
+//! # Person::register(&db)?;
+//! # let school = School { id : 1 };
+//! # let person = Person { id : 1 };
+//! // Events organized by the clubs the members of a school belong to
+//! let events = Traversal::from(&school)
+//!     .out::<Student>("student")
+//!     .out::<Club>("member")
+//!     .out::<Event>(None)
+//!     .collect::<Event>(&db)?;
+//!
+//! // Friends, and friends of friends
+//! let friends = Traversal::from(&person).bfs::<Person>("friend", 2).collect::<Person>(&db)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use sled::Db;
+
+use crate::error::Result;
+use crate::key::{AsBytes, FromBytes};
+use crate::relation::Relation;
+use crate::{Entity, Error, ErrorKind};
+
+enum Step {
+    Out {
+        store: String,
+        name: Option<String>,
+    },
+    Bfs {
+        store: String,
+        name: Option<String>,
+        max_depth: usize,
+    },
+}
+
+/// A traversal of free relations, starting from an entity.
+///
+/// Steps are only recorded when calling [`out`](#method.out) and [`bfs`](#method.bfs), and the database is read
+/// when the reached entities are fetched with [`collect`](#method.collect) or [`keys`](#method.keys).
+/// A relation name of `None` follows every relation, whatever its name.
+///
+/// Each entity is only reached once per step, so cycles in the relations do not make a traversal loop.
+pub struct Traversal {
+    store: String,
+    keys: Vec<Vec<u8>>,
+    steps: Vec<Step>,
+}
+
+impl Traversal {
+    /// Starts a traversal from `entity`.
+    pub fn from<E: Entity>(entity: &E) -> Traversal {
+        Traversal::from_key::<E>(entity.get_key())
+    }
+
+    /// Starts a traversal from the entity of `E` with key `key`, without reading it.
+    pub fn from_key<E: Entity>(key: &E::Key) -> Traversal {
+        Traversal {
+            store: String::from(E::store_name()),
+            keys: vec![key.as_bytes()],
+            steps: Vec::new(),
+        }
+    }
+
+    /// Follows the relations named `name` to the entities of `E`.
+    pub fn out<'a, E: Entity>(mut self, name: impl Into<Option<&'a str>>) -> Traversal {
+        self.steps.push(Step::Out {
+            store: String::from(E::store_name()),
+            name: name.into().map(String::from),
+        });
+        self
+    }
+
+    /// Follows the relations named `name` to the entities of `E`, then from these entities to other entities of `E`,
+    /// up to `max_depth` hops. Every entity reached along the way is kept, in breadth-first order, except
+    /// the entities this step starts from.
+    pub fn bfs<'a, E: Entity>(mut self, name: impl Into<Option<&'a str>>, max_depth: usize) -> Traversal {
+        self.steps.push(Step::Bfs {
+            store: String::from(E::store_name()),
+            name: name.into().map(String::from),
+            max_depth,
+        });
+        self
+    }
+
+    /// Runs the traversal and fetches the entities it reaches, which must be entities of `E`.
+    pub fn collect<E: Entity>(&self, db: &Db) -> Result<Vec<E>> {
+        E::get_each_u8(&self.run::<E>(db)?, db)
+    }
+
+    /// Runs the traversal and returns the keys of the entities it reaches, which must be entities of `E`.
+    pub fn keys<E: Entity>(&self, db: &Db) -> Result<Vec<E::Key>> {
+        self.run::<E>(db)?
+            .iter()
+            .map(|key| E::Key::from_bytes(key))
+            .collect()
+    }
+
+    /// Finds one of the shortest paths from `from` to `to` through the relations named `name` between entities of `E`,
+    /// and returns the keys of the entities along the path, both ends included. Returns `None` if `to` cannot be reached.
+    ///
+    /// ### Example
+    /// ```rust
+    /// # use reindeer::{Entity, Serialize, Deserialize, Traversal};
+    /// # #[derive(Serialize, Deserialize, Entity)]
+    /// # #[entity(name = "person")]
+    /// # struct Person { id : u32 }
+    /// # fn main() -> reindeer::Result<()> {
+    /// # let db = sled::Config::new().temporary(true).open()?;
+    /// # Person::register(&db)?;
+    /// # let (alice, bob) = (Person { id : 1 }, Person { id : 2 });
+    /// let path = Traversal::shortest_path(&alice, &bob, Some("friend"), &db)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn shortest_path<E: Entity>(
+        from: &E,
+        to: &E,
+        name: Option<&str>,
+        db: &Db,
+    ) -> Result<Option<Vec<E::Key>>> {
+        let (from, to) = (from.get_key().as_bytes(), to.get_key().as_bytes());
+        // Each reached entity is mapped to the entity it has been reached from
+        let mut previous: HashMap<Vec<u8>, Option<Vec<u8>>> = HashMap::from([(from.clone(), None)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(key) = queue.pop_front() {
+            if key == to {
+                let mut path = vec![E::Key::from_bytes(&key)?];
+                let mut current = &key;
+                while let Some(Some(prev)) = previous.get(current) {
+                    path.push(E::Key::from_bytes(prev)?);
+                    current = prev;
+                }
+                path.reverse();
+                return Ok(Some(path));
+            }
+            for next in neighbours(E::store_name(), std::slice::from_ref(&key), E::store_name(), name, db)? {
+                if !previous.contains_key(&next) {
+                    previous.insert(next.clone(), Some(key.clone()));
+                    queue.push_back(next);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn run<E: Entity>(&self, db: &Db) -> Result<Vec<Vec<u8>>> {
+        let mut store = self.store.as_str();
+        let mut frontier = self.keys.clone();
+        for step in &self.steps {
+            match step {
+                Step::Out { store: to, name } => {
+                    frontier = neighbours(store, &frontier, to, name.as_deref(), db)?;
+                    store = to;
+                }
+                Step::Bfs {
+                    store: to,
+                    name,
+                    max_depth,
+                } => {
+                    let mut visited = HashSet::new();
+                    if store == to {
+                        visited.extend(frontier.iter().cloned());
+                    }
+                    let mut reached = Vec::new();
+                    for _ in 0..*max_depth {
+                        frontier = neighbours(store, &frontier, to, name.as_deref(), db)?
+                            .into_iter()
+                            .filter(|key| visited.insert(key.clone()))
+                            .collect();
+                        store = to;
+                        if frontier.is_empty() {
+                            break;
+                        }
+                        reached.extend(frontier.iter().cloned());
+                    }
+                    frontier = reached;
+                    store = to;
+                }
+            }
+        }
+        if store != E::store_name() {
+            return Err(Error::new(
+                ErrorKind::IntegrityError,
+                format!(
+                    "Traversal ends in store {}, not in store {}",
+                    store,
+                    E::store_name()
+                ),
+            ));
+        }
+        Ok(frontier)
+    }
+}
+
+/// Returns the keys of the entities of `to` related to any entity of `store` with a key in `keys`,
+/// through relations named `name`, each key only once.
+fn neighbours(
+    store: &str,
+    keys: &[Vec<u8>],
+    to: &str,
+    name: Option<&str>,
+    db: &Db,
+) -> Result<Vec<Vec<u8>>> {
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    for key in keys {
+        for rd in Relation::links_to_store_with_tree_names(store, key, to, db)? {
+            if (name.is_none() || rd.name.as_deref() == name) && seen.insert(rd.key.clone()) {
+                result.push(rd.key);
+            }
+        }
+    }
+    Ok(result)
+}